@@ -0,0 +1,5 @@
+module bfilters/fixtures/go_bloom
+
+go 1.21
+
+require github.com/bits-and-blooms/bloom/v3 v3.7.0
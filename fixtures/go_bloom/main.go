@@ -0,0 +1,48 @@
+// Prints the fixtures of the tests in src/go_bloom.rs with github.com/bits-and-blooms/bloom/v3:
+//
+//	cd fixtures/go_bloom && go mod tidy && go run .
+package main
+
+import (
+	"bytes"
+	"encoding/hex"
+	"fmt"
+	"strconv"
+
+	"github.com/bits-and-blooms/bloom/v3"
+)
+
+func main() {
+	// FIXTURE of test_go_bloom_fixture.
+	filter := bloom.NewWithEstimates(100, 0.01)
+
+	filter.AddString("John Green")
+	filter.AddString("John White")
+	filter.Add([]byte("Vinegar"))
+
+	var buf bytes.Buffer
+
+	if _, err := filter.WriteTo(&buf); err != nil {
+		panic(err)
+	}
+
+	fmt.Printf("m=%d k=%d\n", filter.Cap(), filter.K())
+	fmt.Printf("FIXTURE=%s\n", hex.EncodeToString(buf.Bytes()))
+
+	// The false positives of test_go_bloom_false_positives.
+	filter = bloom.NewWithEstimates(1000, 0.01)
+
+	for i := 0; i < 1000; i++ {
+		filter.AddString("item-" + strconv.Itoa(i))
+	}
+
+	falsePositives := 0
+
+	for i := 1000; i < 11000; i++ {
+		if filter.TestString("item-" + strconv.Itoa(i)) {
+			falsePositives++
+		}
+	}
+
+	fmt.Printf("m=%d k=%d false_positives=%d\n", filter.Cap(), filter.K(), falsePositives)
+}
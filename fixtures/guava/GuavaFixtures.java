@@ -0,0 +1,70 @@
+// Prints the FIXTURES of the tests in src/guava.rs with Guava's BloomFilter.writeTo:
+//
+//   java -cp guava-33.3.1-jre.jar fixtures/guava/GuavaFixtures.java
+//
+// MURMUR128_MITZ_32 is not public, so the filters are created through the package-private
+// BloomFilter.create(funnel, expectedInsertions, fpp, strategy) by reflection.
+
+import com.google.common.hash.BloomFilter;
+import com.google.common.hash.Funnel;
+import com.google.common.hash.PrimitiveSink;
+import java.io.ByteArrayOutputStream;
+import java.lang.reflect.Method;
+import java.nio.charset.StandardCharsets;
+
+public class GuavaFixtures {
+  /** The funnels of the GuavaFunnel implementations, picked by the type of the value. */
+  enum MixedFunnel implements Funnel<Object> {
+    INSTANCE;
+
+    @Override
+    public void funnel(Object value, PrimitiveSink into) {
+      if (value instanceof String) {
+        into.putString((String) value, StandardCharsets.UTF_8);
+      } else if (value instanceof byte[]) {
+        into.putBytes((byte[]) value);
+      } else if (value instanceof Integer) {
+        into.putInt((Integer) value);
+      } else if (value instanceof Long) {
+        into.putLong((Long) value);
+      } else {
+        throw new IllegalArgumentException(value.getClass().getName());
+      }
+    }
+  }
+
+  @SuppressWarnings("unchecked")
+  public static void main(String[] args) throws Exception {
+    Class<?> strategyClass = Class.forName("com.google.common.hash.BloomFilter$Strategy");
+    Class<?> strategies = Class.forName("com.google.common.hash.BloomFilterStrategies");
+    Method create =
+        BloomFilter.class.getDeclaredMethod(
+            "create", Funnel.class, long.class, double.class, strategyClass);
+
+    create.setAccessible(true);
+
+    for (String name : new String[] {"MURMUR128_MITZ_32", "MURMUR128_MITZ_64"}) {
+      Object strategy = Enum.valueOf((Class<Enum>) strategies.asSubclass(Enum.class), name);
+      BloomFilter<Object> filter =
+          (BloomFilter<Object>) create.invoke(null, MixedFunnel.INSTANCE, 100L, 0.01, strategy);
+
+      filter.put("John Green");
+      filter.put("John White");
+      filter.put("Vinegar".getBytes(StandardCharsets.UTF_8));
+      filter.put(42);
+      filter.put(7L);
+
+      ByteArrayOutputStream out = new ByteArrayOutputStream();
+
+      filter.writeTo(out);
+
+      StringBuilder hex = new StringBuilder();
+
+      for (byte b : out.toByteArray()) {
+        hex.append(String.format("%02x", b));
+      }
+
+      System.out.println(name + "=" + hex);
+    }
+  }
+}
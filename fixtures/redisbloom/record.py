@@ -0,0 +1,55 @@
+"""Records the BF.SCANDUMP chunks of the tests in src/redisbloom.rs from a RedisBloom server.
+
+    docker run -d -p 6379:6379 redis/redis-stack-server:7.4.0-v1
+    pip install redis==5.0.8
+    python3 fixtures/redisbloom/record.py
+
+Every case prints the options of its dump header, so the NO_ROUND, FORCE64 and NO_SCALING bits
+the server set could be told apart, and its chunks as (iterator, hex) pairs.
+"""
+
+import struct
+
+import redis
+
+CASES = [
+    # Three links grown by the default expansion of 2.
+    ("expanding", ["BF.RESERVE", "expanding", "0.01", "10"], 50),
+    ("expansion_4", ["BF.RESERVE", "expansion_4", "0.01", "10", "EXPANSION", "4"], 50),
+    # A single link with the NO_SCALING bit set.
+    ("nonscaling", ["BF.RESERVE", "nonscaling", "0.01", "100", "NONSCALING"], 50),
+]
+
+
+def main() -> None:
+    client = redis.Redis()
+
+    for name, reserve, items in CASES:
+        client.delete(name)
+        client.execute_command(*reserve)
+
+        for i in range(items):
+            client.execute_command("BF.ADD", name, f"item-{i}")
+
+        chunks = []
+        iterator = 0
+
+        while True:
+            iterator, data = client.execute_command("BF.SCANDUMP", name, iterator)
+
+            if iterator == 0:
+                break
+
+            chunks.append((iterator, data))
+
+        # The header starts with the size, the number of filters, the options and the growth.
+        _, number_of_filters, options, growth = struct.unpack_from("<QIII", chunks[0][1])
+
+        print(f"// {name}: {number_of_filters} links, options {options}, growth {growth}")
+
+        for iterator, data in chunks:
+            print(f'({iterator}, "{data.hex()}"),')
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,43 @@
+// Prints the XXPH3_64bits vectors of the test_xxph3_64 test in src/hash.rs
+// with the util/xxph3.h of a RocksDB checkout:
+//
+//   g++ -std=c++17 -I <rocksdb> fixtures/rocksdb/xxph3_vectors.cc -o xxph3_vectors && ./xxph3_vectors
+
+#include <cinttypes>
+#include <cstdio>
+#include <cstring>
+#include <vector>
+
+#include "util/xxph3.h"
+
+// The same deterministic input as the input(len) of the Rust test.
+static std::vector<uint8_t> input(size_t len) {
+  std::vector<uint8_t> bytes(len);
+
+  for (size_t i = 0; i < len; i++) {
+    uint64_t x = static_cast<uint64_t>(i) * 0x9e3779b97f4a7c15ULL;
+
+    bytes[i] = static_cast<uint8_t>((x << 17) | (x >> 47));
+  }
+
+  return bytes;
+}
+
+int main() {
+  const size_t lens[] = {0,   1,   3,   4,   8,   9,   16,   17,
+                         100, 128, 129, 200, 240, 241, 1024, 1100};
+
+  for (size_t len : lens) {
+    std::vector<uint8_t> bytes = input(len);
+
+    printf("(%zu, 0x%016" PRIx64 "),\n", len, XXPH3_64bits(bytes.data(), len));
+  }
+
+  const char* strings[] = {"hello", "Hello test world!"};
+
+  for (const char* s : strings) {
+    printf("%s: 0x%016" PRIx64 "\n", s, XXPH3_64bits(s, strlen(s)));
+  }
+
+  return 0;
+}
@@ -0,0 +1,235 @@
+//! Non-blocking saving and loading of the bloom filter with tokio.
+//!
+//! The bloom filter is written in the binary format, the payload is streamed in chunks,
+//! so the runtime is given back control between them and no serialized copy of the bits is built.
+//! The checksum and the signature are calculated chunk by chunk as well, yielding to the runtime in between.
+
+use hmac::Mac;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::task;
+
+use crate::format::{FileFormat, Header, HEADER_SIZE, SIGNATURE_SIZE};
+use crate::sign::{self, HmacSha256};
+use crate::stream::CHUNK_SIZE;
+use crate::{compress, BloomFilter, LoadBloomFilterError, LoadOptions, SaveBloomFilterError};
+
+/// Calculates the checksum of the header parameters and the payload, yielding to the runtime after every chunk.
+async fn calc_checksum(header: &Header, payload: &[u8]) -> u32 {
+    let mut checksum: u32 = crc32c::crc32c(&header.canonical_bytes());
+
+    for chunk in payload.chunks(CHUNK_SIZE) {
+        checksum = crc32c::crc32c_append(checksum, chunk);
+        task::yield_now().await;
+    }
+
+    checksum
+}
+
+/// Feeds the payload to the HMAC, yielding to the runtime after every chunk.
+async fn update_mac(mac: &mut HmacSha256, payload: &[u8]) {
+    for chunk in payload.chunks(CHUNK_SIZE) {
+        mac.update(chunk);
+        task::yield_now().await;
+    }
+}
+
+impl BloomFilter {
+    /// Writes the bloom filter in the binary format to the given async writer,
+    /// the same bytes as bloom_filter.to_bytes() returns.
+    ///
+    /// ```rust
+    /// use bfilters::{BloomFilter, LoadOptions};
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+    /// let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+    /// let mut bytes: Vec<u8> = Vec::new();
+    ///
+    /// bloom_filter.insert("Vinegar");
+    /// bloom_filter.save_async(&mut bytes).await.unwrap();
+    ///
+    /// let mut loaded_bloom_filter: BloomFilter =
+    ///     BloomFilter::load_async(&mut &bytes[..], LoadOptions::new()).await.unwrap();
+    ///
+    /// assert!(loaded_bloom_filter.is_probably_present("Vinegar"));
+    /// # });
+    /// ```
+    pub async fn save_async<W: AsyncWrite + Unpin>(
+        &self,
+        writer: &mut W,
+    ) -> Result<(), SaveBloomFilterError> {
+        let checksum: u32 = calc_checksum(&self.header(), self.payload()).await;
+        let mut mac: Option<HmacSha256> = self
+            .signing_key
+            .map(|signing_key| sign::header_mac(&signing_key, &self.header()));
+
+        writer
+            .write_all(&self.binary_header_with_checksum(checksum).to_bytes())
+            .await?;
+
+        for chunk in self.payload().chunks(CHUNK_SIZE) {
+            writer.write_all(chunk).await?;
+
+            if let Some(mac) = &mut mac {
+                update_mac(mac, chunk).await;
+            }
+        }
+
+        if let Some(mac) = mac {
+            writer.write_all(&mac.finalize().into_bytes()).await?;
+        }
+
+        writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// Reads the bloom filter from the given async reader with the given options.
+    /// The binary bloom filters are streamed straight into the bit array and verified chunk by chunk,
+    /// the JSON ones and the compressed payloads are read whole.
+    pub async fn load_async<R: AsyncRead + Unpin>(
+        reader: &mut R,
+        options: LoadOptions,
+    ) -> Result<Self, LoadBloomFilterError> {
+        let mut header_bytes: Vec<u8> = vec![0; HEADER_SIZE];
+        let mut header_len: usize = 0;
+
+        // The JSON bloom filters could be shorter than the binary header.
+        while header_len < HEADER_SIZE {
+            match reader.read(&mut header_bytes[header_len..]).await? {
+                0 => break,
+                read => header_len += read,
+            }
+        }
+
+        header_bytes.truncate(header_len);
+
+        if FileFormat::detect(&header_bytes) == FileFormat::Json {
+            reader.read_to_end(&mut header_bytes).await?;
+
+            return Self::from_bytes_with_options(&header_bytes, options);
+        }
+
+        let header: Header = Header::parse_header(&header_bytes)?;
+        let mut bloom_filter: Self = Self::from_header(&header, &[]);
+
+        let payload: &mut [u8] = &mut bloom_filter.bit_array.bit_array[..header.payload_len()];
+
+        if header.compression.is_some() {
+            let mut stored: Vec<u8> = Vec::new();
+
+            // The size comes from the file, so the buffer grows with the bytes actually read.
+            (&mut *reader)
+                .take(header.stored_payload_len() as u64)
+                .read_to_end(&mut stored)
+                .await?;
+
+            compress::read_compressed(&stored[..], &header, payload)?;
+        } else {
+            for chunk in payload.chunks_mut(CHUNK_SIZE) {
+                reader.read_exact(chunk).await?;
+            }
+        }
+
+        if header.is_signed() {
+            let mut signature: [u8; SIGNATURE_SIZE] = [0; SIGNATURE_SIZE];
+
+            reader.read_exact(&mut signature).await?;
+            bloom_filter.signature = Some(signature);
+        }
+
+        if options.verify_checksum {
+            header.check_checksum(calc_checksum(&header, bloom_filter.payload()).await)?;
+        }
+
+        if let Some(signing_key) = &options.signing_key {
+            let mut mac: HmacSha256 = sign::header_mac(signing_key, &header);
+
+            update_mac(&mut mac, bloom_filter.payload()).await;
+            sign::verify_mac(
+                mac,
+                bloom_filter
+                    .signature
+                    .as_ref()
+                    .map(|signature| &signature[..]),
+            )?;
+        }
+
+        // The checksum and the signature are verified above without blocking the runtime.
+        let verified_options: LoadOptions = LoadOptions {
+            verify_checksum: false,
+            signing_key: None,
+            ..options
+        };
+
+        Self {
+            signing_key: options.signing_key,
+            ..bloom_filter.verify(&verified_options)?
+        }
+        .with_loaded_key(options.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BloomFilter, FileFormat, HashKey, LoadBloomFilterError, LoadOptions};
+
+    #[tokio::test]
+    async fn test_save_load_async() {
+        let item: &str = "John Green";
+        let key: HashKey = *b"0123456789abcdef";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 200_000)
+            .unwrap()
+            .with_key(key)
+            .unwrap()
+            .with_signing_key([7; 32]);
+
+        bloom_filter.insert(item);
+
+        let mut bytes: Vec<u8> = Vec::new();
+
+        bloom_filter.save_async(&mut bytes).await.unwrap();
+
+        assert_eq!(bytes, bloom_filter.to_bytes());
+
+        let options: LoadOptions = LoadOptions::new().with_key(key).with_signing_key([7; 32]);
+        let mut loaded_bloom_filter: BloomFilter =
+            BloomFilter::load_async(&mut &bytes[..], options)
+                .await
+                .unwrap();
+
+        assert!(loaded_bloom_filter.is_probably_present(item));
+        assert!(matches!(
+            BloomFilter::load_async(&mut &bytes[..], options.with_signing_key([8; 32])).await,
+            Err(LoadBloomFilterError::InvalidSignature)
+        ));
+
+        bytes[100] ^= 1;
+
+        assert!(matches!(
+            BloomFilter::load_async(&mut &bytes[..], options).await,
+            Err(LoadBloomFilterError::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            BloomFilter::load_async(&mut &bytes[..1000], options).await,
+            Err(LoadBloomFilterError::Io(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_load_async_json() {
+        let item: &str = "John Green";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        bloom_filter.insert(item);
+
+        let json: Vec<u8> = bloom_filter.serialize(FileFormat::Json).unwrap();
+        let mut loaded_bloom_filter: BloomFilter =
+            BloomFilter::load_async(&mut &json[..], LoadOptions::new())
+                .await
+                .unwrap();
+
+        assert!(loaded_bloom_filter.is_probably_present(item));
+    }
+}
@@ -0,0 +1,423 @@
+//! A bloom filter saved periodically by a background thread.
+//!
+//! The thread holds the bloom filter lock only to serialize it, the file is written outside of the lock
+//! atomically, so the inserts are blocked only for the time of copying the bits.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{persist, BloomFilter, FileFormat, SaveBloomFilterError};
+
+/// The options of the background saving.
+#[derive(Clone, Copy, Debug)]
+pub struct AutosaveOptions {
+    interval: Option<Duration>,
+    insert_threshold: Option<u64>,
+    format: FileFormat,
+}
+
+impl Default for AutosaveOptions {
+    fn default() -> Self {
+        Self {
+            interval: Some(Duration::from_secs(60)),
+            insert_threshold: None,
+            format: FileFormat::Binary,
+        }
+    }
+}
+
+impl AutosaveOptions {
+    /// Creates the default options: saving every minute in the binary format.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how often the bloom filter with new items is saved, None disables the periodic saving.
+    pub fn with_interval(mut self, interval: Option<Duration>) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Makes the bloom filter save itself after the given number of inserts since the last save.
+    pub fn with_insert_threshold(mut self, insert_threshold: u64) -> Self {
+        self.insert_threshold = Some(insert_threshold);
+        self
+    }
+
+    /// Sets the format the bloom filter is saved in.
+    pub fn with_format(mut self, format: FileFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+struct State {
+    bloom_filter: BloomFilter,
+    inserts_since_save: u64,
+    shutdown: bool,
+    last_save_time: Option<SystemTime>,
+    last_error: Option<String>,
+    save_attempts: u64,
+}
+
+struct Shared {
+    path: PathBuf,
+    options: AutosaveOptions,
+    state: Mutex<State>,
+    wakeup: Condvar,
+    // Serializes the saves, so an older snapshot never replaces a newer one.
+    save_lock: Mutex<()>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Serializes the bloom filter under the lock and writes it outside of it.
+    fn save(&self) -> Result<(), SaveBloomFilterError> {
+        let _save_guard: MutexGuard<'_, ()> =
+            self.save_lock.lock().unwrap_or_else(|err| err.into_inner());
+
+        let (serialized, inserts): (Result<Vec<u8>, SaveBloomFilterError>, u64) = {
+            let state: MutexGuard<'_, State> = self.lock();
+
+            (
+                state.bloom_filter.serialize(self.options.format),
+                state.inserts_since_save,
+            )
+        };
+
+        let result: Result<(), SaveBloomFilterError> =
+            serialized.and_then(|bytes| persist::write_atomic(&self.path, &bytes));
+
+        let mut state: MutexGuard<'_, State> = self.lock();
+
+        state.save_attempts += 1;
+
+        match &result {
+            Ok(()) => {
+                state.inserts_since_save -= inserts;
+                state.last_save_time = Some(SystemTime::now());
+                state.last_error = None;
+            }
+            Err(err) => state.last_error = Some(format!("{:?}", err)),
+        }
+
+        result
+    }
+
+    fn run(&self) {
+        let mut next_save: Option<Instant> = self
+            .options
+            .interval
+            .map(|interval| Instant::now() + interval);
+        // The inserts the threshold counts from, None until the next interval after a failed save.
+        let mut threshold_base: Option<u64> = Some(0);
+        let mut state: MutexGuard<'_, State> = self.lock();
+
+        loop {
+            if state.shutdown {
+                return;
+            }
+
+            let insert_threshold_reached: bool =
+                match (self.options.insert_threshold, threshold_base) {
+                    (Some(threshold), Some(base)) => {
+                        state.inserts_since_save > base
+                            && state.inserts_since_save - base >= threshold
+                    }
+                    _ => false,
+                };
+            let interval_elapsed: bool =
+                matches!(next_save, Some(next_save) if Instant::now() >= next_save);
+
+            if interval_elapsed || insert_threshold_reached {
+                let dirty: bool = state.inserts_since_save > 0;
+
+                drop(state);
+
+                // The errors are reported by last_error, the next attempt is made on the next interval,
+                // or without the interval once the threshold is reached again by the new inserts.
+                let failed: bool = dirty && self.save().is_err();
+
+                next_save = self
+                    .options
+                    .interval
+                    .map(|interval| Instant::now() + interval);
+                state = self.lock();
+                threshold_base = match (failed, next_save) {
+                    (false, _) => Some(0),
+                    (true, Some(_)) => None,
+                    (true, None) => Some(state.inserts_since_save),
+                };
+
+                continue;
+            }
+
+            state = match next_save {
+                Some(next_save) => {
+                    let timeout: Duration = next_save.saturating_duration_since(Instant::now());
+
+                    self.wakeup
+                        .wait_timeout(state, timeout)
+                        .unwrap_or_else(|err| err.into_inner())
+                        .0
+                }
+                None => self
+                    .wakeup
+                    .wait(state)
+                    .unwrap_or_else(|err| err.into_inner()),
+            };
+        }
+    }
+}
+
+/// A bloom filter owned by a background thread saving it to the file on an interval
+/// and/or after a number of inserts. Dropping it or calling shutdown() saves the final snapshot.
+///
+/// ```rust
+/// use std::fs;
+/// use std::time::Duration;
+/// use bfilters::{AutosaveBloomFilter, AutosaveOptions, BloomFilter};
+///
+/// let bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+/// let autosave_bloom_filter: AutosaveBloomFilter = AutosaveBloomFilter::start(
+///     bloom_filter,
+///     "./bfilter_autosave_doc.bin",
+///     AutosaveOptions::new().with_interval(Some(Duration::from_secs(5))),
+/// );
+///
+/// autosave_bloom_filter.insert("Vinegar");
+///
+/// assert!(autosave_bloom_filter.is_probably_present("Vinegar"));
+///
+/// autosave_bloom_filter.shutdown().unwrap();
+///
+/// assert!(BloomFilter::from_file("./bfilter_autosave_doc.bin").unwrap().is_probably_present("Vinegar"));
+///
+/// fs::remove_file("./bfilter_autosave_doc.bin").unwrap();
+/// ```
+pub struct AutosaveBloomFilter {
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl AutosaveBloomFilter {
+    /// Takes the ownership of the bloom filter and starts the background thread saving it to the given path.
+    pub fn start<P: AsRef<Path>>(
+        bloom_filter: BloomFilter,
+        path: P,
+        options: AutosaveOptions,
+    ) -> Self {
+        let shared: Arc<Shared> = Arc::new(Shared {
+            path: path.as_ref().to_owned(),
+            options,
+            state: Mutex::new(State {
+                bloom_filter,
+                inserts_since_save: 0,
+                shutdown: false,
+                last_save_time: None,
+                last_error: None,
+                save_attempts: 0,
+            }),
+            wakeup: Condvar::new(),
+            save_lock: Mutex::new(()),
+        });
+
+        let worker_shared: Arc<Shared> = Arc::clone(&shared);
+        let worker: JoinHandle<()> = thread::spawn(move || worker_shared.run());
+
+        Self {
+            shared,
+            worker: Some(worker),
+        }
+    }
+
+    /// Inserts the item into the bloom filter, waking up the background thread if the insert threshold is reached.
+    /// Returns false if the bloom filter is full, the same as bloom_filter.insert(...).
+    pub fn insert(&self, item: &str) -> bool {
+        let mut state: MutexGuard<'_, State> = self.shared.lock();
+
+        if !state.bloom_filter.insert(item) {
+            return false;
+        }
+
+        state.inserts_since_save += 1;
+
+        if matches!(self.shared.options.insert_threshold, Some(threshold) if state.inserts_since_save >= threshold)
+        {
+            self.shared.wakeup.notify_one();
+        }
+
+        true
+    }
+
+    /// Given the negative or false positive answer about the item presence in the bloom filter.
+    pub fn is_probably_present(&self, item: &str) -> bool {
+        self.shared.lock().bloom_filter.is_probably_present(item)
+    }
+
+    /// Saves the bloom filter right away on the calling thread.
+    pub fn save_now(&self) -> Result<(), SaveBloomFilterError> {
+        self.shared.save()
+    }
+
+    /// Returns the time of the last successful save, None if the bloom filter was not saved yet.
+    pub fn last_save_time(&self) -> Option<SystemTime> {
+        self.shared.lock().last_save_time
+    }
+
+    /// Returns the description of the error the last save failed with, None if it succeeded.
+    pub fn last_error(&self) -> Option<String> {
+        self.shared.lock().last_error.clone()
+    }
+
+    /// Stops the background thread and saves the final snapshot if there are unsaved items.
+    fn stop(&mut self) -> Result<(), SaveBloomFilterError> {
+        let worker: JoinHandle<()> = match self.worker.take() {
+            Some(worker) => worker,
+            None => return Ok(()),
+        };
+
+        self.shared.lock().shutdown = true;
+        self.shared.wakeup.notify_one();
+
+        let _ = worker.join();
+
+        if self.shared.lock().inserts_since_save > 0 {
+            self.shared.save()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Stops the background thread and saves the final snapshot, returning the error of the final save.
+    pub fn shutdown(mut self) -> Result<(), SaveBloomFilterError> {
+        self.stop()
+    }
+}
+
+impl Drop for AutosaveBloomFilter {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use super::{AutosaveBloomFilter, AutosaveOptions};
+    use crate::{BloomFilter, FileFormat};
+
+    fn wait_for_save(autosave_bloom_filter: &AutosaveBloomFilter) -> bool {
+        let deadline: Instant = Instant::now() + Duration::from_secs(10);
+
+        while autosave_bloom_filter.last_save_time().is_none() {
+            if Instant::now() > deadline {
+                return false;
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        true
+    }
+
+    #[test]
+    fn test_autosave_insert_threshold() {
+        let tmp_save_path: &Path = Path::new("./bfilter_autosave.json");
+        let bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        let autosave_bloom_filter: AutosaveBloomFilter = AutosaveBloomFilter::start(
+            bloom_filter,
+            tmp_save_path,
+            AutosaveOptions::new()
+                .with_interval(None)
+                .with_insert_threshold(2)
+                .with_format(FileFormat::Json),
+        );
+
+        autosave_bloom_filter.insert("John Green");
+
+        thread::sleep(Duration::from_millis(50));
+
+        let saved_before_threshold: bool = autosave_bloom_filter.last_save_time().is_some();
+
+        autosave_bloom_filter.insert("John White");
+
+        let saved_after_threshold: bool = wait_for_save(&autosave_bloom_filter);
+
+        autosave_bloom_filter.insert("John Black");
+        autosave_bloom_filter.shutdown().unwrap();
+
+        let mut saved_bloom_filter: BloomFilter = BloomFilter::from_file(tmp_save_path).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert!(!saved_before_threshold);
+        assert!(saved_after_threshold);
+        assert!(saved_bloom_filter.is_probably_present("John Black"));
+        assert_eq!(saved_bloom_filter.items_added(), 3);
+    }
+
+    #[test]
+    fn test_autosave_interval_error() {
+        let bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        let autosave_bloom_filter: AutosaveBloomFilter = AutosaveBloomFilter::start(
+            bloom_filter,
+            "./.test/bfilter_autosave.bin",
+            AutosaveOptions::new().with_interval(Some(Duration::from_millis(10))),
+        );
+
+        autosave_bloom_filter.insert("John Green");
+
+        let deadline: Instant = Instant::now() + Duration::from_secs(10);
+
+        while autosave_bloom_filter.last_error().is_none() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert!(autosave_bloom_filter.last_error().is_some());
+        assert!(autosave_bloom_filter.last_save_time().is_none());
+        assert!(autosave_bloom_filter.shutdown().is_err());
+    }
+
+    #[test]
+    fn test_autosave_insert_threshold_error() {
+        let bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1).unwrap();
+
+        let autosave_bloom_filter: AutosaveBloomFilter = AutosaveBloomFilter::start(
+            bloom_filter,
+            "./.test/bfilter_autosave_threshold.bin",
+            AutosaveOptions::new()
+                .with_interval(Some(Duration::from_secs(60)))
+                .with_insert_threshold(1),
+        );
+
+        assert!(autosave_bloom_filter.insert("John Green"));
+        assert!(!autosave_bloom_filter.insert("John White"));
+
+        let deadline: Instant = Instant::now() + Duration::from_secs(10);
+
+        while autosave_bloom_filter.last_error().is_none() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // The failed save is not retried before the next interval.
+        thread::sleep(Duration::from_millis(100));
+
+        let save_attempts: u64 = autosave_bloom_filter.shared.lock().save_attempts;
+
+        assert!(autosave_bloom_filter.last_error().is_some());
+        assert_eq!(save_attempts, 1);
+        assert!(autosave_bloom_filter.shutdown().is_err());
+    }
+}
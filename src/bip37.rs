@@ -0,0 +1,339 @@
+//! The Bitcoin BIP37 bloom filter sent by the SPV clients in the `filterload` message.
+//!
+//! The filter is a bit field of at most 36000 bytes probed by at most 50 hash functions. The hash function
+//! number `n` is `MurmurHash3_x86_32(data, n * 0xFBA4C795 + nTweak) mod (number of bits)` and the bit `i`
+//! is the bit `i % 8` of the byte `i / 8`. The `filterload` payload is the bit field as a byte vector
+//! (prefixed with its compact size), `nHashFuncs` and `nTweak` as 32 bit little endian integers and the `nFlags` byte.
+//!
+//! See <https://github.com/bitcoin/bips/blob/master/bip-0037.mediawiki>.
+
+use std::f64::consts::LN_2;
+
+use crate::hash::murmur3_32;
+use crate::LoadBloomFilterError;
+
+/// The maximal size of the bit field in bytes.
+pub const BIP37_MAX_FILTER_SIZE: usize = 36_000;
+
+/// The maximal number of hash functions.
+pub const BIP37_MAX_HASH_FUNCS: u32 = 50;
+
+/// The `nFlags` value telling the node not to update the filter with the matched outpoints.
+pub const BIP37_UPDATE_NONE: u8 = 0;
+/// The `nFlags` value telling the node to insert the outpoint of every matched output.
+pub const BIP37_UPDATE_ALL: u8 = 1;
+/// The `nFlags` value telling the node to insert the outpoints of the matched pay-to-pubkey and multisig outputs only.
+pub const BIP37_UPDATE_P2PUBKEY_ONLY: u8 = 2;
+
+const HASH_NUM_MULTIPLIER: u32 = 0xfba4c795;
+
+/// A BIP37 bloom filter with the same sizing, hashing and `filterload` layout as Bitcoin Core.
+///
+/// ```rust
+/// use bfilters::{Bip37BloomFilter, BIP37_UPDATE_ALL};
+///
+/// let mut bloom_filter: Bip37BloomFilter = Bip37BloomFilter::new(3, 0.01, 0, BIP37_UPDATE_ALL);
+///
+/// bloom_filter.insert(b"Vinegar");
+///
+/// let payload: Vec<u8> = bloom_filter.to_bytes();
+/// let parsed_bloom_filter: Bip37BloomFilter = Bip37BloomFilter::from_bytes(&payload).unwrap();
+///
+/// assert!(parsed_bloom_filter.is_probably_present(b"Vinegar"));
+/// assert!(!parsed_bloom_filter.is_probably_present(b"Coke"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bip37BloomFilter {
+    data: Vec<u8>,
+    number_of_hashes: u32,
+    tweak: u32,
+    flags: u8,
+}
+
+impl Bip37BloomFilter {
+    /// Creates an empty bloom filter for the given number of elements and false positive probability,
+    /// capped by the BIP37 limits the same way as the Bitcoin Core CBloomFilter constructor.
+    pub fn new(elements: u32, false_positive_probability: f64, tweak: u32, flags: u8) -> Self {
+        let number_of_bits: f64 =
+            -1.0 / (LN_2 * LN_2) * elements as f64 * false_positive_probability.ln();
+        let size: usize =
+            (number_of_bits as u32).min(BIP37_MAX_FILTER_SIZE as u32 * 8) as usize / 8;
+        // The bits per element are divided as integers first, as Bitcoin Core does.
+        let bits_per_element: usize = (size * 8).checked_div(elements as usize).unwrap_or(0);
+        let number_of_hashes: u32 =
+            ((bits_per_element as f64 * LN_2) as u32).min(BIP37_MAX_HASH_FUNCS);
+
+        Self {
+            data: vec![0; size],
+            number_of_hashes,
+            tweak,
+            flags,
+        }
+    }
+
+    /// Instantiates the bloom filter from its parts, validating the BIP37 size limits.
+    pub fn from_parts(
+        data: Vec<u8>,
+        number_of_hashes: u32,
+        tweak: u32,
+        flags: u8,
+    ) -> Result<Self, LoadBloomFilterError> {
+        if data.len() > BIP37_MAX_FILTER_SIZE || number_of_hashes > BIP37_MAX_HASH_FUNCS {
+            return Err(LoadBloomFilterError::Format(format!(
+                "The BIP37 bloom filter of {} bytes with {} hash functions exceeds the limits of {} bytes and {} hash functions.",
+                data.len(),
+                number_of_hashes,
+                BIP37_MAX_FILTER_SIZE,
+                BIP37_MAX_HASH_FUNCS
+            )));
+        }
+
+        Ok(Self {
+            data,
+            number_of_hashes,
+            tweak,
+            flags,
+        })
+    }
+
+    /// Parses the `filterload` message payload. The payloads over the BIP37 limits are rejected,
+    /// as Bitcoin Core rejects them from the peers.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadBloomFilterError> {
+        let malformed = || {
+            LoadBloomFilterError::Format(
+                "The BIP37 filterload payload is malformed or truncated.".to_owned(),
+            )
+        };
+
+        let (size, prefix_size): (u64, usize) = read_compact_size(bytes).ok_or_else(malformed)?;
+
+        if size > BIP37_MAX_FILTER_SIZE as u64 {
+            return Err(LoadBloomFilterError::Format(format!(
+                "The BIP37 bloom filter of {} bytes exceeds the limit of {} bytes.",
+                size, BIP37_MAX_FILTER_SIZE
+            )));
+        }
+
+        let rest: &[u8] = &bytes[prefix_size..];
+
+        if rest.len() != size as usize + 9 {
+            return Err(malformed());
+        }
+
+        let (data, rest): (&[u8], &[u8]) = rest.split_at(size as usize);
+
+        Self::from_parts(
+            data.to_vec(),
+            u32::from_le_bytes(rest[..4].try_into().unwrap()),
+            u32::from_le_bytes(rest[4..8].try_into().unwrap()),
+            rest[8],
+        )
+    }
+
+    /// Serializes the bloom filter into the `filterload` message payload.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(self.data.len() + 14);
+
+        write_compact_size(&mut bytes, self.data.len() as u64);
+        bytes.extend_from_slice(&self.data);
+        bytes.extend_from_slice(&self.number_of_hashes.to_le_bytes());
+        bytes.extend_from_slice(&self.tweak.to_le_bytes());
+        bytes.push(self.flags);
+        bytes
+    }
+
+    /// Returns the bit field.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the number of hash functions (`nHashFuncs`).
+    pub fn number_of_hashes(&self) -> u32 {
+        self.number_of_hashes
+    }
+
+    /// Returns the random value added to the hash seeds (`nTweak`).
+    pub fn tweak(&self) -> u32 {
+        self.tweak
+    }
+
+    /// Returns how the node updates the filter with the matched outpoints (`nFlags`), one of BIP37_UPDATE_*.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Inserts the data element, e.g. a public key, a public key hash or a serialized outpoint.
+    /// Nothing is inserted into the filter of 0 bytes.
+    pub fn insert(&mut self, data: &[u8]) {
+        if self.data.is_empty() {
+            return;
+        }
+
+        for hash_num in 0..self.number_of_hashes {
+            let index: usize = self.bit_index(hash_num, data);
+
+            self.data[index >> 3] |= 1 << (index & 7);
+        }
+    }
+
+    /// Given the negative or false positive answer about the data element presence in the bloom filter.
+    /// The filter of 0 bytes matches everything, as in Bitcoin Core.
+    pub fn is_probably_present(&self, data: &[u8]) -> bool {
+        if self.data.is_empty() {
+            return true;
+        }
+
+        (0..self.number_of_hashes).all(|hash_num| {
+            let index: usize = self.bit_index(hash_num, data);
+
+            self.data[index >> 3] & (1 << (index & 7)) != 0
+        })
+    }
+
+    /// Clears all the bits, as the `filterclear` message does on the node.
+    pub fn clear(&mut self) {
+        self.data.fill(0);
+    }
+
+    fn bit_index(&self, hash_num: u32, data: &[u8]) -> usize {
+        let seed: u32 = hash_num
+            .wrapping_mul(HASH_NUM_MULTIPLIER)
+            .wrapping_add(self.tweak);
+
+        murmur3_32(data, seed) as usize % (self.data.len() * 8)
+    }
+}
+
+/// Reads the Bitcoin compact size, returns the value with the size of its encoding.
+/// The non canonical encodings are rejected as Bitcoin Core does.
+pub(crate) fn read_compact_size(bytes: &[u8]) -> Option<(u64, usize)> {
+    let (value, len, min): (u64, usize, u64) = match *bytes.first()? {
+        0xfd => (
+            u64::from(u16::from_le_bytes(bytes.get(1..3)?.try_into().ok()?)),
+            3,
+            0xfd,
+        ),
+        0xfe => (
+            u64::from(u32::from_le_bytes(bytes.get(1..5)?.try_into().ok()?)),
+            5,
+            0x1_0000,
+        ),
+        0xff => (
+            u64::from_le_bytes(bytes.get(1..9)?.try_into().ok()?),
+            9,
+            0x1_0000_0000,
+        ),
+        byte => (u64::from(byte), 1, 0),
+    };
+
+    (value >= min).then_some((value, len))
+}
+
+pub(crate) fn write_compact_size(bytes: &mut Vec<u8>, value: u64) {
+    match value {
+        0..=0xfc => bytes.push(value as u8),
+        0xfd..=0xffff => {
+            bytes.push(0xfd);
+            bytes.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            bytes.push(0xfe);
+            bytes.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        _ => {
+            bytes.push(0xff);
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bip37BloomFilter, BIP37_MAX_FILTER_SIZE, BIP37_UPDATE_ALL};
+    use crate::LoadBloomFilterError;
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// The bloom_create_insert_serialize and bloom_create_insert_serialize_with_tweak cases of the Bitcoin Core bloom_tests.
+    #[test]
+    fn test_bip37_core_vectors() {
+        for (tweak, expected) in [
+            (0, "03614e9b050000000000000001"),
+            (2147483649, "03ce4299050000000100008001"),
+        ] {
+            let mut bloom_filter: Bip37BloomFilter =
+                Bip37BloomFilter::new(3, 0.01, tweak, BIP37_UPDATE_ALL);
+
+            bloom_filter.insert(&unhex("99108ad8ed9bb6274d3980bab5a85c048f0950c8"));
+
+            assert!(bloom_filter
+                .is_probably_present(&unhex("99108ad8ed9bb6274d3980bab5a85c048f0950c8")));
+            assert!(!bloom_filter
+                .is_probably_present(&unhex("19108ad8ed9bb6274d3980bab5a85c048f0950c8")));
+
+            bloom_filter.insert(&unhex("b5a2c786d9ef4658287ced5914b37a1b4aa32eee"));
+            bloom_filter.insert(&unhex("b9300670b4c5366e95b2699e8b18bc75e5f729c5"));
+
+            assert!(bloom_filter
+                .is_probably_present(&unhex("b5a2c786d9ef4658287ced5914b37a1b4aa32eee")));
+            assert!(bloom_filter
+                .is_probably_present(&unhex("b9300670b4c5366e95b2699e8b18bc75e5f729c5")));
+            assert_eq!(bloom_filter.to_bytes(), unhex(expected));
+
+            let parsed: Bip37BloomFilter = Bip37BloomFilter::from_bytes(&unhex(expected)).unwrap();
+
+            assert_eq!(parsed, bloom_filter);
+            assert_eq!(parsed.tweak(), tweak);
+            assert_eq!(parsed.flags(), BIP37_UPDATE_ALL);
+        }
+    }
+
+    #[test]
+    fn test_bip37_limits() {
+        let bloom_filter: Bip37BloomFilter = Bip37BloomFilter::new(1_000_000, 0.000_001, 0, 0);
+
+        assert_eq!(bloom_filter.data().len(), BIP37_MAX_FILTER_SIZE);
+        assert_eq!(bloom_filter.number_of_hashes(), 0);
+
+        let bloom_filter: Bip37BloomFilter = Bip37BloomFilter::new(1, 1e-30, 0, 0);
+
+        assert_eq!(bloom_filter.number_of_hashes(), 50);
+
+        let bytes: Vec<u8> = Bip37BloomFilter::new(20_000, 0.0001, 7, 2).to_bytes();
+
+        assert_eq!(bytes[0], 0xfd);
+        assert_eq!(Bip37BloomFilter::from_bytes(&bytes).unwrap().tweak(), 7);
+
+        // Oversized data, too many hash functions, a truncated payload and a non canonical compact size.
+        let mut oversized: Vec<u8> = vec![0xfd, 0xa1, 0x8c];
+
+        oversized.resize(3 + 36_001 + 9, 0);
+
+        let mut too_many_hashes: Vec<u8> = unhex("03614e9b050000000000000001");
+
+        too_many_hashes[4] = 51;
+
+        for bytes in [
+            oversized,
+            too_many_hashes,
+            unhex("03614e9b0500000000000000"),
+            unhex("fd0300614e9b050000000000000001"),
+        ] {
+            assert!(matches!(
+                Bip37BloomFilter::from_bytes(&bytes),
+                Err(LoadBloomFilterError::Format(_))
+            ));
+        }
+
+        let mut empty: Bip37BloomFilter =
+            Bip37BloomFilter::from_bytes(&unhex("00000000000000000000")).unwrap();
+
+        empty.insert(b"Vinegar");
+        assert!(empty.is_probably_present(b"Coke"));
+    }
+}
@@ -0,0 +1,294 @@
+//! Compression of the binary payload with zstd or lz4, enabled by the `zstd` and `lz4` features.
+//!
+//! The sparsely filled bloom filters are mostly zero bytes, so their payload shrinks by orders of magnitude.
+//! The codec and the compressed size are recorded in the header and the payload is decompressed transparently
+//! on loading. The loading of a compressed file fails if the crate is built without the feature of its codec.
+
+use std::io::{self, Read};
+
+use crate::format::{Header, CODEC_LZ4, CODEC_ZSTD};
+use crate::LoadBloomFilterError;
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+use std::io::Write;
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+use std::path::Path;
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+use crate::format::CompressedPayload;
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+use crate::{persist, BloomFilter, SaveBloomFilterError};
+
+/// The compression of the payload of the binary bloom filter files.
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// The payload is stored as is, the file could be memory mapped.
+    None,
+    /// Zstandard with the given level, from 1 (the fastest) to 22 (the smallest), 0 is the zstd default.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+    /// LZ4 block compression, it has no levels and is the fastest to decompress.
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+impl Compression {
+    /// Returns the compression of the serialized bloom filter, None for the JSON and the uncompressed ones.
+    /// The level is not stored, the zstd payloads are reported with the default level 0.
+    pub fn detect(bytes: &[u8]) -> Compression {
+        match Header::parse_header(bytes).map(|header| header.compression) {
+            #[cfg(feature = "zstd")]
+            Ok(Some(CompressedPayload {
+                codec: CODEC_ZSTD, ..
+            })) => Compression::Zstd(0),
+            #[cfg(feature = "lz4")]
+            Ok(Some(CompressedPayload {
+                codec: CODEC_LZ4, ..
+            })) => Compression::Lz4,
+            _ => Compression::None,
+        }
+    }
+
+    /// Compresses the payload, returns the codec with the compressed bytes or None if it is not compressed.
+    fn compress(self, payload: &[u8]) -> io::Result<Option<(u8, Vec<u8>)>> {
+        match self {
+            Compression::None => Ok(None),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => {
+                Ok(Some((CODEC_ZSTD, zstd::bulk::compress(payload, level)?)))
+            }
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(Some((CODEC_LZ4, lz4_flex::block::compress(payload)))),
+        }
+    }
+}
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+impl BloomFilter {
+    /// Serializes the bloom filter into the binary format with the payload compressed.
+    /// The payload which does not shrink is stored uncompressed.
+    ///
+    /// ```rust
+    /// use bfilters::{BloomFilter, Compression};
+    ///
+    /// let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1_000_000).unwrap();
+    ///
+    /// bloom_filter.insert("Vinegar");
+    ///
+    /// # #[cfg(feature = "zstd")]
+    /// let bytes: Vec<u8> = bloom_filter.to_compressed_bytes(Compression::Zstd(19)).unwrap();
+    /// # #[cfg(not(feature = "zstd"))]
+    /// # let bytes: Vec<u8> = bloom_filter.to_compressed_bytes(Compression::Lz4).unwrap();
+    ///
+    /// assert!(bytes.len() < bloom_filter.to_bytes().len() / 100);
+    /// assert!(BloomFilter::from_bytes(&bytes).unwrap().is_probably_present("Vinegar"));
+    /// ```
+    pub fn to_compressed_bytes(&self, compression: Compression) -> io::Result<Vec<u8>> {
+        let mut bytes: Vec<u8> = Vec::new();
+
+        self.write_compressed(&mut bytes, compression)?;
+
+        Ok(bytes)
+    }
+
+    /// Atomically saves the bloom filter in the binary format with the payload compressed.
+    /// The file is loaded with BloomFilter::from_file(...) as usual, but could not be memory mapped.
+    pub fn save_compressed<P: AsRef<Path>>(
+        &self,
+        path: P,
+        compression: Compression,
+    ) -> Result<(), SaveBloomFilterError> {
+        persist::write_atomic_with(path.as_ref(), |writer| {
+            Ok(self.write_compressed(writer, compression)?)
+        })
+    }
+
+    fn write_compressed<W: Write>(
+        &self,
+        mut writer: W,
+        compression: Compression,
+    ) -> io::Result<()> {
+        let (codec, compressed): (u8, Vec<u8>) = match compression.compress(self.payload())? {
+            Some((codec, compressed)) if compressed.len() < self.payload().len() => {
+                (codec, compressed)
+            }
+            _ => return self.write_binary(writer),
+        };
+
+        let header: Header = Header {
+            compression: Some(CompressedPayload {
+                codec,
+                len: compressed.len() as u64,
+            }),
+            ..self.binary_header()
+        };
+
+        writer.write_all(&header.to_bytes())?;
+        writer.write_all(&compressed)?;
+
+        if let Some(signature) = self.calc_signature() {
+            writer.write_all(&signature)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn codec_name(codec: u8) -> &'static str {
+    match codec {
+        CODEC_ZSTD => "zstd",
+        CODEC_LZ4 => "lz4",
+        _ => "unknown",
+    }
+}
+
+/// Decompresses the stored payload into the bit array payload, the uncompressed payload is copied as is.
+pub(crate) fn decompress(
+    header: &Header,
+    stored: &[u8],
+    payload: &mut [u8],
+) -> Result<(), LoadBloomFilterError> {
+    let codec: u8 = match header.compression {
+        Some(compression) => compression.codec,
+        None => {
+            payload.copy_from_slice(stored);
+
+            return Ok(());
+        }
+    };
+
+    let corrupted = |err: &dyn std::fmt::Display| {
+        LoadBloomFilterError::Format(format!(
+            "The {} compressed payload is corrupted: {}.",
+            codec_name(codec),
+            err
+        ))
+    };
+
+    let len: usize = match codec {
+        #[cfg(feature = "zstd")]
+        CODEC_ZSTD => {
+            zstd::bulk::decompress_to_buffer(stored, payload).map_err(|err| corrupted(&err))
+        }
+        #[cfg(feature = "lz4")]
+        CODEC_LZ4 => {
+            lz4_flex::block::decompress_into(stored, payload).map_err(|err| corrupted(&err))
+        }
+        _ => Err(LoadBloomFilterError::Format(format!(
+            "The binary bloom filter payload is compressed with {0}, the crate should be built with the {0} feature to load it.",
+            codec_name(codec)
+        ))),
+    }?;
+
+    if len != payload.len() {
+        return Err(corrupted(&format!(
+            "{} bytes decompressed, expected {}",
+            len,
+            payload.len()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Reads the stored payload following the header from the reader and decompresses it into the bit array payload.
+pub(crate) fn read_compressed<R: Read>(
+    reader: R,
+    header: &Header,
+    payload: &mut [u8],
+) -> Result<(), LoadBloomFilterError> {
+    let len: usize = header.stored_payload_len();
+    let mut stored: Vec<u8> = Vec::new();
+
+    // The size comes from the file, so the buffer grows with the bytes actually read.
+    reader.take(len as u64).read_to_end(&mut stored)?;
+
+    if stored.len() < len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+
+    decompress(header, &stored, payload)
+}
+
+#[cfg(all(test, any(feature = "zstd", feature = "lz4")))]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::Compression;
+    use crate::{BloomFilter, LoadBloomFilterError, LoadOptions, MmapBloomFilter};
+
+    fn compressions() -> Vec<Compression> {
+        vec![
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(3),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(19),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4,
+        ]
+    }
+
+    #[test]
+    fn test_compressed_serialize_deserialize() {
+        let tmp_save_path: &Path = Path::new("./bfilter_compressed.bin");
+        let item: &str = "John Green";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 20_000_000)
+            .unwrap()
+            .with_salt(42)
+            .unwrap()
+            .with_signing_key([7; 32]);
+
+        bloom_filter.insert(item);
+
+        let payload_len: u64 = bloom_filter.to_bytes().len() as u64;
+
+        for compression in compressions() {
+            bloom_filter
+                .save_compressed(tmp_save_path, compression)
+                .unwrap();
+
+            let file_len: u64 = fs::metadata(tmp_save_path).unwrap().len();
+            let options: LoadOptions = LoadOptions::new().with_signing_key([7; 32]);
+            let mut loaded_bloom_filter: BloomFilter =
+                BloomFilter::from_file_with_options(tmp_save_path, options).unwrap();
+            let mapped: bool = MmapBloomFilter::open(tmp_save_path).is_ok();
+
+            fs::remove_file(tmp_save_path).unwrap();
+
+            assert!(
+                file_len < payload_len / 100,
+                "{:?}: {}",
+                compression,
+                file_len
+            );
+            assert!(loaded_bloom_filter.is_probably_present(item));
+            assert_eq!(
+                loaded_bloom_filter.count_set_bits(),
+                bloom_filter.count_set_bits()
+            );
+            assert!(!mapped);
+
+            let bytes: Vec<u8> = bloom_filter.to_compressed_bytes(compression).unwrap();
+
+            assert!(BloomFilter::from_bytes_with_options(&bytes, options).is_ok());
+            assert!(BloomFilter::read_from(&bytes[..], options, |_, _| ()).is_ok());
+            assert!(matches!(
+                BloomFilter::from_bytes_with_options(&bytes[..bytes.len() - 40], options),
+                Err(LoadBloomFilterError::Format(_))
+            ));
+            assert!(matches!(
+                BloomFilter::read_from(&bytes[..bytes.len() - 40], options, |_, _| ()),
+                Err(LoadBloomFilterError::Io(_))
+            ));
+        }
+
+        assert_eq!(
+            bloom_filter.to_compressed_bytes(Compression::None).unwrap(),
+            bloom_filter.to_bytes()
+        );
+    }
+}
@@ -0,0 +1,465 @@
+//! The binary on-disk layout of the bloom filter.
+//!
+//! The file is a fixed 64 bytes header followed by the bit payload, all the integers are little endian:
+//!
+//! | offset | size | field                                         |
+//! |--------|------|-----------------------------------------------|
+//! | 0      | 4    | magic `BFLT`                                  |
+//! | 4      | 2    | format version                                |
+//! | 6      | 2    | flags (1 keyed, 2 salted, 4 crc, 8 signed,    |
+//! |        |      | 16 compressed)                                |
+//! | 8      | 4    | hash version                                  |
+//! | 12     | 4    | false positive probability (f32)              |
+//! | 16     | 4    | number of bits                                |
+//! | 20     | 4    | items count                                   |
+//! | 24     | 4    | number of hashes                              |
+//! | 28     | 4    | items added                                   |
+//! | 32     | 8    | salt (0 if not salted)                        |
+//! | 40     | 4    | CRC32C checksum (0 if no checksum)            |
+//! | 44     | 1    | compression codec (0 none, 1 zstd, 2 lz4)     |
+//! | 45     | 3    | reserved, zeros                               |
+//! | 48     | 8    | compressed payload size (0 if not compressed) |
+//! | 56     | 8    | inserts through the writable memory maps      |
+//!
+//! The payload is `ceil(number_of_bits / 8)` bytes, the bit `i` is the bit `i % 8`
+//! (counted from the least significant one) of the byte `i / 8`. The compressed files store the payload
+//! compressed with the codec instead, such files could not be memory mapped.
+//!
+//! The checksum is the CRC32C of the header with the checksum flag and field zeroed followed by the payload.
+//! The compression flag and fields are zeroed as well and the payload is the uncompressed one,
+//! so the checksum does not depend on the compression.
+//! The JSON files store the same checksum in the `checksum` field.
+//!
+//! The signed files are followed by the 32 bytes HMAC-SHA256 tag of the same bytes as the checksum
+//! (the signed flag is zeroed as well). The JSON files store it in the `signature` field.
+//! The header size is a multiple of 8, so a page aligned mapping keeps the payload aligned.
+//!
+//! The inserts counter lets the writable memory maps tell whether the bits changed while the checksum was calculated,
+//! it is not covered by the checksum and is written as zeros otherwise.
+
+use crate::{HashKey, LoadBloomFilterError, HASH_VERSION};
+
+/// The first bytes of every binary bloom filter file.
+pub const MAGIC: [u8; 4] = *b"BFLT";
+
+/// The version of the binary layout written by this version of the crate.
+pub const FORMAT_VERSION: u16 = 1;
+
+/// The version of the serde layout (the JSON files and any other serde format) written by this version of the crate,
+/// stored in its `format_version` field. The JSON files written by the crate 0.2 have no such field
+/// and are the legacy layout, version 0. The version 1 is the same flat layout with the field.
+pub const SERDE_FORMAT_VERSION: u16 = 2;
+
+/// The size of the binary header preceding the bit payload.
+pub const HEADER_SIZE: usize = 64;
+
+/// The offset of the items added counter, updated in place by the writable memory maps.
+pub(crate) const ITEMS_ADDED_OFFSET: usize = 28;
+
+pub(crate) const FLAG_KEYED: u16 = 1;
+pub(crate) const FLAG_SALTED: u16 = 1 << 1;
+pub(crate) const FLAG_CHECKSUM: u16 = 1 << 2;
+pub(crate) const FLAG_SIGNED: u16 = 1 << 3;
+pub(crate) const FLAG_COMPRESSED: u16 = 1 << 4;
+
+const KNOWN_FLAGS: u16 = FLAG_KEYED | FLAG_SALTED | FLAG_CHECKSUM | FLAG_SIGNED | FLAG_COMPRESSED;
+
+/// The codecs the payload of the compressed files could be compressed with.
+pub(crate) const CODEC_ZSTD: u8 = 1;
+pub(crate) const CODEC_LZ4: u8 = 2;
+
+/// The size of the HMAC-SHA256 tag following the payload of the signed files.
+pub(crate) const SIGNATURE_SIZE: usize = 32;
+
+/// The offsets of the flags and the checksum, cleared in place by the writable memory maps.
+pub(crate) const FLAGS_OFFSET: usize = 6;
+pub(crate) const CHECKSUM_OFFSET: usize = 40;
+
+/// The offset of the counter of the inserts through the writable memory maps.
+pub(crate) const INSERTS_OFFSET: usize = 56;
+
+/// The serialization formats the bloom filter could be saved in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileFormat {
+    /// The serde JSON of the bloom filter written by bloom_filter.save(...).
+    Json,
+    /// The binary layout written by bloom_filter.save_binary(...), could be memory mapped.
+    Binary,
+}
+
+impl FileFormat {
+    /// Detects the format of the serialized bloom filter by its first bytes.
+    pub fn detect(bytes: &[u8]) -> FileFormat {
+        if bytes.starts_with(&MAGIC) {
+            FileFormat::Binary
+        } else {
+            FileFormat::Json
+        }
+    }
+}
+
+/// The parameters of the bloom filter stored in the binary header.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Header {
+    pub flags: u16,
+    pub hash_version: u32,
+    pub false_positive_probability: f32,
+    pub number_of_bits: u32,
+    pub items_count: u32,
+    pub number_of_hashes: u32,
+    pub items_added: u32,
+    pub salt: Option<u64>,
+    pub checksum: Option<u32>,
+    pub compression: Option<CompressedPayload>,
+}
+
+/// The codec and the size of the compressed payload of the compressed files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct CompressedPayload {
+    pub codec: u8,
+    pub len: u64,
+}
+
+impl Header {
+    /// Returns true if the bloom filter hashes the items with a secret key.
+    pub fn is_keyed(&self) -> bool {
+        self.flags & FLAG_KEYED != 0
+    }
+
+    /// Returns true if the payload is followed by the HMAC-SHA256 tag.
+    pub fn is_signed(&self) -> bool {
+        self.flags & FLAG_SIGNED != 0
+    }
+
+    /// Returns the number of payload bytes following the header.
+    pub fn payload_len(&self) -> usize {
+        payload_len(self.number_of_bits)
+    }
+
+    /// Returns the number of payload bytes stored in the file, the compressed ones if the payload is compressed.
+    pub fn stored_payload_len(&self) -> usize {
+        match self.compression {
+            Some(compression) => compression.len as usize,
+            None => self.payload_len(),
+        }
+    }
+
+    /// Returns the number of bytes the whole bloom filter occupies: the header, the payload and the tag.
+    pub fn total_len(&self) -> usize {
+        HEADER_SIZE + self.stored_payload_len() + if self.is_signed() { SIGNATURE_SIZE } else { 0 }
+    }
+
+    /// Encodes the header parameters covered by the checksum and the signature.
+    pub fn canonical_bytes(&self) -> [u8; HEADER_SIZE] {
+        Header {
+            flags: self.flags & !(FLAG_CHECKSUM | FLAG_SIGNED | FLAG_COMPRESSED),
+            checksum: None,
+            compression: None,
+            ..self.clone()
+        }
+        .to_bytes()
+    }
+
+    /// Calculates the checksum of the header parameters and the given payload.
+    pub fn calc_checksum(&self, payload: &[u8]) -> u32 {
+        crc32c::crc32c_append(crc32c::crc32c(&self.canonical_bytes()), payload)
+    }
+
+    /// Returns the header with the given checksum of its payload.
+    pub fn with_checksum(self, checksum: u32) -> Header {
+        Header {
+            flags: self.flags | FLAG_CHECKSUM,
+            checksum: Some(checksum),
+            ..self
+        }
+    }
+
+    /// Validates that the key is passed only for the keyed bloom filters and only if it is required.
+    pub fn check_key(&self, key: Option<&HashKey>) -> Result<(), LoadBloomFilterError> {
+        match (self.is_keyed(), key) {
+            (true, None) => Err(LoadBloomFilterError::MissingKey),
+            (false, Some(_)) => Err(LoadBloomFilterError::UnexpectedKey),
+            _ => Ok(()),
+        }
+    }
+
+    /// Validates that the payload matches the checksum the bloom filter was saved with.
+    /// The binary bloom filters are always saved with the checksum, so a missing one is rejected as well.
+    pub fn verify_checksum(&self, payload: &[u8]) -> Result<(), LoadBloomFilterError> {
+        self.check_checksum(self.calc_checksum(payload))
+    }
+
+    /// Validates the checksum the bloom filter was saved with against the one calculated over its payload.
+    pub fn check_checksum(&self, calculated: u32) -> Result<(), LoadBloomFilterError> {
+        match self.checksum {
+            None => Err(LoadBloomFilterError::MissingChecksum),
+            Some(checksum) if checksum != calculated => Err(LoadBloomFilterError::ChecksumMismatch),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Encodes the header into its binary layout.
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
+        let mut flags: u16 = self.flags & !(FLAG_CHECKSUM | FLAG_COMPRESSED);
+
+        if self.checksum.is_some() {
+            flags |= FLAG_CHECKSUM;
+        }
+
+        if self.compression.is_some() {
+            flags |= FLAG_COMPRESSED;
+        }
+
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..6].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes[6..8].copy_from_slice(&flags.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.hash_version.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.false_positive_probability.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.number_of_bits.to_le_bytes());
+        bytes[20..24].copy_from_slice(&self.items_count.to_le_bytes());
+        bytes[24..28].copy_from_slice(&self.number_of_hashes.to_le_bytes());
+        bytes[28..32].copy_from_slice(&self.items_added.to_le_bytes());
+        bytes[32..40].copy_from_slice(&self.salt.unwrap_or_default().to_le_bytes());
+        bytes[40..44].copy_from_slice(&self.checksum.unwrap_or_default().to_le_bytes());
+
+        if let Some(compression) = self.compression {
+            bytes[44] = compression.codec;
+            bytes[48..56].copy_from_slice(&compression.len.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    /// Decodes and validates the header at the beginning of the given bytes.
+    /// The bytes should contain the whole payload as well.
+    pub fn parse(bytes: &[u8]) -> Result<Header, LoadBloomFilterError> {
+        let header: Header = Header::parse_header(bytes)?;
+
+        if bytes.len() < header.total_len() {
+            return Err(LoadBloomFilterError::Format(format!(
+                "The binary bloom filter payload is truncated, expected {} bytes, found {}.",
+                header.total_len() - HEADER_SIZE,
+                bytes.len() - HEADER_SIZE
+            )));
+        }
+
+        Ok(header)
+    }
+
+    /// Decodes and validates the header alone, e.g. before streaming the payload.
+    pub fn parse_header(bytes: &[u8]) -> Result<Header, LoadBloomFilterError> {
+        if bytes.len() < HEADER_SIZE || !bytes.starts_with(&MAGIC) {
+            return Err(LoadBloomFilterError::Format(
+                "The binary bloom filter header is missing.".to_owned(),
+            ));
+        }
+
+        let format_version: u16 = read_u16(bytes, 4);
+
+        if format_version != FORMAT_VERSION {
+            return Err(LoadBloomFilterError::Format(format!(
+                "The binary bloom filter format version {} is not supported.",
+                format_version
+            )));
+        }
+
+        let flags: u16 = read_u16(bytes, 6);
+
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(LoadBloomFilterError::Format(format!(
+                "The binary bloom filter has unknown flags {:#06x}.",
+                flags
+            )));
+        }
+
+        let header: Header = Header {
+            flags,
+            hash_version: read_u32(bytes, 8),
+            false_positive_probability: f32::from_le_bytes(bytes[12..16].try_into().unwrap()),
+            number_of_bits: read_u32(bytes, 16),
+            items_count: read_u32(bytes, 20),
+            number_of_hashes: read_u32(bytes, 24),
+            items_added: read_u32(bytes, 28),
+            salt: if flags & FLAG_SALTED != 0 {
+                Some(u64::from_le_bytes(bytes[32..40].try_into().unwrap()))
+            } else {
+                None
+            },
+            checksum: if flags & FLAG_CHECKSUM != 0 {
+                Some(read_u32(bytes, CHECKSUM_OFFSET))
+            } else {
+                None
+            },
+            compression: if flags & FLAG_COMPRESSED != 0 {
+                Some(CompressedPayload {
+                    codec: bytes[44],
+                    len: u64::from_le_bytes(bytes[48..56].try_into().unwrap()),
+                })
+            } else {
+                None
+            },
+        };
+
+        if let Some(compression) = header.compression {
+            if compression.codec != CODEC_ZSTD && compression.codec != CODEC_LZ4 {
+                return Err(LoadBloomFilterError::Format(format!(
+                    "The binary bloom filter payload is compressed with the unknown codec {}.",
+                    compression.codec
+                )));
+            }
+
+            // The payloads which do not shrink are saved uncompressed.
+            if compression.len == 0 || compression.len >= header.payload_len() as u64 {
+                return Err(LoadBloomFilterError::Format(format!(
+                    "The binary bloom filter compressed payload size {} is invalid.",
+                    compression.len
+                )));
+            }
+        }
+
+        if header.hash_version != HASH_VERSION {
+            return Err(LoadBloomFilterError::UnsupportedHashVersion(
+                header.hash_version,
+            ));
+        }
+
+        if header.number_of_bits == 0 {
+            return Err(LoadBloomFilterError::Format(
+                "The binary bloom filter has no bits.".to_owned(),
+            ));
+        }
+
+        Ok(header)
+    }
+}
+
+/// Returns the number of bytes needed to store the given number of bits.
+pub(crate) fn payload_len(number_of_bits: u32) -> usize {
+    (number_of_bits as usize).div_ceil(8)
+}
+
+/// Returns true if the bit at the given index is set in the payload.
+pub(crate) fn is_bit_set(payload: &[u8], index: u64) -> bool {
+    payload[(index / 8) as usize] & (1 << (index % 8)) != 0
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompressedPayload, FileFormat, Header, CODEC_ZSTD, HEADER_SIZE};
+    use crate::LoadBloomFilterError;
+
+    fn header() -> Header {
+        Header {
+            flags: super::FLAG_SALTED,
+            hash_version: crate::HASH_VERSION,
+            false_positive_probability: 0.01,
+            number_of_bits: 9,
+            items_count: 1,
+            number_of_hashes: 6,
+            items_added: 1,
+            salt: Some(42),
+            checksum: None,
+            compression: None,
+        }
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let mut bytes: Vec<u8> = header().to_bytes().to_vec();
+
+        bytes.extend_from_slice(&[0xff, 0x01]);
+
+        assert_eq!(Header::parse(&bytes).unwrap(), header());
+        assert_eq!(FileFormat::detect(&bytes), FileFormat::Binary);
+        assert_eq!(FileFormat::detect(b"{}"), FileFormat::Json);
+    }
+
+    #[test]
+    fn test_header_truncated_payload() {
+        let bytes: Vec<u8> = header().to_bytes().to_vec();
+
+        assert!(matches!(
+            Header::parse(&bytes),
+            Err(LoadBloomFilterError::Format(_))
+        ));
+        assert!(matches!(
+            Header::parse(&bytes[..HEADER_SIZE - 1]),
+            Err(LoadBloomFilterError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn test_header_unknown_flags() {
+        let mut bytes: Vec<u8> = header().to_bytes().to_vec();
+
+        bytes.extend_from_slice(&[0, 0]);
+        bytes[7] = 0x80;
+
+        assert!(matches!(
+            Header::parse(&bytes),
+            Err(LoadBloomFilterError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn test_header_compression() {
+        let header: Header = Header {
+            flags: super::FLAG_SALTED | super::FLAG_COMPRESSED,
+            number_of_bits: 800,
+            compression: Some(CompressedPayload {
+                codec: CODEC_ZSTD,
+                len: 12,
+            }),
+            ..header()
+        };
+        let mut bytes: Vec<u8> = header.to_bytes().to_vec();
+
+        bytes.extend_from_slice(&[0; 12]);
+
+        assert_eq!(Header::parse(&bytes).unwrap(), header);
+        assert_eq!(header.total_len(), HEADER_SIZE + 12);
+        assert_eq!(
+            header.canonical_bytes(),
+            Header {
+                compression: None,
+                ..header.clone()
+            }
+            .canonical_bytes()
+        );
+
+        // A compressed payload larger than the uncompressed one.
+        bytes[48] = 100;
+
+        assert!(matches!(
+            Header::parse_header(&bytes),
+            Err(LoadBloomFilterError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn test_header_checksum() {
+        let payload: [u8; 2] = [0xff, 0x01];
+        let header: Header = header().with_checksum(header().calc_checksum(&payload));
+        let mut bytes: Vec<u8> = header.to_bytes().to_vec();
+
+        bytes.extend_from_slice(&payload);
+
+        let parsed: Header = Header::parse(&bytes).unwrap();
+
+        assert_eq!(parsed, header);
+        assert!(parsed.verify_checksum(&payload).is_ok());
+        assert!(matches!(
+            parsed.verify_checksum(&[0xff, 0x00]),
+            Err(LoadBloomFilterError::ChecksumMismatch)
+        ));
+    }
+}
@@ -0,0 +1,494 @@
+//! The Golomb-coded set (GCS) filters of BIP158, served to the Bitcoin light clients for every block.
+//!
+//! A set of N items is hashed with SipHash-2-4 keyed by the first 16 bytes of the block hash and mapped
+//! uniformly into `[0, N * M)`. The sorted hashes are delta encoded with the Golomb-Rice coding of the parameter P:
+//! the quotient `delta >> P` in unary (ones closed by a zero) followed by the P lower bits, most significant bit first.
+//! The serialized filter is N as a compact size followed by the bit stream padded with zeros to whole bytes.
+//! The basic filters use P = 19 and M = 784931, which gives the false positive rate of about 1 / 784931.
+//!
+//! Unlike a bloom filter a GCS could not be updated, but it takes about P + 2 bits per item.
+//!
+//! See <https://github.com/bitcoin/bips/blob/master/bip-0158.mediawiki>.
+
+use std::hash::Hasher;
+
+use sha2::{Digest, Sha256};
+use siphasher::sip::SipHasher24;
+
+use crate::bip37::{read_compact_size, write_compact_size};
+use crate::LoadBloomFilterError;
+
+/// The Golomb-Rice parameter of the BIP158 basic filters.
+pub const BIP158_P: u8 = 19;
+
+/// The inverse false positive rate of the BIP158 basic filters.
+pub const BIP158_M: u64 = 784_931;
+
+/// A BIP158 Golomb-coded set filter keyed by its block hash.
+///
+/// The block hashes are in the internal byte order, the reverse of the hex shown by the RPC and the block explorers.
+///
+/// ```rust
+/// use bfilters::GcsFilter;
+///
+/// let block_hash: [u8; 32] = [7; 32];
+/// let filter: GcsFilter = GcsFilter::new(&block_hash, ["Vinegar".as_bytes(), "Salt".as_bytes()]);
+///
+/// let bytes: Vec<u8> = filter.to_bytes();
+/// let parsed_filter: GcsFilter = GcsFilter::from_bytes(&block_hash, &bytes).unwrap();
+///
+/// assert!(parsed_filter.match_single(b"Vinegar"));
+/// assert!(parsed_filter.match_any(["Coke".as_bytes(), "Salt".as_bytes()]));
+/// assert!(!parsed_filter.match_any(["Coke".as_bytes(), "Sugar".as_bytes()]));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GcsFilter {
+    key: [u8; 16],
+    p: u8,
+    m: u64,
+    n: u64,
+    /// The serialized filter, N followed by the Golomb-Rice coded deltas.
+    bytes: Vec<u8>,
+    /// The offset of the coded deltas in the serialized filter.
+    offset: usize,
+}
+
+impl GcsFilter {
+    /// Builds the basic filter (P = 19, M = 784931) of the items, the duplicates are inserted once.
+    pub fn new<I, T>(block_hash: &[u8; 32], items: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        Self::with_params(block_hash, BIP158_P, BIP158_M, items)
+    }
+
+    /// Builds the filter of the items with the given Golomb-Rice parameter P (at most 32) and inverse false positive rate M.
+    pub fn with_params<I, T>(block_hash: &[u8; 32], p: u8, m: u64, items: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        assert!(p <= 32, "The Golomb-Rice parameter should be at most 32.");
+
+        let key: [u8; 16] = block_hash[..16].try_into().unwrap();
+        let items: Vec<T> = items.into_iter().collect();
+        let mut items: Vec<&[u8]> = items.iter().map(|item| item.as_ref()).collect();
+
+        items.sort_unstable();
+        items.dedup();
+
+        // The distinct items colliding on the same hash are coded as zero deltas, as Bitcoin Core does.
+        let n: u64 = items.len() as u64;
+        let mut hashes: Vec<u64> = items
+            .iter()
+            .map(|item| hash_to_range(&key, n * m, item))
+            .collect();
+
+        hashes.sort_unstable();
+
+        let mut bytes: Vec<u8> = Vec::new();
+
+        write_compact_size(&mut bytes, n);
+
+        let offset: usize = bytes.len();
+        let mut writer: BitWriter = BitWriter { bytes, bits: 0 };
+        let mut last: u64 = 0;
+
+        for hash in hashes.iter() {
+            writer.write_golomb_rice(hash - last, p);
+            last = *hash;
+        }
+
+        Self {
+            key,
+            p,
+            m,
+            n,
+            bytes: writer.bytes,
+            offset,
+        }
+    }
+
+    /// Parses the serialized basic filter of the block.
+    pub fn from_bytes(block_hash: &[u8; 32], bytes: &[u8]) -> Result<Self, LoadBloomFilterError> {
+        Self::from_bytes_with_params(block_hash, BIP158_P, BIP158_M, bytes)
+    }
+
+    /// Parses the serialized filter with the given parameters, validating that it holds exactly N coded items.
+    pub fn from_bytes_with_params(
+        block_hash: &[u8; 32],
+        p: u8,
+        m: u64,
+        bytes: &[u8],
+    ) -> Result<Self, LoadBloomFilterError> {
+        let (n, offset): (u64, usize) = read_compact_size(bytes).ok_or_else(|| {
+            LoadBloomFilterError::Format("The GCS filter is truncated.".to_owned())
+        })?;
+
+        if p > 32 || n >= 1 << 32 || n.checked_mul(m).is_none() {
+            return Err(LoadBloomFilterError::Format(format!(
+                "The GCS filter of {} items with P = {} and M = {} is not supported.",
+                n, p, m
+            )));
+        }
+
+        let filter: Self = Self {
+            key: block_hash[..16].try_into().unwrap(),
+            p,
+            m,
+            n,
+            bytes: bytes.to_vec(),
+            offset,
+        };
+
+        let mut values: GolombRiceReader = filter.values();
+
+        for _ in 0..n {
+            values.next().ok_or_else(|| {
+                LoadBloomFilterError::Format(format!(
+                    "The GCS filter holds less than the {} items it declares.",
+                    n
+                ))
+            })?;
+        }
+
+        if values.reader.position.div_ceil(8) != bytes.len() - offset {
+            return Err(LoadBloomFilterError::Format(
+                "The GCS filter has excess data after its items.".to_owned(),
+            ));
+        }
+
+        Ok(filter)
+    }
+
+    /// Returns the serialized filter, as it is sent in the `cfilter` message.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bytes.clone()
+    }
+
+    /// Returns the number of items (N) the filter was built from.
+    pub fn len(&self) -> u64 {
+        self.n
+    }
+
+    /// Checks that the filter was built from no items.
+    pub fn is_empty(&self) -> bool {
+        self.n == 0
+    }
+
+    /// Given the negative or false positive answer about the item presence in the filter.
+    pub fn match_single(&self, item: &[u8]) -> bool {
+        self.match_any([item])
+    }
+
+    /// Checks that any of the items is probably in the filter, e.g. any script of the wallet.
+    /// The items are hashed, sorted and matched in a single pass over the filter.
+    pub fn match_any<I, T>(&self, items: I) -> bool
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        let range: u64 = self.n * self.m;
+        let mut queries: Vec<u64> = items
+            .into_iter()
+            .map(|item| hash_to_range(&self.key, range, item.as_ref()))
+            .collect();
+
+        queries.sort_unstable();
+
+        let mut queries = queries.into_iter().peekable();
+
+        for value in self.values().take(self.n as usize) {
+            while queries.next_if(|query| *query < value).is_some() {}
+
+            match queries.peek() {
+                Some(query) if *query == value => return true,
+                Some(_) => {}
+                None => return false,
+            }
+        }
+
+        false
+    }
+
+    /// Returns the double SHA-256 of the serialized filter in the internal byte order.
+    pub fn filter_hash(&self) -> [u8; 32] {
+        double_sha256(&[&self.bytes])
+    }
+
+    /// Returns the filter header chaining this filter to the header of the previous block's filter,
+    /// both in the internal byte order. The header of the genesis block follows the 32 zero bytes.
+    pub fn filter_header(&self, previous_header: &[u8; 32]) -> [u8; 32] {
+        double_sha256(&[&self.filter_hash(), previous_header])
+    }
+
+    fn values(&self) -> GolombRiceReader<'_> {
+        GolombRiceReader {
+            reader: BitReader {
+                bytes: &self.bytes[self.offset..],
+                position: 0,
+            },
+            p: self.p,
+            last: 0,
+        }
+    }
+}
+
+/// Maps the SipHash-2-4 of the item uniformly into `[0, range)` with the multiply-and-shift reduction.
+fn hash_to_range(key: &[u8; 16], range: u64, item: &[u8]) -> u64 {
+    let mut sip_hasher: SipHasher24 = SipHasher24::new_with_key(key);
+
+    sip_hasher.write(item);
+
+    ((sip_hasher.finish() as u128 * range as u128) >> 64) as u64
+}
+
+fn double_sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher: Sha256 = Sha256::new();
+
+    for part in parts {
+        hasher.update(part);
+    }
+
+    Sha256::digest(hasher.finalize()).into()
+}
+
+/// Writes the bits most significant first, the last byte is padded with zeros.
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        if self.bits == 0 {
+            self.bytes.push(0);
+        }
+
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 0x80 >> self.bits;
+        }
+
+        self.bits = (self.bits + 1) % 8;
+    }
+
+    fn write_golomb_rice(&mut self, value: u64, p: u8) {
+        for _ in 0..value >> p {
+            self.write_bit(true);
+        }
+
+        self.write_bit(false);
+
+        for i in (0..p).rev() {
+            self.write_bit(value >> i & 1 == 1);
+        }
+    }
+}
+
+/// Reads the bits most significant first.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read_bit(&mut self) -> Option<bool> {
+        let byte: u8 = *self.bytes.get(self.position / 8)?;
+        let bit: bool = byte & (0x80 >> (self.position % 8)) != 0;
+
+        self.position += 1;
+
+        Some(bit)
+    }
+}
+
+/// Decodes the Golomb-Rice coded deltas into the sorted hashes.
+struct GolombRiceReader<'a> {
+    reader: BitReader<'a>,
+    p: u8,
+    last: u64,
+}
+
+impl Iterator for GolombRiceReader<'_> {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let mut quotient: u64 = 0;
+
+        while self.reader.read_bit()? {
+            quotient += 1;
+        }
+
+        let mut remainder: u64 = 0;
+
+        for _ in 0..self.p {
+            remainder = remainder << 1 | self.reader.read_bit()? as u64;
+        }
+
+        let delta: u64 = quotient.checked_mul(1 << self.p)? | remainder;
+
+        self.last = self.last.checked_add(delta)?;
+
+        Some(self.last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GcsFilter;
+    use crate::LoadBloomFilterError;
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// Parses the hash shown in the reversed byte order.
+    fn unhex_hash(hex: &str) -> [u8; 32] {
+        let mut hash: [u8; 32] = unhex(hex).try_into().unwrap();
+
+        hash.reverse();
+        hash
+    }
+
+    /// A testnet block of the BIP158 test vectors as (height, block hash, scripts, previous header, filter, header),
+    /// the scripts are the output scripts and the scripts spent by the inputs the basic filter is built from.
+    type Vector = (
+        u32,
+        &'static str,
+        &'static [&'static str],
+        &'static str,
+        &'static str,
+        &'static str,
+    );
+
+    const VECTORS: [Vector; 10] = [
+        (
+            0,
+            "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943",
+            &["4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac"],
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            "019dfca8",
+            "21584579b7eb08997773e5aeff3a7f932700042d0ed2a6129012b7d7ae81b750",
+        ),
+        (
+            2,
+            "000000006c02c8ea6e4ff69651f7fcde348fb9d557a06e6957b65552002a7820",
+            &["21038a7f6ef1c8ca0c588aa53fa860128077c9e6c11e6830f4d7ee4e763a56b7718fac"],
+            "d7bdac13a59d745b1add0d2ce852f1a0442e8945fc1bf3848d3cbffd88c24fe1",
+            "0174a170",
+            "186afd11ef2b5e7e3504f2e8cbf8df28a1fd251fe53d60dff8b1467d1b386cf0",
+        ),
+        (
+            3,
+            "000000008b896e272758da5297bcd98fdc6d97c9b765ecec401e286dc1fdbe10",
+            &["2103f6d9ff4c12959445ca5549c811683bf9c88e637b222dd2e0311154c4c85cf423ac"],
+            "186afd11ef2b5e7e3504f2e8cbf8df28a1fd251fe53d60dff8b1467d1b386cf0",
+            "016cf7a0",
+            "8d63aadf5ab7257cb6d2316a57b16f517bff1c6388f124ec4c04af1212729d2a",
+        ),
+        (
+            15007,
+            "0000000038c44c703bae0f98cdd6bf30922326340a5996cc692aaae8bacf47ad",
+            &["2103f268e9ae07e0f8cb2f6e901d87c510d650b97230c0365b021df8f467363cafb1ac"],
+            "18b5c2b0146d2d09d24fb00ff5b52bd0742f36c9e65527abdb9de30c027a4748",
+            "013c3710",
+            "07384b01311867949e0c046607c66b7a766d338474bb67f66c8ae9dbd454b20e",
+        ),
+        (
+            49291,
+            "0000000018b07dca1b28b4b5a119f6d6e71698ce1ed96f143f54179ce177a19c",
+            &["2102971dd6034ed0cf52450b608d196c07d6345184fcb14deb277a6b82d526a6163dac", "76a91445db0b779c0b9fa207f12a8218c94fc77aff504588ac", "5221033423007d8f263819a2e42becaaf5b06f34cb09919e06304349d950668209eaed21021d69e2b68c3960903b702af7829fadcd80bd89b158150c85c4a75b2c8cb9c39452ae", "52210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f8179821021d69e2b68c3960903b702af7829fadcd80bd89b158150c85c4a75b2c8cb9c39452ae", "522102a7ae1e0971fc1689bd66d2a7296da3a1662fd21a53c9e38979e0f090a375c12d21022adb62335f41eb4e27056ac37d462cda5ad783fa8e0e526ed79c752475db285d52ae", "52210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f8179821022adb62335f41eb4e27056ac37d462cda5ad783fa8e0e526ed79c752475db285d52ae", "512103b9d1d0e2b4355ec3cdef7c11a5c0beff9e8b8d8372ab4b4e0aaf30e80173001951ae", "76a9149144761ebaccd5b4bbdc2a35453585b5637b2f8588ac", "522103f1848b40621c5d48471d9784c8174ca060555891ace6d2b03c58eece946b1a9121020ee5d32b54d429c152fdc7b1db84f2074b0564d35400d89d11870f9273ec140c52ae", "76a914f4fa1cc7de742d135ea82c17adf0bb9cf5f4fb8388ac"],
+            "ed47705334f4643892ca46396eb3f4196a5e30880589e4009ef38eae895d4a13",
+            "0afbc2920af1b027f31f87b592276eb4c32094bb4d3697021b4c6380",
+            "b6d98692cec5145f67585f3434ec3c2b3030182e1cb3ec58b855c5c164dfaaa3",
+        ),
+        (
+            180480,
+            "00000000fd3ceb2404ff07a785c7fdcc76619edc8ed61bd25134eaa22084366a",
+            &["2102e769e60137a4df6b0df8ebd387cca44c4c57ae74cc0114a8e8317c8f3bfd85e9ac", "2103bb52138972c48a132fc1f637858c5189607dd0f7fe40c4f20f6ad65f2d389ba4ac", "76a9147779b7fba1c1e06b717069b80ca170e8b04458a488ac", "76a9142a0307cd925dbb66b534c4db33003dd18c57015788ac", "76a914ae19d27efe12f5a886dc79af37ad6805db6f922d88ac", "76a9143b8d051d37a07ea1042067e93efe63dbf73920b988ac", "76a914797fb8777d7991d8284d88bfd421ce520f0f843188ac", "76a9146d10f3f592699265d10b106eda37c3ce793f7a8588ac", "76a9142903b138c24be9e070b3e73ec495d77a204615e788ac", "76a91433a1941fd9a37b9821d376f5a51bd4b52fa50e2888ac", "76a914e4374e8155d0865742ca12b8d4d14d41b57d682f88ac", "76a914001fa7459a6cfc64bdc178ba7e7a21603bb2568f88ac", "76a914f6039952bc2b307aeec5371bfb96b66078ec17f688ac"],
+            "d34ef98386f413769502808d4bac5f20f8dfd5bffc9eedafaa71de0eb1f01489",
+            "0db414c859a07e8205876354a210a75042d0463404913d61a8e068e58a3ae2aa080026",
+            "c582d51c0ca365e3fcf36c51cb646d7f83a67e867cb4743fd2128e3e022b700c",
+        ),
+        (
+            926485,
+            "000000000000015d6077a411a8f5cc95caf775ccf11c54e27df75ce58d187313",
+            &["76a914876fbb82ec05caa6af7a3b5e5a983aae6c6cc6d688ac", "52534b424c4f434b3acd16772ad61a3c5f00287480b720f6035d5e54c9efc71be94bb5e3727f109090", "76a9143ebc40e411ed3c76f86711507ab952300890397288ac", "76a91450333046115eaa0ac9e0216565f945070e44573988ac", "76a914c01a7ca16b47be50cbdbc60724f701d52d75156688ac", "a914b7e6f7ff8658b2d1fb107e3d7be7af4742e6b1b387", "76a914913bcc2be49cb534c20474c4dee1e9c4c317e7eb88ac", "a9148fc37ad460fdfbd2b44fe446f6e3071a4f64faa687", "a914feb8a29635c56d9cd913122f90678756bf23887687"],
+            "8f13b9a9c85611635b47906c3053ac53cfcec7211455d4cb0d63dc9acc13d472",
+            "09027acea61b6cc3fb33f5d52f7d088a6b2f75d234e89ca800",
+            "546c574a0472144bcaf9b6aeabf26372ad87c7af7d1ee0dbfae5e099abeae49c",
+        ),
+        (
+            987876,
+            "0000000000000c00901f2049055e2a437c819d79a3d54fd63e6af796cd7b8a79",
+            &["76a914c486de584a735ec2f22da7cd9681614681f92173d83d0aa68688ac"],
+            "fe4d230dbb0f4fec9bed23a5283e08baf996e3f32b93f52c7de1f641ddfd04ad",
+            "010c0b40",
+            "0965a544743bbfa36f254446e75630c09404b3d164a261892372977538928ed5",
+        ),
+        (
+            1263442,
+            "000000006f27ddfe1dd680044a34548f41bed47eba9e6f0b310da21423bc5f33",
+            &["76a914f2c25ac3d59f3d674b1d1d0a25c27339aaac0ba688ac", "001446c29eabe8208a33aa1023c741fa79aa92e881ff", "002027a5000c7917f785d8fc6e5a55adfca8717ecb973ebb7743849ff956d896a7ed"],
+            "31d66d516a9eda7de865df29f6ef6cb8e4bf9309e5dac899968a9a62a5df61e3",
+            "0385acb4f0fe889ef0",
+            "4e6d564c2a2452065c205dd7eb2791124e0c4e0dbb064c410c24968572589dec",
+        ),
+        (
+            1414221,
+            "0000000000000027b2b3b3381f114f674f481544ff2be37ae3788d7e078383b1",
+            &[],
+            "5e5e12d90693c8e936f01847859404c67482439681928353ca1296982042864e",
+            "00",
+            "021e8882ef5a0ed932edeebbecfeda1d7ce528ec7b3daa27641acf1189d7b5dc",
+        ),
+    ];
+
+    #[test]
+    fn test_gcs_bip158_vectors() {
+        for (height, block_hash, scripts, previous_header, filter, header) in VECTORS {
+            let block_hash: [u8; 32] = unhex_hash(block_hash);
+            let scripts: Vec<Vec<u8>> = scripts.iter().map(|script| unhex(script)).collect();
+            let built: GcsFilter = GcsFilter::new(&block_hash, &scripts);
+            let parsed: GcsFilter = GcsFilter::from_bytes(&block_hash, &unhex(filter)).unwrap();
+
+            assert_eq!(built.to_bytes(), unhex(filter), "{}", height);
+            assert_eq!(parsed, built);
+            assert_eq!(
+                parsed.filter_header(&unhex_hash(previous_header)),
+                unhex_hash(header)
+            );
+            assert!(scripts.iter().all(|script| parsed.match_single(script)));
+            assert_eq!(parsed.match_any(&scripts), !scripts.is_empty());
+            assert!(!parsed.match_single(b"Vinegar"));
+            assert!(!parsed.match_any(["Vinegar", "Coke"]));
+        }
+    }
+
+    #[test]
+    fn test_gcs_invalid() {
+        let block_hash: [u8; 32] = [7; 32];
+        let bytes: Vec<u8> =
+            GcsFilter::new(&block_hash, (0..100_u32).map(|i| i.to_le_bytes())).to_bytes();
+
+        assert_eq!(
+            GcsFilter::from_bytes(&block_hash, &bytes).unwrap().len(),
+            100
+        );
+
+        let mut excess: Vec<u8> = bytes.clone();
+
+        excess.push(0);
+
+        for bytes in [&bytes[..bytes.len() - 2], &excess, &[], &[0xff; 9]] {
+            assert!(matches!(
+                GcsFilter::from_bytes(&block_hash, bytes),
+                Err(LoadBloomFilterError::Format(_))
+            ));
+        }
+    }
+}
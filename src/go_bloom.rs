@@ -0,0 +1,337 @@
+//! Import and export of the bloom filters of the Go package `github.com/bits-and-blooms/bloom/v3`,
+//! as written by `BloomFilter.WriteTo`.
+//!
+//! The stream is big endian: the number of bits `m` and hash functions `k` as 64 bit integers, followed by
+//! the bitset as `BitSet.WriteTo` writes it, its length in bits and the 64 bit words.
+//! The bit `i` is the bit `i % 64` of the word `i / 64`.
+//!
+//! The bytes are hashed into four 64 bit values, the halves of the Murmur3 x64 128 bit hash (seed 0)
+//! of the bytes and of the bytes followed by a 1 byte. The hash function `i` sets the bit
+//! `(h[i % 2] + i * h[2 + ((i + i % 2) % 4) / 2]) % m`.
+
+use std::f64::consts::LN_2;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+use crate::hash::murmur3_128;
+use crate::{persist, LoadBloomFilterError, SaveBloomFilterError};
+
+/// A bloom filter readable and writable by the Go `BloomFilter.ReadFrom` and `BloomFilter.WriteTo`
+/// of `github.com/bits-and-blooms/bloom/v3`.
+///
+/// ```rust
+/// use bfilters::GoBloomFilter;
+///
+/// let mut bloom_filter: GoBloomFilter = GoBloomFilter::new(1000, 0.01).unwrap();
+///
+/// bloom_filter.insert("Vinegar");
+/// bloom_filter.insert(&[0x42, 0x07]);
+///
+/// let bytes: Vec<u8> = bloom_filter.to_bytes();
+/// let loaded_bloom_filter: GoBloomFilter = GoBloomFilter::from_bytes(&bytes).unwrap();
+///
+/// assert!(loaded_bloom_filter.is_probably_present("Vinegar"));
+/// assert!(loaded_bloom_filter.is_probably_present(&[0x42, 0x07]));
+/// assert!(!loaded_bloom_filter.is_probably_present("Coke"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GoBloomFilter {
+    number_of_bits: u64,
+    number_of_hashes: u64,
+    data: Vec<u64>,
+}
+
+impl GoBloomFilter {
+    /// Creates an empty bloom filter sized the same way as Go's `NewWithEstimates(n, fp)`.
+    pub fn new(expected_insertions: u64, false_positive_probability: f64) -> Result<Self, String> {
+        if false_positive_probability <= 0.0 || false_positive_probability >= 1.0 {
+            return Err(
+                "The bloom filter's false positive probability should be in range from 0 to 1."
+                    .to_owned(),
+            );
+        }
+
+        if expected_insertions == 0 {
+            return Err("The bloom filter's expected insertions should be positive.".to_owned());
+        }
+
+        let expected_insertions: f64 = expected_insertions as f64;
+        let number_of_bits: f64 =
+            (-expected_insertions * false_positive_probability.ln() / (LN_2 * LN_2)).ceil();
+        let number_of_hashes: f64 = (LN_2 * number_of_bits / expected_insertions).ceil();
+
+        Self::with_size(number_of_bits as u64, number_of_hashes as u64)
+    }
+
+    /// Creates an empty bloom filter with the given number of bits and hash functions, the same as Go's `New(m, k)`.
+    pub fn with_size(number_of_bits: u64, number_of_hashes: u64) -> Result<Self, String> {
+        if number_of_bits == 0 || number_of_hashes == 0 {
+            return Err(
+                "The bloom filter's number of bits and hash functions should be positive."
+                    .to_owned(),
+            );
+        }
+
+        Ok(Self {
+            number_of_bits,
+            number_of_hashes,
+            data: vec![0; number_of_bits.div_ceil(64) as usize],
+        })
+    }
+
+    /// Returns the number of bits, `Cap()` in Go.
+    pub fn number_of_bits(&self) -> u64 {
+        self.number_of_bits
+    }
+
+    /// Returns the number of hash functions, `K()` in Go.
+    pub fn number_of_hashes(&self) -> u64 {
+        self.number_of_hashes
+    }
+
+    /// Inserts the bytes, the same as Go's `Add` and `AddString`.
+    pub fn insert<T: AsRef<[u8]> + ?Sized>(&mut self, value: &T) {
+        for index in self.bit_indexes(value.as_ref()) {
+            self.data[(index / 64) as usize] |= 1 << (index % 64);
+        }
+    }
+
+    /// Given the negative or false positive answer about the bytes presence, the same as Go's `Test`.
+    pub fn is_probably_present<T: AsRef<[u8]> + ?Sized>(&self, value: &T) -> bool {
+        self.bit_indexes(value.as_ref())
+            .all(|index| self.data[(index / 64) as usize] & (1 << (index % 64)) != 0)
+    }
+
+    /// Merges the bloom filter with the same number of bits and hash functions into this one, the same as Go's `Merge`.
+    pub fn union(&mut self, other: &GoBloomFilter) -> Result<(), String> {
+        if self.number_of_bits != other.number_of_bits
+            || self.number_of_hashes != other.number_of_hashes
+        {
+            return Err(
+                "Only Go bloom filters with the same number of bits and hashes could be merged."
+                    .to_owned(),
+            );
+        }
+
+        for (word, other_word) in self.data.iter_mut().zip(other.data.iter()) {
+            *word |= other_word;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the bloom filter in the stream format of Go's `BloomFilter.WriteTo`.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.number_of_bits.to_be_bytes())?;
+        writer.write_all(&self.number_of_hashes.to_be_bytes())?;
+        writer.write_all(&self.number_of_bits.to_be_bytes())?;
+
+        for words in self.data.chunks(1024) {
+            let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_be_bytes()).collect();
+
+            writer.write_all(&bytes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the bloom filter written by Go's `BloomFilter.WriteTo`, the bytes after it are left in the reader.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, LoadBloomFilterError> {
+        let mut header: [u8; 24] = [0; 24];
+
+        reader.read_exact(&mut header)?;
+
+        let number_of_bits: u64 = u64::from_be_bytes(header[..8].try_into().unwrap());
+        let number_of_hashes: u64 = u64::from_be_bytes(header[8..16].try_into().unwrap());
+        let bitset_length: u64 = u64::from_be_bytes(header[16..].try_into().unwrap());
+
+        // The bitset of the filters created by the Go package has exactly m bits.
+        if number_of_bits == 0 || number_of_hashes == 0 || bitset_length != number_of_bits {
+            return Err(LoadBloomFilterError::Format(format!(
+                "The Go bloom filter with {} bits, {} hash functions and a bitset of {} bits is invalid.",
+                number_of_bits, number_of_hashes, bitset_length
+            )));
+        }
+
+        let number_of_words: u64 = number_of_bits.div_ceil(64);
+        let mut bytes: Vec<u8> = Vec::new();
+
+        // The size comes from the stream, so the buffer grows with the bytes actually read.
+        reader.take(number_of_words * 8).read_to_end(&mut bytes)?;
+
+        if (bytes.len() as u64) < number_of_words * 8 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        Ok(Self {
+            number_of_bits,
+            number_of_hashes,
+            data: bytes
+                .chunks_exact(8)
+                .map(|word| u64::from_be_bytes(word.try_into().unwrap()))
+                .collect(),
+        })
+    }
+
+    /// Serializes the bloom filter in the stream format of Go's `BloomFilter.WriteTo`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(24 + self.data.len() * 8);
+
+        self.write_to(&mut bytes)
+            .expect("Writing to a vector never fails");
+        bytes
+    }
+
+    /// Parses the bloom filter written by Go's `BloomFilter.WriteTo`, the bytes should hold nothing else.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, LoadBloomFilterError> {
+        let bloom_filter: Self = Self::read_from(&mut bytes).map_err(|err| match err {
+            LoadBloomFilterError::Io(_) => {
+                LoadBloomFilterError::Format("The Go bloom filter is truncated.".to_owned())
+            }
+            err => err,
+        })?;
+
+        if !bytes.is_empty() {
+            return Err(LoadBloomFilterError::Format(format!(
+                "The Go bloom filter is followed by {} unexpected bytes.",
+                bytes.len()
+            )));
+        }
+
+        Ok(bloom_filter)
+    }
+
+    /// Atomically saves the bloom filter into the file Go's `BloomFilter.ReadFrom` accepts.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SaveBloomFilterError> {
+        persist::write_atomic_with(path.as_ref(), |writer| Ok(self.write_to(writer)?))
+    }
+
+    /// Loads the bloom filter from the file written by Go's `BloomFilter.WriteTo`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, LoadBloomFilterError> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Returns the bit indexes of the bytes, the `location`s of the Go package.
+    fn bit_indexes(&self, bytes: &[u8]) -> impl Iterator<Item = u64> {
+        let (h0, h1): (u64, u64) = murmur3_128(bytes, 0);
+        let (h2, h3): (u64, u64) = murmur3_128(&[bytes, &[1]].concat(), 0);
+        let hashes: [u64; 4] = [h0, h1, h2, h3];
+        let number_of_bits: u64 = self.number_of_bits;
+
+        (0..self.number_of_hashes).map(move |i| {
+            let step: u64 = hashes[2 + ((i + i % 2) % 4 / 2) as usize];
+
+            hashes[(i % 2) as usize].wrapping_add(i.wrapping_mul(step)) % number_of_bits
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::GoBloomFilter;
+    use crate::LoadBloomFilterError;
+
+    /// The stream of `NewWithEstimates(100, 0.01)` with "John Green", "John White" and "Vinegar" added, in the layout
+    /// of `WriteTo`, as printed by fixtures/go_bloom/main.go.
+    ///
+    /// TODO: replace with the output of fixtures/go_bloom/main.go. These bytes still come from a transcription
+    /// of the bloom/v3 and bitset sources, the program has not been run against the Go package yet.
+    const FIXTURE: &str = "00000000000003bf000000000000000700000000000003bf000000000000000000000000000004020000000000000000000000001000000000500200000000000000000000010000001000800000000000000000000080004000000000002000000000000000000000400000000004000000000000000000000000004000800000000000400401000000000400010000";
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_go_bloom_fixture() {
+        let tmp_save_path: &Path = Path::new("./bfilter_go.bin");
+        let mut bloom_filter: GoBloomFilter = GoBloomFilter::new(100, 0.01).unwrap();
+
+        bloom_filter.insert("John Green");
+        bloom_filter.insert(&"John White".to_owned());
+        bloom_filter.insert("Vinegar".as_bytes());
+
+        assert_eq!(bloom_filter.number_of_bits(), 959);
+        assert_eq!(bloom_filter.number_of_hashes(), 7);
+        assert_eq!(bloom_filter.to_bytes(), unhex(FIXTURE));
+
+        fs::write(tmp_save_path, unhex(FIXTURE)).unwrap();
+
+        let loaded_bloom_filter: GoBloomFilter = GoBloomFilter::from_file(tmp_save_path).unwrap();
+
+        bloom_filter.save(tmp_save_path).unwrap();
+
+        let saved_bytes: Vec<u8> = fs::read(tmp_save_path).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert_eq!(loaded_bloom_filter, bloom_filter);
+        assert_eq!(saved_bytes, unhex(FIXTURE));
+        assert!(loaded_bloom_filter.is_probably_present("John Green"));
+        assert!(loaded_bloom_filter.is_probably_present("Vinegar"));
+        assert!(!loaded_bloom_filter.is_probably_present("Coke"));
+        assert!(!loaded_bloom_filter.is_probably_present("John Black"));
+    }
+
+    #[test]
+    fn test_go_bloom_false_positives() {
+        let mut bloom_filter: GoBloomFilter = GoBloomFilter::new(1000, 0.01).unwrap();
+
+        for i in 0..1000 {
+            bloom_filter.insert(&format!("item-{}", i));
+        }
+
+        // The transcription answers 90 false positives for the next 10000 items, see the TODO of FIXTURE.
+        let false_positives: usize = (1000..11000)
+            .filter(|i| bloom_filter.is_probably_present(&format!("item-{}", i)))
+            .count();
+
+        assert_eq!(bloom_filter.number_of_bits(), 9586);
+        assert_eq!(bloom_filter.number_of_hashes(), 7);
+        assert_eq!(false_positives, 90);
+    }
+
+    #[test]
+    fn test_go_bloom_invalid() {
+        let bytes: Vec<u8> = unhex(FIXTURE);
+        let mut trailing: Vec<u8> = bytes.clone();
+
+        trailing.push(0);
+
+        let mut other_length: Vec<u8> = bytes.clone();
+
+        other_length[23] = 0xc0;
+
+        for invalid in [
+            &bytes[..bytes.len() - 1],
+            &bytes[..20],
+            &trailing,
+            &other_length,
+            &unhex("000000000000000000000000000000070000000000000000"),
+            &unhex("000000000000004000000000000000000000000000000040"),
+        ] {
+            assert!(matches!(
+                GoBloomFilter::from_bytes(invalid),
+                Err(LoadBloomFilterError::Format(_))
+            ));
+        }
+
+        let mut bloom_filter: GoBloomFilter = GoBloomFilter::with_size(959, 7).unwrap();
+        let other: GoBloomFilter = GoBloomFilter::from_bytes(&bytes).unwrap();
+
+        assert!(GoBloomFilter::with_size(0, 7).is_err());
+        assert!(GoBloomFilter::new(0, 0.01).is_err());
+        assert!(bloom_filter.union(&other).is_ok());
+        assert!(bloom_filter.is_probably_present("John White"));
+        assert!(bloom_filter
+            .union(&GoBloomFilter::with_size(959, 6).unwrap())
+            .is_err());
+    }
+}
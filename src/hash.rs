@@ -0,0 +1,907 @@
+//! The hash functions the bloom filter derives its bit indexes from.
+//!
+//! By default both functions are implemented in pure Rust. The `fasthash` cargo feature
+//! switches them to the C implementations of the fasthash crate, the results are bit-identical.
+//!
+//! The bit index of an item is a stable contract: the same (item, seed, number_of_bits, key, salt)
+//! give the same index on every platform, endianness and version of the crate with the same HASH_VERSION.
+//! Any change of the index calculation requires a new HASH_VERSION, so the saved bloom filters
+//! built with the other scheme fail to load instead of giving false negatives.
+//!
+//! The hashing scheme version 1 is:
+//!
+//! - `data` is the item bytes, prefixed with the salt as 8 little endian bytes for salted bloom filters;
+//! - `(hash_1, hash_2)` is `(MurmurHash1_32(data, seed 0), CityHash64_v1.0(data))` for unkeyed bloom filters
+//!   and the `(h1, h2)` halves of `SipHash-1-3_128(key, data)` for keyed ones,
+//!   multi-byte words are always read as little endian;
+//! - `index = (hash_1 + seed * hash_2) mod number_of_bits` computed without overflow in 128 bits.
+
+use std::borrow::Cow;
+use std::hash::Hasher;
+
+use siphasher::sip128::{Hasher128, SipHasher13};
+
+use crate::HashKey;
+
+/// The version of the hashing scheme used to calculate the bit indexes,
+/// saved with every bloom filter.
+pub const HASH_VERSION: u32 = 1;
+
+/// Calculates the bit index of the item for the given seed (hash function number)
+/// following the hashing scheme HASH_VERSION.
+pub(crate) fn calc_bit_index(
+    item: &[u8],
+    seed: u32,
+    number_of_bits: u32,
+    key: Option<&HashKey>,
+    salt: Option<u64>,
+) -> u64 {
+    let salt_bytes: [u8; 8] = salt.unwrap_or_default().to_le_bytes();
+    let salt_bytes: &[u8] = if salt.is_some() { &salt_bytes } else { &[] };
+
+    let (hash_1, hash_2): (u64, u64) = match key {
+        Some(key) => {
+            let mut sip_hasher: SipHasher13 = SipHasher13::new_with_key(key);
+
+            sip_hasher.write(salt_bytes);
+            sip_hasher.write(item);
+
+            let sip_hash = sip_hasher.finish128();
+
+            (sip_hash.h1, sip_hash.h2)
+        }
+        None => {
+            let salted_item: Cow<[u8]> = if salt_bytes.is_empty() {
+                Cow::Borrowed(item)
+            } else {
+                Cow::Owned([salt_bytes, item].concat())
+            };
+
+            (
+                murmur_hash_32(&salted_item) as u64,
+                city_hash_64(&salted_item),
+            )
+        }
+    };
+
+    // Solution is based on answer:
+    // https://stackoverflow.com/questions/24676237/generating-random-hash-functions-for-lsh-minhash-algorithm#answer-24685697
+    let aka_random_hash: u128 = hash_1 as u128 + (seed as u128) * hash_2 as u128;
+
+    (aka_random_hash % number_of_bits as u128) as u64
+}
+
+/// Calculates the 32 bit MurmurHash1 (seed 0) of the given bytes,
+/// the same as fasthash::murmur::hash32 does.
+#[cfg(not(feature = "fasthash"))]
+pub(crate) fn murmur_hash_32(bytes: &[u8]) -> u32 {
+    murmur1_32(bytes, 0)
+}
+
+/// Calculates the 32 bit MurmurHash1 (seed 0) of the given bytes.
+#[cfg(feature = "fasthash")]
+pub(crate) fn murmur_hash_32(bytes: &[u8]) -> u32 {
+    fasthash::murmur::hash32(bytes)
+}
+
+/// Calculates the 64 bit CityHash64 (v1.0) of the given bytes,
+/// the same as fasthash::city::hash64 does.
+#[cfg(not(feature = "fasthash"))]
+pub(crate) fn city_hash_64(bytes: &[u8]) -> u64 {
+    city64(bytes)
+}
+
+/// Calculates the 64 bit CityHash64 (v1.0) of the given bytes.
+#[cfg(feature = "fasthash")]
+pub(crate) fn city_hash_64(bytes: &[u8]) -> u64 {
+    fasthash::city::hash64(bytes)
+}
+
+/// Reads 4 bytes at the given offset as a little endian integer.
+fn fetch_32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// Reads 8 bytes at the given offset as a little endian integer.
+fn fetch_64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// MurmurHash1 by Austin Appleby, ported from the SMHasher MurmurHash1.cpp.
+/// The blocks are always read as little endian, so the result does not depend on the platform.
+fn murmur1_32(bytes: &[u8], seed: u32) -> u32 {
+    const M: u32 = 0xc6a4a793;
+    const R: u32 = 16;
+
+    let mut h: u32 = seed ^ (bytes.len() as u32).wrapping_mul(M);
+
+    let mut blocks = bytes.chunks_exact(4);
+
+    for block in blocks.by_ref() {
+        h = h.wrapping_add(fetch_32(block, 0));
+        h = h.wrapping_mul(M);
+        h ^= h >> 16;
+    }
+
+    let tail: &[u8] = blocks.remainder();
+
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h = h.wrapping_add((*byte as u32) << (8 * i));
+        }
+
+        h = h.wrapping_mul(M);
+        h ^= h >> R;
+    }
+
+    h = h.wrapping_mul(M);
+    h ^= h >> 10;
+    h = h.wrapping_mul(M);
+    h ^= h >> 17;
+
+    h
+}
+
+/// MurmurHash64A by Austin Appleby, ported from the SMHasher MurmurHash2.cpp.
+/// The blocks are always read as little endian, the same as RedisBloom reads them on x86-64.
+pub(crate) fn murmur2_64a(bytes: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+
+    let mut h: u64 = seed ^ (bytes.len() as u64).wrapping_mul(M);
+
+    let mut blocks = bytes.chunks_exact(8);
+
+    for block in blocks.by_ref() {
+        let mut k: u64 = fetch_64(block, 0).wrapping_mul(M);
+
+        k ^= k >> R;
+        h ^= k.wrapping_mul(M);
+        h = h.wrapping_mul(M);
+    }
+
+    let tail: &[u8] = blocks.remainder();
+
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+
+    h
+}
+
+/// MurmurHash3 x86 32 bit by Austin Appleby, ported from the SMHasher MurmurHash3.cpp.
+/// The blocks are always read as little endian, the same as Bitcoin Core does for the BIP37 bloom filters.
+pub(crate) fn murmur3_32(bytes: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e2d51;
+    const C2: u32 = 0x1b873593;
+
+    let mix = |k: u32| k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+
+    let mut h: u32 = seed;
+
+    let mut blocks = bytes.chunks_exact(4);
+
+    for block in blocks.by_ref() {
+        h ^= mix(fetch_32(block, 0));
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe6546b64);
+    }
+
+    let tail: &[u8] = blocks.remainder();
+
+    if !tail.is_empty() {
+        let k: u32 = tail
+            .iter()
+            .enumerate()
+            .fold(0, |k, (i, byte)| k | (*byte as u32) << (8 * i));
+
+        h ^= mix(k);
+    }
+
+    h ^= bytes.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85ebca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2ae35);
+    h ^= h >> 16;
+
+    h
+}
+
+/// MurmurHash3 x64 128 bit by Austin Appleby, ported from the SMHasher MurmurHash3.cpp.
+/// Returns the (h1, h2) halves, the same as Guava's Hashing.murmur3_128(seed) reads them.
+pub(crate) fn murmur3_128(bytes: &[u8], seed: u32) -> (u64, u64) {
+    const C1: u64 = 0x87c37b91114253d5;
+    const C2: u64 = 0x4cf5ad432745937f;
+
+    let mix_k1 = |k: u64| k.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    let mix_k2 = |k: u64| k.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+
+    let mut h1: u64 = seed as u64;
+    let mut h2: u64 = seed as u64;
+
+    let mut blocks = bytes.chunks_exact(16);
+
+    for block in blocks.by_ref() {
+        h1 ^= mix_k1(fetch_64(block, 0));
+        h1 = h1
+            .rotate_left(27)
+            .wrapping_add(h2)
+            .wrapping_mul(5)
+            .wrapping_add(0x52dce729);
+        h2 ^= mix_k2(fetch_64(block, 8));
+        h2 = h2
+            .rotate_left(31)
+            .wrapping_add(h1)
+            .wrapping_mul(5)
+            .wrapping_add(0x38495ab5);
+    }
+
+    let tail: &[u8] = blocks.remainder();
+
+    if !tail.is_empty() {
+        let (k1, k2): (u64, u64) = tail.iter().enumerate().fold((0, 0), |(k1, k2), (i, byte)| {
+            if i < 8 {
+                (k1 | (*byte as u64) << (8 * i), k2)
+            } else {
+                (k1, k2 | (*byte as u64) << (8 * (i - 8)))
+            }
+        });
+
+        h1 ^= mix_k1(k1);
+        h2 ^= mix_k2(k2);
+    }
+
+    h1 ^= bytes.len() as u64;
+    h2 ^= bytes.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix_64(h1);
+    h2 = fmix_64(h2);
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+
+    (h1, h2)
+}
+
+fn fmix_64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51afd7ed558ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ceb9fe1a85ec53);
+    k ^= k >> 33;
+
+    k
+}
+
+// The secret and primes of the XXH3 preview (xxHash v0.7.2).
+const XXPH3_SECRET: [u8; 192] = [
+    0xb8, 0xfe, 0x6c, 0x39, 0x23, 0xa4, 0x4b, 0xbe, 0x7c, 0x01, 0x81, 0x2c, 0xf7, 0x21, 0xad, 0x1c,
+    0xde, 0xd4, 0x6d, 0xe9, 0x83, 0x90, 0x97, 0xdb, 0x72, 0x40, 0xa4, 0xa4, 0xb7, 0xb3, 0x67, 0x1f,
+    0xcb, 0x79, 0xe6, 0x4e, 0xcc, 0xc0, 0xe5, 0x78, 0x82, 0x5a, 0xd0, 0x7d, 0xcc, 0xff, 0x72, 0x21,
+    0xb8, 0x08, 0x46, 0x74, 0xf7, 0x43, 0x24, 0x8e, 0xe0, 0x35, 0x90, 0xe6, 0x81, 0x3a, 0x26, 0x4c,
+    0x3c, 0x28, 0x52, 0xbb, 0x91, 0xc3, 0x00, 0xcb, 0x88, 0xd0, 0x65, 0x8b, 0x1b, 0x53, 0x2e, 0xa3,
+    0x71, 0x64, 0x48, 0x97, 0xa2, 0x0d, 0xf9, 0x4e, 0x38, 0x19, 0xef, 0x46, 0xa9, 0xde, 0xac, 0xd8,
+    0xa8, 0xfa, 0x76, 0x3f, 0xe3, 0x9c, 0x34, 0x3f, 0xf9, 0xdc, 0xbb, 0xc7, 0xc7, 0x0b, 0x4f, 0x1d,
+    0x8a, 0x51, 0xe0, 0x4b, 0xcd, 0xb4, 0x59, 0x31, 0xc8, 0x9f, 0x7e, 0xc9, 0xd9, 0x78, 0x73, 0x64,
+    0xea, 0xc5, 0xac, 0x83, 0x34, 0xd3, 0xeb, 0xc3, 0xc5, 0x81, 0xa0, 0xff, 0xfa, 0x13, 0x63, 0xeb,
+    0x17, 0x0d, 0xdd, 0x51, 0xb7, 0xf0, 0xda, 0x49, 0xd3, 0x16, 0x55, 0x26, 0x29, 0xd4, 0x68, 0x9e,
+    0x2b, 0x16, 0xbe, 0x58, 0x7d, 0x47, 0xa1, 0xfc, 0x8f, 0xf8, 0xb8, 0xd1, 0x7a, 0xd0, 0x31, 0xce,
+    0x45, 0xcb, 0x3a, 0x8f, 0x95, 0x16, 0x04, 0x28, 0xaf, 0xd7, 0xfb, 0xca, 0xbb, 0x4b, 0x40, 0x7e,
+];
+const PRIME32_1: u64 = 0x9e3779b1;
+const PRIME32_2: u64 = 0x85ebca77;
+const PRIME32_3: u64 = 0xc2b2ae3d;
+const PRIME64_1: u64 = 0x9e3779b185ebca87;
+const PRIME64_2: u64 = 0xc2b2ae3d27d4eb4f;
+const PRIME64_3: u64 = 0x165667b19e3779f9;
+const PRIME64_4: u64 = 0x85ebca77c2b2ae63;
+const PRIME64_5: u64 = 0x27d4eb2f165667c5;
+
+/// The 64 bit XXH3 preview (seed 0) RocksDB keeps for its filters as `XXPH3_64bits`, ported from its util/xxph3.h.
+/// Unlike the final XXH3 it hashes the empty input to a non zero value.
+pub(crate) fn xxph3_64(bytes: &[u8]) -> u64 {
+    let secret: &[u8] = &XXPH3_SECRET;
+    let len: u64 = bytes.len() as u64;
+
+    match bytes.len() {
+        0 => mul128_fold_64(fetch_64(secret, 0), PRIME64_2),
+        1..=3 => {
+            let combined: u64 = bytes[0] as u64
+                | (bytes[bytes.len() >> 1] as u64) << 8
+                | (bytes[bytes.len() - 1] as u64) << 16
+                | len << 24;
+
+            xxph3_avalanche((combined ^ fetch_32(secret, 0) as u64).wrapping_mul(PRIME64_1))
+        }
+        4..=8 => {
+            let input: u64 =
+                fetch_32(bytes, 0) as u64 | (fetch_32(bytes, bytes.len() - 4) as u64) << 32;
+            let keyed: u64 = input ^ fetch_64(secret, 0);
+            let mix: u64 = len.wrapping_add((keyed ^ (keyed >> 51)).wrapping_mul(PRIME32_1));
+
+            xxph3_avalanche((mix ^ (mix >> 47)).wrapping_mul(PRIME64_2))
+        }
+        9..=16 => {
+            let low: u64 = fetch_64(bytes, 0) ^ fetch_64(secret, 0);
+            let high: u64 = fetch_64(bytes, bytes.len() - 8) ^ fetch_64(secret, 8);
+
+            xxph3_avalanche(
+                len.wrapping_add(low.wrapping_add(high))
+                    .wrapping_add(mul128_fold_64(low, high)),
+            )
+        }
+        17..=128 => {
+            let mut acc: u64 = len.wrapping_mul(PRIME64_1);
+            let rounds: usize = (bytes.len() - 1) / 32;
+
+            for i in (0..=rounds).rev() {
+                acc = acc
+                    .wrapping_add(mix_16(bytes, 16 * i, secret, 32 * i))
+                    .wrapping_add(mix_16(
+                        bytes,
+                        bytes.len() - 16 * (i + 1),
+                        secret,
+                        32 * i + 16,
+                    ));
+            }
+
+            xxph3_avalanche(acc)
+        }
+        129..=240 => {
+            let mut acc: u64 = len.wrapping_mul(PRIME64_1);
+
+            for i in 0..8 {
+                acc = acc.wrapping_add(mix_16(bytes, 16 * i, secret, 16 * i));
+            }
+
+            acc = xxph3_avalanche(acc);
+
+            for i in 8..bytes.len() / 16 {
+                acc = acc.wrapping_add(mix_16(bytes, 16 * i, secret, 16 * (i - 8) + 3));
+            }
+
+            xxph3_avalanche(acc.wrapping_add(mix_16(bytes, bytes.len() - 16, secret, 136 - 17)))
+        }
+        _ => {
+            const STRIPE_LEN: usize = 64;
+            const STRIPES_PER_BLOCK: usize = (XXPH3_SECRET.len() - STRIPE_LEN) / 8;
+
+            let mut acc: [u64; 8] = [
+                PRIME32_3, PRIME64_1, PRIME64_2, PRIME64_3, PRIME64_4, PRIME32_2, PRIME64_5,
+                PRIME32_1,
+            ];
+            let accumulate = |acc: &mut [u64; 8], stripe: &[u8], secret: &[u8]| {
+                for (i, lane) in acc.iter_mut().enumerate() {
+                    let value: u64 = fetch_64(stripe, 8 * i);
+                    let key: u64 = value ^ fetch_64(secret, 8 * i);
+
+                    *lane = lane
+                        .wrapping_add(value)
+                        .wrapping_add((key & 0xffffffff).wrapping_mul(key >> 32));
+                }
+            };
+            let block_len: usize = STRIPE_LEN * STRIPES_PER_BLOCK;
+            let number_of_blocks: usize = bytes.len() / block_len;
+
+            for block in bytes.chunks_exact(block_len) {
+                for (n, stripe) in block.chunks_exact(STRIPE_LEN).enumerate() {
+                    accumulate(&mut acc, stripe, &secret[8 * n..]);
+                }
+
+                for (i, lane) in acc.iter_mut().enumerate() {
+                    let key: u64 = fetch_64(secret, secret.len() - STRIPE_LEN + 8 * i);
+
+                    *lane = ((*lane ^ (*lane >> 47)) ^ key).wrapping_mul(PRIME32_1);
+                }
+            }
+
+            let last_block: &[u8] = &bytes[number_of_blocks * block_len..];
+
+            for (n, stripe) in last_block.chunks_exact(STRIPE_LEN).enumerate() {
+                accumulate(&mut acc, stripe, &secret[8 * n..]);
+            }
+
+            if !bytes.len().is_multiple_of(STRIPE_LEN) {
+                accumulate(
+                    &mut acc,
+                    &bytes[bytes.len() - STRIPE_LEN..],
+                    &secret[secret.len() - STRIPE_LEN - 7..],
+                );
+            }
+
+            let mut result: u64 = len.wrapping_mul(PRIME64_1);
+
+            for i in 0..4 {
+                result = result.wrapping_add(mul128_fold_64(
+                    acc[2 * i] ^ fetch_64(secret, 11 + 16 * i),
+                    acc[2 * i + 1] ^ fetch_64(secret, 11 + 16 * i + 8),
+                ));
+            }
+
+            xxph3_avalanche(result)
+        }
+    }
+}
+
+fn mul128_fold_64(lhs: u64, rhs: u64) -> u64 {
+    let product: u128 = lhs as u128 * rhs as u128;
+
+    product as u64 ^ (product >> 64) as u64
+}
+
+fn mix_16(bytes: &[u8], offset: usize, secret: &[u8], secret_offset: usize) -> u64 {
+    mul128_fold_64(
+        fetch_64(bytes, offset) ^ fetch_64(secret, secret_offset),
+        fetch_64(bytes, offset + 8) ^ fetch_64(secret, secret_offset + 8),
+    )
+}
+
+fn xxph3_avalanche(mut h: u64) -> u64 {
+    h ^= h >> 37;
+    h = h.wrapping_mul(PRIME64_3);
+    h ^= h >> 32;
+
+    h
+}
+
+// Some primes between 2^63 and 2^64 used by CityHash.
+const K0: u64 = 0xc3a5c85c97cb3127;
+const K1: u64 = 0xb492b66fbe98f273;
+const K2: u64 = 0x9ae16a3b2f90404f;
+const K3: u64 = 0xc949d7c7509e6557;
+
+fn shift_mix(value: u64) -> u64 {
+    value ^ (value >> 47)
+}
+
+fn hash_len_16(u: u64, v: u64) -> u64 {
+    const K_MUL: u64 = 0x9ddfea08eb382d69;
+
+    let mut a: u64 = (u ^ v).wrapping_mul(K_MUL);
+    a ^= a >> 47;
+
+    let mut b: u64 = (v ^ a).wrapping_mul(K_MUL);
+    b ^= b >> 47;
+
+    b.wrapping_mul(K_MUL)
+}
+
+fn hash_len_0_to_16(bytes: &[u8]) -> u64 {
+    let len: usize = bytes.len();
+
+    if len > 8 {
+        let a: u64 = fetch_64(bytes, 0);
+        let b: u64 = fetch_64(bytes, len - 8);
+
+        return hash_len_16(a, b.wrapping_add(len as u64).rotate_right(len as u32)) ^ b;
+    }
+
+    if len >= 4 {
+        let a: u64 = fetch_32(bytes, 0) as u64;
+
+        return hash_len_16(
+            (len as u64).wrapping_add(a << 3),
+            fetch_32(bytes, len - 4) as u64,
+        );
+    }
+
+    if len > 0 {
+        let a: u32 = bytes[0] as u32;
+        let b: u32 = bytes[len >> 1] as u32;
+        let c: u32 = bytes[len - 1] as u32;
+        let y: u32 = a.wrapping_add(b << 8);
+        let z: u32 = (len as u32).wrapping_add(c << 2);
+
+        return shift_mix((y as u64).wrapping_mul(K2) ^ (z as u64).wrapping_mul(K3))
+            .wrapping_mul(K2);
+    }
+
+    K2
+}
+
+fn hash_len_17_to_32(bytes: &[u8]) -> u64 {
+    let len: usize = bytes.len();
+
+    let a: u64 = fetch_64(bytes, 0).wrapping_mul(K1);
+    let b: u64 = fetch_64(bytes, 8);
+    let c: u64 = fetch_64(bytes, len - 8).wrapping_mul(K2);
+    let d: u64 = fetch_64(bytes, len - 16).wrapping_mul(K0);
+
+    hash_len_16(
+        a.wrapping_sub(b)
+            .rotate_right(43)
+            .wrapping_add(c.rotate_right(30))
+            .wrapping_add(d),
+        a.wrapping_add((b ^ K3).rotate_right(20))
+            .wrapping_sub(c)
+            .wrapping_add(len as u64),
+    )
+}
+
+fn weak_hash_len_32_with_seeds(bytes: &[u8], offset: usize, a: u64, b: u64) -> (u64, u64) {
+    let w: u64 = fetch_64(bytes, offset);
+    let x: u64 = fetch_64(bytes, offset + 8);
+    let y: u64 = fetch_64(bytes, offset + 16);
+    let z: u64 = fetch_64(bytes, offset + 24);
+
+    let mut a: u64 = a.wrapping_add(w);
+    let mut b: u64 = b.wrapping_add(a).wrapping_add(z).rotate_right(21);
+    let c: u64 = a;
+
+    a = a.wrapping_add(x);
+    a = a.wrapping_add(y);
+    b = b.wrapping_add(a.rotate_right(44));
+
+    (a.wrapping_add(z), b.wrapping_add(c))
+}
+
+fn hash_len_33_to_64(bytes: &[u8]) -> u64 {
+    let len: usize = bytes.len();
+
+    let mut z: u64 = fetch_64(bytes, 24);
+    let mut a: u64 = fetch_64(bytes, 0).wrapping_add(
+        (len as u64)
+            .wrapping_add(fetch_64(bytes, len - 16))
+            .wrapping_mul(K0),
+    );
+    let mut b: u64 = a.wrapping_add(z).rotate_right(52);
+    let mut c: u64 = a.rotate_right(37);
+
+    a = a.wrapping_add(fetch_64(bytes, 8));
+    c = c.wrapping_add(a.rotate_right(7));
+    a = a.wrapping_add(fetch_64(bytes, 16));
+
+    let vf: u64 = a.wrapping_add(z);
+    let vs: u64 = b.wrapping_add(a.rotate_right(31)).wrapping_add(c);
+
+    a = fetch_64(bytes, 16).wrapping_add(fetch_64(bytes, len - 32));
+    z = fetch_64(bytes, len - 8);
+    b = a.wrapping_add(z).rotate_right(52);
+    c = a.rotate_right(37);
+    a = a.wrapping_add(fetch_64(bytes, len - 24));
+    c = c.wrapping_add(a.rotate_right(7));
+    a = a.wrapping_add(fetch_64(bytes, len - 16));
+
+    let wf: u64 = a.wrapping_add(z);
+    let ws: u64 = b.wrapping_add(a.rotate_right(31)).wrapping_add(c);
+    let r: u64 = shift_mix(
+        vf.wrapping_add(ws)
+            .wrapping_mul(K2)
+            .wrapping_add(wf.wrapping_add(vs).wrapping_mul(K0)),
+    );
+
+    shift_mix(r.wrapping_mul(K0).wrapping_add(vs)).wrapping_mul(K2)
+}
+
+/// CityHash64 by Geoff Pike and Jyrki Alakuijala, ported from the SMHasher City.cpp.
+/// The words are always read as little endian, so the result does not depend on the platform.
+fn city64(bytes: &[u8]) -> u64 {
+    let len: usize = bytes.len();
+
+    if len <= 16 {
+        return hash_len_0_to_16(bytes);
+    }
+
+    if len <= 32 {
+        return hash_len_17_to_32(bytes);
+    }
+
+    if len <= 64 {
+        return hash_len_33_to_64(bytes);
+    }
+
+    // For strings over 64 bytes we hash the end first, and then as we
+    // loop we keep 56 bytes of state: v, w, x, y, and z.
+    let mut x: u64 = fetch_64(bytes, len - 40);
+    let mut y: u64 = fetch_64(bytes, len - 16).wrapping_add(fetch_64(bytes, len - 56));
+    let mut z: u64 = hash_len_16(
+        fetch_64(bytes, len - 48).wrapping_add(len as u64),
+        fetch_64(bytes, len - 24),
+    );
+    let mut v: (u64, u64) = weak_hash_len_32_with_seeds(bytes, len - 64, len as u64, z);
+    let mut w: (u64, u64) = weak_hash_len_32_with_seeds(bytes, len - 32, y.wrapping_add(K1), x);
+
+    x = x.wrapping_mul(K1).wrapping_add(fetch_64(bytes, 0));
+
+    // Operate on 64-byte chunks up to the nearest multiple of 64 below len.
+    let mut offset: usize = 0;
+    let mut remaining: usize = (len - 1) & !63;
+
+    while remaining != 0 {
+        x = x
+            .wrapping_add(y)
+            .wrapping_add(v.0)
+            .wrapping_add(fetch_64(bytes, offset + 8))
+            .rotate_right(37)
+            .wrapping_mul(K1);
+        y = y
+            .wrapping_add(v.1)
+            .wrapping_add(fetch_64(bytes, offset + 48))
+            .rotate_right(42)
+            .wrapping_mul(K1);
+        x ^= w.1;
+        y = y
+            .wrapping_add(v.0)
+            .wrapping_add(fetch_64(bytes, offset + 40));
+        z = z.wrapping_add(w.0).rotate_right(33).wrapping_mul(K1);
+        v = weak_hash_len_32_with_seeds(bytes, offset, v.1.wrapping_mul(K1), x.wrapping_add(w.0));
+        w = weak_hash_len_32_with_seeds(
+            bytes,
+            offset + 32,
+            z.wrapping_add(w.1),
+            y.wrapping_add(fetch_64(bytes, offset + 16)),
+        );
+
+        std::mem::swap(&mut z, &mut x);
+
+        offset += 64;
+        remaining -= 64;
+    }
+
+    hash_len_16(
+        hash_len_16(v.0, w.0)
+            .wrapping_add(shift_mix(y).wrapping_mul(K1))
+            .wrapping_add(z),
+        hash_len_16(v.1, w.1).wrapping_add(x),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        calc_bit_index, city64, murmur1_32, murmur2_64a, murmur3_128, murmur3_32, xxph3_64,
+    };
+
+    /// Generates a deterministic pseudo random input of the given length.
+    fn input(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i as u64).wrapping_mul(0x9e3779b97f4a7c15).rotate_left(17) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_murmur1_32() {
+        assert_eq!(murmur1_32(b"hello", 0), 1773990585);
+        assert_eq!(murmur1_32(b"helloworld", 0), 567127608);
+        assert_eq!(murmur1_32(b"", 0), 0);
+        assert_eq!(murmur1_32(b"Hello test world!", 0), 4204627431);
+    }
+
+    #[test]
+    fn test_murmur2_64a() {
+        let seed: u64 = 0xc6a4a7935bd1e995;
+
+        assert_eq!(murmur2_64a(b"", seed), 0x1ab11ea5a7b2c56e);
+        assert_eq!(murmur2_64a(b"a", seed), 0x4292cee227b9150a);
+        assert_eq!(murmur2_64a(b"hello", seed), 0x5ba5b8a59803e699);
+        assert_eq!(murmur2_64a(b"Hello test world!", seed), 0xe0648736d1322997);
+        assert_eq!(murmur2_64a(b"12345678", seed), 0x700bea34339441c4);
+        assert_eq!(murmur2_64a(b"123456789abcdefg", seed), 0xcf6c870356ad81b8);
+        assert_eq!(murmur2_64a(b"hello", 123), 2597646618390559622);
+    }
+
+    /// The vectors of the Bitcoin Core hash_tests as (expected, seed, hex data).
+    #[test]
+    fn test_murmur3_32() {
+        let vectors: [(u32, u32, &str); 14] = [
+            (0x00000000, 0x00000000, ""),
+            (0x6a396f08, 0xfba4c795, ""),
+            (0x81f16f39, 0xffffffff, ""),
+            (0x514e28b7, 0x00000000, "00"),
+            (0xea3f0b17, 0xfba4c795, "00"),
+            (0xfd6cf10d, 0x00000000, "ff"),
+            (0x16c6b7ab, 0x00000000, "0011"),
+            (0x8eb51c3d, 0x00000000, "001122"),
+            (0xb4471bf8, 0x00000000, "00112233"),
+            (0xe2301fa8, 0x00000000, "0011223344"),
+            (0xfc2e4a15, 0x00000000, "001122334455"),
+            (0xb074502c, 0x00000000, "00112233445566"),
+            (0x8034d2a0, 0x00000000, "0011223344556677"),
+            (0xb4698def, 0x00000000, "001122334455667788"),
+        ];
+
+        for (expected, seed, hex) in vectors {
+            let bytes: Vec<u8> = (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect();
+
+            assert_eq!(murmur3_32(&bytes, seed), expected, "{}", hex);
+        }
+    }
+
+    /// The vectors of the Guava Murmur3_128HashFunctionTest as (seed, h1, h2, data).
+    #[test]
+    fn test_murmur3_128() {
+        let vectors: [(u32, u64, u64, &str); 8] = [
+            (0, 0x629942693e10f867, 0x92db0b82baeb5347, "hell"),
+            (1, 0xa78ddff5adae8d10, 0x128900ef20900135, "hello"),
+            (2, 0x8a486b23f422e826, 0xf962a2c58947765f, "hello "),
+            (3, 0x2ea59f466f6bed8c, 0xc610990acc428a17, "hello w"),
+            (4, 0x79f6305a386c572c, 0x46305aed3483b94e, "hello wo"),
+            (5, 0xc2219d213ec1f1b5, 0xa1d8e2e0a52785bd, "hello wor"),
+            (
+                0,
+                0xe34bbc7bbc071b6c,
+                0x7a433ca9c49a9347,
+                "The quick brown fox jumps over the lazy dog",
+            ),
+            (
+                0,
+                0x658ca970ff85269a,
+                0x43fee3eaa68e5c3e,
+                "The quick brown fox jumps over the lazy cog",
+            ),
+        ];
+
+        for (seed, h1, h2, data) in vectors {
+            assert_eq!(murmur3_128(data.as_bytes(), seed), (h1, h2), "{}", data);
+        }
+    }
+
+    /// The `XXPH3_64bits` of the RocksDB 10.4.2 util/xxph3.h over input(len), covering every length branch,
+    /// printed by fixtures/rocksdb/xxph3_vectors.cc.
+    #[test]
+    fn test_xxph3_64() {
+        let vectors: [(usize, u64); 16] = [
+            (0, 0x5342c3010fe1dd04),
+            (1, 0x7198d737cfe7f386),
+            (3, 0x5be3a2b0fbff937e),
+            (4, 0x0d17b69c65ad4d8f),
+            (8, 0x1b7e5f1c28462c5f),
+            (9, 0xcc8f081816e7a41d),
+            (16, 0x411e5d1a63629082),
+            (17, 0xde605efeaede61ab),
+            (100, 0x2c1a82b480a7e4eb),
+            (128, 0xe50ca669039073d4),
+            (129, 0x68227c44b1371d90),
+            (200, 0xd04d53bc962bec0c),
+            (240, 0x950603b6c805f601),
+            (241, 0x880ac0314737a37d),
+            (1024, 0x8e43dd28e826a860),
+            (1100, 0xb1014298b3461d47),
+        ];
+
+        for (len, hash) in vectors {
+            assert_eq!(xxph3_64(&input(len)), hash, "{}", len);
+        }
+
+        assert_eq!(xxph3_64(b"hello"), 0xa0bbc238c97657b5);
+        assert_eq!(xxph3_64(b"Hello test world!"), 0x1813155d5674e151);
+    }
+
+    #[test]
+    fn test_city64() {
+        assert_eq!(city64(b""), 11160318154034397263);
+        assert_eq!(city64(b"hello"), 2578220239953316063);
+        assert_eq!(city64(b"Hello test world!"), 6799593475904362535);
+        assert_eq!(
+            city64(b"Ethereum smart contract addresses"),
+            6262046164676883510
+        );
+        assert_eq!(city64(&[b'x'; 100]), 8303281212501037101);
+    }
+
+    #[cfg(feature = "fasthash")]
+    #[test]
+    fn test_pure_rust_hashes_match_fasthash() {
+        use fasthash::FastHash;
+
+        for len in 0..300 {
+            let bytes: Vec<u8> = input(len);
+
+            assert_eq!(murmur1_32(&bytes, 0), fasthash::murmur::hash32(&bytes));
+            assert_eq!(city64(&bytes), fasthash::city::hash64(&bytes));
+            assert_eq!(
+                murmur2_64a(&bytes, len as u64),
+                fasthash::murmur2::Hash64_x64::hash_with_seed(&bytes, len as u64)
+            );
+        }
+    }
+
+    /// The golden vectors of the hashing scheme version 1 as (item, seed, number_of_bits, index).
+    /// They should never change, a failure here means the saved bloom filters would give false negatives.
+    const GOLDEN_VECTORS: [(&str, u32, u32, u64); 7] = [
+        ("Vinegar", 0, 9_585, 3377),
+        ("Vinegar", 5, 9_585, 6612),
+        ("Coke", 3, 9_585, 5445),
+        ("Hello test world!", 2, 8_852_670, 3083041),
+        ("", 1, 1_000, 263),
+        (
+            "Ethereum smart contract addresses",
+            7,
+            191_701_168,
+            125131773,
+        ),
+        ("John Green", 0, 4_294_967_295, 1441935395),
+    ];
+
+    /// The golden vectors for the salt 42 as (item, seed, number_of_bits, index).
+    const SALTED_GOLDEN_VECTORS: [(&str, u32, u32, u64); 7] = [
+        ("Vinegar", 0, 9_585, 1934),
+        ("Vinegar", 5, 9_585, 6629),
+        ("Coke", 3, 9_585, 6279),
+        ("Hello test world!", 2, 8_852_670, 6922089),
+        ("", 1, 1_000, 139),
+        (
+            "Ethereum smart contract addresses",
+            7,
+            191_701_168,
+            45422537,
+        ),
+        ("John Green", 0, 4_294_967_295, 738054976),
+    ];
+
+    /// The golden vectors for the key "0123456789abcdef" as (item, seed, number_of_bits, index).
+    const KEYED_GOLDEN_VECTORS: [(&str, u32, u32, u64); 7] = [
+        ("Vinegar", 0, 9_585, 7998),
+        ("Vinegar", 5, 9_585, 1398),
+        ("Coke", 3, 9_585, 278),
+        ("Hello test world!", 2, 8_852_670, 698640),
+        ("", 1, 1_000, 335),
+        (
+            "Ethereum smart contract addresses",
+            7,
+            191_701_168,
+            99308149,
+        ),
+        ("John Green", 0, 4_294_967_295, 238585039),
+    ];
+
+    /// The golden vectors for the key "0123456789abcdef" and the salt 42 as (item, seed, number_of_bits, index).
+    const KEYED_SALTED_GOLDEN_VECTORS: [(&str, u32, u32, u64); 7] = [
+        ("Vinegar", 0, 9_585, 8233),
+        ("Vinegar", 5, 9_585, 1913),
+        ("Coke", 3, 9_585, 2591),
+        ("Hello test world!", 2, 8_852_670, 7149660),
+        ("", 1, 1_000, 60),
+        (
+            "Ethereum smart contract addresses",
+            7,
+            191_701_168,
+            108897222,
+        ),
+        ("John Green", 0, 4_294_967_295, 3837343161),
+    ];
+
+    const KEY: [u8; 16] = *b"0123456789abcdef";
+
+    #[test]
+    fn test_calc_bit_index_golden_vectors() {
+        for (item, seed, number_of_bits, index) in GOLDEN_VECTORS {
+            assert_eq!(
+                calc_bit_index(item.as_bytes(), seed, number_of_bits, None, None),
+                index
+            );
+        }
+
+        for (item, seed, number_of_bits, index) in SALTED_GOLDEN_VECTORS {
+            assert_eq!(
+                calc_bit_index(item.as_bytes(), seed, number_of_bits, None, Some(42)),
+                index
+            );
+        }
+
+        for (item, seed, number_of_bits, index) in KEYED_GOLDEN_VECTORS {
+            assert_eq!(
+                calc_bit_index(item.as_bytes(), seed, number_of_bits, Some(&KEY), None),
+                index
+            );
+        }
+
+        for (item, seed, number_of_bits, index) in KEYED_SALTED_GOLDEN_VECTORS {
+            assert_eq!(
+                calc_bit_index(item.as_bytes(), seed, number_of_bits, Some(&KEY), Some(42)),
+                index
+            );
+        }
+    }
+}
@@ -1,37 +1,93 @@
 #![allow(dead_code, unused_variables)]
 
-use std::fs::File;
-use std::hash::Hasher;
-use std::io::{self, Read, Write};
+#[cfg(feature = "tokio")]
+mod async_io;
+mod autosave;
+mod bip37;
+mod compress;
+mod format;
+mod gcs;
+mod go_bloom;
+mod guava;
+mod hash;
+mod logs_bloom;
+mod mmap;
+mod persist;
+mod redisbloom;
+mod rocksdb;
+mod sbbf;
+mod serialization;
+mod sign;
+mod stream;
+mod upgrade;
+mod view;
+mod wal;
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
 use std::path::Path;
 
-use fasthash::city::Hasher64 as CityHasher64;
-use fasthash::murmur::Hasher32 as MurmurHasher32;
-use fasthash::{CityHasher, FastHasher, MurmurHasher};
-
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 
 use bitarray_naive::BitArray;
 
 pub const DEFAULT_FALSE_POSITIVE_PROBABILITY: f32 = 0.4f32;
 
+/// The secret key used by keyed bloom filters to hash the items with SipHash-1-3.
+pub type HashKey = [u8; 16];
+
+/// The secret key used to sign the saved bloom filters with HMAC-SHA256.
+pub type SigningKey = [u8; 32];
+
+pub use autosave::{AutosaveBloomFilter, AutosaveOptions};
+pub use bip37::{
+    Bip37BloomFilter, BIP37_MAX_FILTER_SIZE, BIP37_MAX_HASH_FUNCS, BIP37_UPDATE_ALL,
+    BIP37_UPDATE_NONE, BIP37_UPDATE_P2PUBKEY_ONLY,
+};
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+pub use compress::Compression;
+pub use format::FileFormat;
+pub use gcs::{GcsFilter, BIP158_M, BIP158_P};
+pub use go_bloom::GoBloomFilter;
+pub use guava::{GuavaBloomFilter, GuavaFunnel, GuavaStrategy};
+pub use hash::HASH_VERSION;
+pub use logs_bloom::{LogsBloom, LOGS_BLOOM_SIZE};
+pub use mmap::{MmapBloomFilter, MmapBloomFilterMut};
+pub use persist::SaveStep;
+pub use redisbloom::{RedisBloomFilter, REDISBLOOM_DEFAULT_EXPANSION};
+pub use rocksdb::{RocksDbFilterBuilder, RocksDbFilterReader};
+pub use sbbf::{parquet_hash, ParquetValue, SplitBlockBloomFilter};
+pub use serialization::PendingBloomFilter;
+pub use upgrade::UpgradeBloomFilterError;
+pub use view::BloomFilterRef;
+pub use wal::PersistentBloomFilter;
+
+use format::{
+    Header, FLAG_KEYED, FLAG_SALTED, FLAG_SIGNED, HEADER_SIZE, SERDE_FORMAT_VERSION, SIGNATURE_SIZE,
+};
+use serialization::SavedPendingBloomFilter;
+
 /// The error that can be returned on bloom_filter.save either
 /// if something was wrong with the file or with parsing.
 #[derive(Debug)]
 pub enum SaveBloomFilterError {
     Io(io::Error),
     Serialize(serde_json::Error),
+    /// The atomic replacement of the file failed at the given step. The previous file is left intact,
+    /// except for SaveStep::SyncDir, where the new file is already in place but may not be durable.
+    Step(SaveStep, io::Error),
 }
 
 impl From<io::Error> for SaveBloomFilterError {
     fn from(err: io::Error) -> Self {
-        return SaveBloomFilterError::Io(err);
+        SaveBloomFilterError::Io(err)
     }
 }
 
 impl From<serde_json::Error> for SaveBloomFilterError {
     fn from(err: serde_json::Error) -> Self {
-        return SaveBloomFilterError::Serialize(err);
+        SaveBloomFilterError::Serialize(err)
     }
 }
 
@@ -41,17 +97,88 @@ impl From<serde_json::Error> for SaveBloomFilterError {
 pub enum LoadBloomFilterError {
     Io(io::Error),
     Serialize(serde_json::Error),
+    /// The bloom filter was saved as a keyed one without its key,
+    /// so the key should be passed to BloomFilter::from_file_with_key.
+    MissingKey,
+    /// The key was passed for the bloom filter that is not a keyed one.
+    UnexpectedKey,
+    /// The bloom filter was saved with a hashing scheme this version of the crate does not implement,
+    /// querying it would give false negatives.
+    UnsupportedHashVersion(u32),
+    /// The binary bloom filter is malformed or was written by a newer version of the crate.
+    Format(String),
+    /// The saved parameters or bits do not match the checksum they were saved with,
+    /// the file is corrupted and querying it could give false negatives.
+    ChecksumMismatch,
+    /// The bloom filter was saved without the checksum, which every file of the current formats carries,
+    /// so it was stripped from the file. Such a file is loaded only with the checksum verification disabled.
+    MissingChecksum,
+    /// The signing key was passed, but the bloom filter was saved without the signature.
+    MissingSignature,
+    /// The bloom filter was altered or signed with another signing key.
+    InvalidSignature,
 }
 
 impl From<io::Error> for LoadBloomFilterError {
     fn from(err: io::Error) -> Self {
-        return LoadBloomFilterError::Io(err);
+        LoadBloomFilterError::Io(err)
     }
 }
 
 impl From<serde_json::Error> for LoadBloomFilterError {
     fn from(err: serde_json::Error) -> Self {
-        return LoadBloomFilterError::Serialize(err);
+        LoadBloomFilterError::Serialize(err)
+    }
+}
+
+/// The options of loading a saved bloom filter.
+///
+/// ```rust
+/// use bfilters::LoadOptions;
+///
+/// let options: LoadOptions = LoadOptions::new().with_checksum_verification(false);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct LoadOptions {
+    pub(crate) key: Option<HashKey>,
+    pub(crate) verify_checksum: bool,
+    pub(crate) signing_key: Option<SigningKey>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            key: None,
+            verify_checksum: true,
+            signing_key: None,
+        }
+    }
+}
+
+impl LoadOptions {
+    /// Creates the default options: no key and the checksum verification.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the secret key the keyed bloom filter was created with.
+    pub fn with_key(mut self, key: HashKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// Enables or disables the checksum verification. Verifying reads all the bits of the bloom filter,
+    /// it could be disabled for trusted files on hot paths, e.g. to memory map a huge file instantly.
+    pub fn with_checksum_verification(mut self, verify_checksum: bool) -> Self {
+        self.verify_checksum = verify_checksum;
+        self
+    }
+
+    /// Requires the bloom filter to be signed with the given signing key,
+    /// the unsigned and altered bloom filters are rejected.
+    pub fn with_signing_key(mut self, signing_key: SigningKey) -> Self {
+        self.signing_key = Some(signing_key);
+        self
     }
 }
 
@@ -176,7 +303,9 @@ impl From<serde_json::Error> for LoadBloomFilterError {
 /// fs::remove_file(tmp_save_path).unwrap();
 /// assert!(!tmp_save_path.exists());
 /// ```
-#[derive(Serialize, Deserialize)]
+///
+/// The bloom filter implements Serialize and Deserialize compactly for any serde format,
+/// see the serialization module for the layout.
 pub struct BloomFilter {
     false_positive_probability: f32,
     number_of_bits: u32,
@@ -184,6 +313,28 @@ pub struct BloomFilter {
     number_of_hashes: u32,
     bit_array: BitArray,
     items_added: u32,
+    keyed: bool,
+    key: Option<HashKey>,
+    salt: Option<u64>,
+    hash_version: u32,
+    checksum: Option<u32>,
+    signature: Option<[u8; SIGNATURE_SIZE]>,
+    signing_key: Option<SigningKey>,
+    /// The version of the serde layout the bloom filter was deserialized from, 0 for the legacy files.
+    /// The bloom filter is always serialized with the current version.
+    format_version: u16,
+}
+
+/// Wraps the bloom filter to serialize it together with its checksum and optionally its secret key and signature.
+#[derive(Serialize)]
+struct SavedBloomFilter<'a> {
+    #[serde(flatten)]
+    bloom_filter: &'a BloomFilter,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<&'a HashKey>,
+    checksum: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<[u8; SIGNATURE_SIZE]>,
 }
 
 impl BloomFilter {
@@ -218,6 +369,14 @@ impl BloomFilter {
             number_of_hashes,
             bit_array: BitArray::new(number_of_bits as i64),
             items_added: 0,
+            keyed: false,
+            key: None,
+            salt: None,
+            hash_version: HASH_VERSION,
+            checksum: None,
+            signature: None,
+            signing_key: None,
+            format_version: SERDE_FORMAT_VERSION,
         })
     }
 
@@ -257,19 +416,377 @@ impl BloomFilter {
             number_of_hashes,
             bit_array: BitArray::new(number_of_bits as i64),
             items_added: 0,
+            keyed: false,
+            key: None,
+            salt: None,
+            hash_version: HASH_VERSION,
+            checksum: None,
+            signature: None,
+            signing_key: None,
+            format_version: SERDE_FORMAT_VERSION,
         })
     }
 
+    /// Makes the bloom filter hash the items with SipHash-1-3 keyed by the given secret key
+    /// instead of the fixed unkeyed hash functions. Without the key nobody can precompute
+    /// the items landing on the same bits, so the filter can not be polluted on purpose.
+    /// Fails if any item is inserted already, as the items would be hashed differently.
+    ///
+    /// ```rust
+    /// use bfilters::BloomFilter;
+    ///
+    /// let key: [u8; 16] = *b"0123456789abcdef";
+    ///
+    /// let mut bloom_filter = BloomFilter::new(Some(0.01), 1000).unwrap().with_key(key).unwrap();
+    ///
+    /// bloom_filter.insert("Vinegar");
+    ///
+    /// assert!(bloom_filter.is_probably_present("Vinegar"));
+    /// assert!(bloom_filter.with_key(*b"fedcba9876543210").is_err());
+    /// ```
+    pub fn with_key(mut self, key: HashKey) -> Result<Self, String> {
+        self.check_empty()?;
+        self.keyed = true;
+        self.key = Some(key);
+        Ok(self)
+    }
+
+    /// Returns true if the bloom filter hashes the items with a secret key.
+    pub fn is_keyed(&self) -> bool {
+        self.keyed
+    }
+
+    /// Returns true if the bloom filter was loaded with a signature or is signed on saving.
+    /// Rewriting a signed bloom filter without its signing key would drop the signature.
+    pub fn is_signed(&self) -> bool {
+        self.signature.is_some() || self.signing_key.is_some()
+    }
+
+    /// Makes bloom_filter.save(...), bloom_filter.save_binary(...) and bloom_filter.to_bytes() sign the bloom filter
+    /// with HMAC-SHA256, so the files altered by anyone without the signing key are rejected on loading
+    /// with LoadOptions::with_signing_key. The bloom filters loaded with the signing key keep signing.
+    ///
+    /// ```rust
+    /// use bfilters::{BloomFilter, LoadOptions};
+    ///
+    /// let signing_key: [u8; 32] = [7; 32];
+    ///
+    /// let bloom_filter = BloomFilter::new(Some(0.01), 1000).unwrap().with_signing_key(signing_key);
+    /// let bytes: Vec<u8> = bloom_filter.to_bytes();
+    ///
+    /// assert!(BloomFilter::from_bytes_with_options(&bytes, LoadOptions::new().with_signing_key(signing_key)).is_ok());
+    /// assert!(BloomFilter::from_bytes_with_options(&bytes, LoadOptions::new().with_signing_key([8; 32])).is_err());
+    /// ```
+    pub fn with_signing_key(mut self, signing_key: SigningKey) -> Self {
+        self.signing_key = Some(signing_key);
+        self
+    }
+
+    /// Makes the bloom filter mix the given salt into every item hash, so bloom filters
+    /// built from the same items with different salts do not share the bits set.
+    /// Unlike the key the salt is saved with the bloom filter.
+    /// Fails if any item is inserted already, as the items would be hashed differently.
+    pub fn with_salt(mut self, salt: u64) -> Result<Self, String> {
+        self.check_empty()?;
+        self.salt = Some(salt);
+        Ok(self)
+    }
+
+    /// Validates that no item is inserted yet, so the hashing could still be changed.
+    fn check_empty(&self) -> Result<(), String> {
+        if self.items_added != 0 {
+            return Err(
+                "The bloom filter's hashing could not be changed once items are inserted."
+                    .to_owned(),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Same as bloom_filter.with_salt(...) with a salt generated randomly per bloom filter.
+    ///
+    /// ```rust
+    /// use bfilters::BloomFilter;
+    ///
+    /// let mut bloom_filter = BloomFilter::new(Some(0.01), 1000).unwrap().with_random_salt().unwrap();
+    ///
+    /// bloom_filter.insert("Vinegar");
+    ///
+    /// assert!(bloom_filter.salt().is_some());
+    /// assert!(bloom_filter.is_probably_present("Vinegar"));
+    /// ```
+    pub fn with_random_salt(self) -> Result<Self, String> {
+        // The std random state is seeded from the OS randomness once per thread and
+        // incremented per instance, so its hasher output is a unique random salt.
+        let salt: u64 = RandomState::new().build_hasher().finish();
+
+        self.with_salt(salt)
+    }
+
+    /// Returns the salt mixed into the item hashes, if any.
+    pub fn salt(&self) -> Option<u64> {
+        self.salt
+    }
+
+    /// Returns the version of the hashing scheme the bloom filter calculates the bit indexes with.
+    pub fn hash_version(&self) -> u32 {
+        self.hash_version
+    }
+
+    /// Returns true if the bloom filter was loaded from an older JSON layout, e.g. the one written by the crate 0.2,
+    /// such files could be converted with BloomFilter::upgrade_file(...).
+    pub fn is_legacy(&self) -> bool {
+        self.format_version < SERDE_FORMAT_VERSION
+    }
+
     /// Tries to instantiate a new instance of the bloom filter from the given file.
+    /// Both the JSON and the binary formats are recognized.
+    /// Keyed bloom filters could be loaded only if they were saved with bloom_filter.save_with_key(...),
+    /// otherwise BloomFilter::from_file_with_key should be used.
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, LoadBloomFilterError> {
-        let mut _file = File::open(path)?;
-        let mut _buffer: String = String::new();
+        Self::from_file_with_options(path, LoadOptions::new())
+    }
+
+    /// Tries to instantiate a new instance of the keyed bloom filter from the given file
+    /// with the secret key the bloom filter was created with.
+    pub fn from_file_with_key<P: AsRef<Path>>(
+        path: P,
+        key: HashKey,
+    ) -> Result<Self, LoadBloomFilterError> {
+        Self::from_file_with_options(path, LoadOptions::new().with_key(key))
+    }
+
+    /// Tries to instantiate a new instance of the bloom filter from the given file with the given options.
+    /// The checksum the bloom filter was saved with is verified unless disabled in the options.
+    pub fn from_file_with_options<P: AsRef<Path>>(
+        path: P,
+        options: LoadOptions,
+    ) -> Result<Self, LoadBloomFilterError> {
+        Self::from_file_with_progress(path, options, |_, _| ())
+    }
+
+    /// Tries to instantiate a new instance of the bloom filter from the serialized one
+    /// either in the JSON or in the binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadBloomFilterError> {
+        Self::from_bytes_with_options(bytes, LoadOptions::new())
+    }
+
+    /// Tries to instantiate a new instance of the bloom filter from the serialized one with the given options.
+    pub fn from_bytes_with_options(
+        bytes: &[u8],
+        options: LoadOptions,
+    ) -> Result<Self, LoadBloomFilterError> {
+        Self::parse(bytes, &options)?.with_loaded_key(options.key)
+    }
+
+    /// Deserializes the bloom filter in any of the supported formats,
+    /// validates that its hashing scheme is supported and that it is not corrupted.
+    fn parse(bytes: &[u8], options: &LoadOptions) -> Result<Self, LoadBloomFilterError> {
+        let bloom_filter: Self = match FileFormat::detect(bytes) {
+            FileFormat::Json => serde_json::from_slice::<SavedPendingBloomFilter>(bytes)?
+                .0
+                .check_format_version()?,
+            FileFormat::Binary => {
+                let header: Header = Header::parse(bytes)?;
+                let stored: &[u8] = &bytes[HEADER_SIZE..HEADER_SIZE + header.stored_payload_len()];
+                let mut bloom_filter: Self = Self::from_header(&header, &[]);
+
+                compress::decompress(
+                    &header,
+                    stored,
+                    &mut bloom_filter.bit_array.bit_array[..header.payload_len()],
+                )?;
+
+                if header.is_signed() {
+                    bloom_filter.signature = bytes[HEADER_SIZE + stored.len()..header.total_len()]
+                        .try_into()
+                        .ok();
+                }
+
+                bloom_filter
+            }
+        };
+
+        bloom_filter.verify(options)
+    }
+
+    /// Validates that the serde layout of the deserialized bloom filter is not newer than this version of the crate.
+    fn check_format_version(self) -> Result<Self, LoadBloomFilterError> {
+        if self.format_version > SERDE_FORMAT_VERSION {
+            return Err(LoadBloomFilterError::Format(format!(
+                "The JSON bloom filter format version {} is not supported.",
+                self.format_version
+            )));
+        }
+
+        Ok(self)
+    }
+
+    /// Validates that the hashing scheme of the deserialized bloom filter is supported and that it is not corrupted,
+    /// then sets the signing key it was verified with.
+    fn verify(self, options: &LoadOptions) -> Result<Self, LoadBloomFilterError> {
+        if self.hash_version != HASH_VERSION {
+            return Err(LoadBloomFilterError::UnsupportedHashVersion(
+                self.hash_version,
+            ));
+        }
+
+        // The legacy JSON bloom filters could be saved before the checksum was introduced.
+        if options.verify_checksum && (self.format_version > 0 || self.checksum.is_some()) {
+            let header: Header = Header {
+                checksum: self.checksum,
+                ..self.header()
+            };
+
+            header.verify_checksum(self.payload())?;
+        }
+
+        if let Some(signing_key) = &options.signing_key {
+            sign::verify_signature(
+                signing_key,
+                &self.header(),
+                self.payload(),
+                self.signature.as_ref().map(|signature| &signature[..]),
+            )?;
+        }
+
+        Ok(Self {
+            signing_key: options.signing_key,
+            ..self
+        })
+    }
+
+    /// Instantiates the bloom filter from the binary header and the bit payload.
+    fn from_header(header: &Header, payload: &[u8]) -> Self {
+        let mut bit_array: BitArray = BitArray::new(header.number_of_bits as i64);
+
+        bit_array.bit_array[..payload.len()].copy_from_slice(payload);
+
+        Self {
+            false_positive_probability: header.false_positive_probability,
+            number_of_bits: header.number_of_bits,
+            items_count: header.items_count,
+            number_of_hashes: header.number_of_hashes,
+            bit_array,
+            items_added: header.items_added,
+            keyed: header.is_keyed(),
+            key: None,
+            salt: header.salt,
+            hash_version: header.hash_version,
+            checksum: header.checksum,
+            signature: None,
+            signing_key: None,
+            format_version: SERDE_FORMAT_VERSION,
+        }
+    }
+
+    /// Sets the key passed on loading, validating that it is passed only for the keyed bloom filters.
+    fn with_loaded_key(mut self, key: Option<HashKey>) -> Result<Self, LoadBloomFilterError> {
+        match key {
+            Some(key) if !self.keyed => Err(LoadBloomFilterError::UnexpectedKey),
+            Some(key) => {
+                self.key = Some(key);
+                Ok(self)
+            }
+            None if self.keyed && self.key.is_none() => Err(LoadBloomFilterError::MissingKey),
+            None => Ok(self),
+        }
+    }
+
+    /// Returns the binary header describing the bloom filter parameters.
+    fn header(&self) -> Header {
+        let mut flags: u16 = 0;
+
+        if self.keyed {
+            flags |= FLAG_KEYED;
+        }
+
+        if self.salt.is_some() {
+            flags |= FLAG_SALTED;
+        }
+
+        Header {
+            flags,
+            hash_version: self.hash_version,
+            false_positive_probability: self.false_positive_probability,
+            number_of_bits: self.number_of_bits,
+            items_count: self.items_count,
+            number_of_hashes: self.number_of_hashes,
+            items_added: self.items_added,
+            salt: self.salt,
+            checksum: None,
+            compression: None,
+        }
+    }
+
+    /// Returns the bytes of the bit array as stored in the binary payload.
+    fn payload(&self) -> &[u8] {
+        &self.bit_array.bit_array[..format::payload_len(self.number_of_bits)]
+    }
 
-        _file.read_to_string(&mut _buffer)?;
+    /// Serializes the bloom filter into the binary format, see the format module for the layout.
+    /// The secret key of keyed bloom filters is never written.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(self.binary_header().total_len());
 
-        let bloom_filter: Self = serde_json::from_str::<Self>(&_buffer)?;
+        // Writing to a vector never fails.
+        self.write_binary(&mut bytes).unwrap();
 
-        Ok(bloom_filter)
+        bytes
+    }
+
+    /// Returns the header written in front of the payload with the checksum and the signed flag set.
+    fn binary_header(&self) -> Header {
+        self.binary_header_with_checksum(self.header().calc_checksum(self.payload()))
+    }
+
+    /// Same as bloom_filter.binary_header() with the checksum calculated by the caller.
+    fn binary_header_with_checksum(&self, checksum: u32) -> Header {
+        let mut header: Header = self.header().with_checksum(checksum);
+
+        if self.signing_key.is_some() {
+            header.flags |= FLAG_SIGNED;
+        }
+
+        header
+    }
+
+    /// Calculates the HMAC-SHA256 tag of the bloom filter if it has the signing key.
+    fn calc_signature(&self) -> Option<[u8; SIGNATURE_SIZE]> {
+        self.signing_key
+            .map(|signing_key| sign::calc_signature(&signing_key, &self.header(), self.payload()))
+    }
+
+    /// Writes the bloom filter as JSON with its checksum, signature and optionally its secret key.
+    fn write_json<W: io::Write>(
+        &self,
+        writer: W,
+        key: Option<&HashKey>,
+    ) -> Result<(), serde_json::Error> {
+        serde_json::to_writer(
+            writer,
+            &SavedBloomFilter {
+                bloom_filter: self,
+                key,
+                checksum: self.header().calc_checksum(self.payload()),
+                signature: self.calc_signature(),
+            },
+        )
+    }
+
+    /// Serializes the bloom filter the same way bloom_filter.save(...) or bloom_filter.save_binary(...) do.
+    pub(crate) fn serialize(&self, format: FileFormat) -> Result<Vec<u8>, SaveBloomFilterError> {
+        match format {
+            FileFormat::Json => {
+                let mut bytes: Vec<u8> = Vec::new();
+
+                self.write_json(&mut bytes, None)?;
+
+                Ok(bytes)
+            }
+            FileFormat::Binary => Ok(self.to_bytes()),
+        }
     }
 
     /// Calculates the best number of bits for the bloom filter's bit array.
@@ -298,6 +815,30 @@ impl BloomFilter {
         -f32::log2(false_positive_probability) as i8
     }
 
+    /// Estimates the number of distinct items stored in the bloom filter from the share of bits set.
+    ///
+    /// The formula is:
+    ///
+    /// estimated_items_count = - number_of_bits * ln(1 - fill_ratio) / number_of_hashes
+    ///
+    /// For more information please use <https://en.wikipedia.org/wiki/Bloom_filter#Approximating_the_number_of_items_in_a_Bloom_filter>
+    pub fn calc_estimated_items_count(
+        number_of_bits: u32,
+        number_of_hashes: u32,
+        fill_ratio: f64,
+    ) -> f64 {
+        -(number_of_bits as f64) * (1.0 - fill_ratio).ln() / number_of_hashes as f64
+    }
+
+    /// Calculates the false positive probability of the bloom filter from the share of bits set.
+    ///
+    /// The formula is:
+    ///
+    /// false_positive_probability = fill_ratio ^ number_of_hashes
+    pub fn calc_false_positive_probability(number_of_hashes: u32, fill_ratio: f64) -> f64 {
+        fill_ratio.powi(number_of_hashes as i32)
+    }
+
     /// Calculates the index for the given single string item in the bit array.
     /// Uses a simplified formula to replace a necessity to pick a random function.
     /// The simplified formula to simulate picking of random hash function is:
@@ -306,19 +847,26 @@ impl BloomFilter {
     ///
     /// For more information please use <https://stackoverflow.com/questions/24676237/generating-random-hash-functions-for-lsh-minhash-algorithm#answer-24685697>
     /// Or the original paper: <https://www.eecs.harvard.edu/~michaelm/postscripts/rsa2008.pdf>
+    ///
+    /// Keyed bloom filters take both hash function return values from the 128 bit SipHash-1-3 of the item.
+    /// Salted bloom filters hash the salt (as 8 little endian bytes) followed by the item.
+    ///
+    /// The index is a stable contract described in the hash module and versioned with HASH_VERSION.
+    ///
+    /// Panics for the keyed bloom filter without its key, hashing without the key would give false negatives.
     pub fn _calc_random_bit_array_index(&mut self, item: &str, seed: u32) -> usize {
-        let mut murmur_hasher: MurmurHasher32 = MurmurHasher::new();
-        let mut city_hasher: CityHasher64 = CityHasher::new();
-
-        murmur_hasher.write(item.as_bytes());
-        city_hasher.write(item.as_bytes());
-
-        // Solution is based on answer:
-        // https://stackoverflow.com/questions/24676237/generating-random-hash-functions-for-lsh-minhash-algorithm#answer-24685697
-        let aka_random_hash: u128 =
-            murmur_hasher.finish() as u128 + (seed as u128) * city_hasher.finish() as u128;
+        assert!(
+            !self.keyed || self.key.is_some(),
+            "The keyed bloom filter has no key, it should be loaded with its key."
+        );
 
-        (aka_random_hash % self.number_of_bits as u128) as usize
+        hash::calc_bit_index(
+            item.as_bytes(),
+            seed,
+            self.number_of_bits,
+            self.key.as_ref(),
+            self.salt,
+        ) as usize
     }
 
     /// Saving a given item to the bloom filter.
@@ -353,26 +901,139 @@ impl BloomFilter {
         true
     }
 
+    /// Merges the other bloom filter into the current one, so the current bloom filter
+    /// answers "probably present" for every item inserted in either of them.
+    /// Both filters should share the same number of bits, number of hashes, key and salt.
+    pub fn union(&mut self, other: &BloomFilter) -> Result<(), String> {
+        if self.number_of_bits != other.number_of_bits
+            || self.number_of_hashes != other.number_of_hashes
+        {
+            return Err(
+                "The bloom filters should have the same number of bits and hashes to be merged."
+                    .to_owned(),
+            );
+        }
+
+        if self.keyed != other.keyed || self.key != other.key {
+            return Err("The bloom filters should have the same key to be merged.".to_owned());
+        }
+
+        if self.salt != other.salt {
+            return Err("The bloom filters should have the same salt to be merged.".to_owned());
+        }
+
+        for (byte, other_byte) in self
+            .bit_array
+            .bit_array
+            .iter_mut()
+            .zip(other.bit_array.bit_array.iter())
+        {
+            *byte |= *other_byte;
+        }
+
+        self.items_added = self.items_added.saturating_add(other.items_added);
+
+        Ok(())
+    }
+
+    /// Returns the false positive probability the bloom filter was configured with.
+    pub fn false_positive_probability(&self) -> f32 {
+        self.false_positive_probability
+    }
+
+    /// Returns the number of bits in the bloom filter's bit array.
+    pub fn number_of_bits(&self) -> u32 {
+        self.number_of_bits
+    }
+
+    /// Returns the number of items the bloom filter was sized for (also known as capacity).
+    pub fn items_count(&self) -> u32 {
+        self.items_count
+    }
+
+    /// Returns the number of hash functions used to store the single string item.
+    pub fn number_of_hashes(&self) -> u32 {
+        self.number_of_hashes
+    }
+
+    /// Returns the number of items successfully inserted into the bloom filter.
+    pub fn items_added(&self) -> u32 {
+        self.items_added
+    }
+
+    /// Returns the number of bits set to true in the bloom filter's bit array.
+    pub fn count_set_bits(&self) -> u64 {
+        self.bit_array
+            .bit_array
+            .iter()
+            .map(|byte| byte.count_ones() as u64)
+            .sum()
+    }
+
+    /// Returns the share of bits set to true in the bloom filter's bit array.
+    pub fn fill_ratio(&self) -> f64 {
+        self.count_set_bits() as f64 / self.number_of_bits as f64
+    }
+
+    /// Estimates the number of distinct items stored in the bloom filter from its fill ratio.
+    pub fn estimated_items_count(&self) -> f64 {
+        Self::calc_estimated_items_count(
+            self.number_of_bits,
+            self.number_of_hashes,
+            self.fill_ratio(),
+        )
+    }
+
+    /// Calculates the false positive probability for the current state of the bloom filter.
+    pub fn current_false_positive_probability(&self) -> f64 {
+        Self::calc_false_positive_probability(self.number_of_hashes, self.fill_ratio())
+    }
+
     /// With given path to a file saves a state of the current bloom filter in order
     /// to be able to deserialize it later.
-    /// Returns an empty std::io::Result as IoResult
+    /// The file is replaced atomically, a crash in the middle leaves the previously saved file intact.
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SaveBloomFilterError> {
-        let mut _file = File::create(path)?;
+        persist::write_atomic_with(path.as_ref(), |writer| Ok(self.write_json(writer, None)?))
+    }
+
+    /// With given path to a file saves a state of the current bloom filter in the binary format.
+    /// The binary file is much smaller than the JSON one and could be memory mapped with
+    /// MmapBloomFilter, BloomFilter::from_file loads it as well.
+    pub fn save_binary<P: AsRef<Path>>(&self, path: P) -> Result<(), SaveBloomFilterError> {
+        persist::write_atomic_with(path.as_ref(), |writer| Ok(self.write_binary(writer)?))
+    }
 
-        let _serialized_bfilter: String = serde_json::to_string(self)?;
-        _file.write_all(_serialized_bfilter.as_str().as_bytes())?;
+    /// Saves a state of the current keyed bloom filter together with its secret key,
+    /// so it could be loaded back with BloomFilter::from_file without passing the key.
+    /// Anyone who reads the file learns the key, for the unkeyed bloom filters it is the same as bloom_filter.save(...).
+    pub fn save_with_key<P: AsRef<Path>>(&self, path: P) -> Result<(), SaveBloomFilterError> {
+        let key: &HashKey = match &self.key {
+            Some(key) => key,
+            None => return self.save(path),
+        };
 
-        Ok(())
+        persist::write_atomic_with(path.as_ref(), |writer| {
+            Ok(self.write_json(writer, Some(key))?)
+        })
     }
 }
 
 #[cfg(test)]
+#[allow(
+    clippy::bool_assert_comparison,
+    clippy::inconsistent_digit_grouping,
+    clippy::redundant_pattern_matching,
+    clippy::unnecessary_cast
+)]
 mod tests {
     use std::{fs, path::Path};
 
-    use crate::SaveBloomFilterError;
+    use crate::{
+        FileFormat, HashKey, LoadBloomFilterError, LoadOptions, SaveBloomFilterError, SaveStep,
+        SigningKey,
+    };
 
-    use super::BloomFilter;
+    use super::{format, BitArray, BloomFilter, SavedBloomFilter};
 
     #[test]
     fn test_item_not_present() {
@@ -437,8 +1098,10 @@ mod tests {
 
         let io_error_received: bool = match bloom_filter.save(tmp_save_path_ser) {
             Ok(_) => false,
-            Err(SaveBloomFilterError::Io(err)) => true,
+            Err(SaveBloomFilterError::Io(err)) => false,
             Err(SaveBloomFilterError::Serialize(err)) => false,
+            Err(SaveBloomFilterError::Step(SaveStep::CreateTemp, err)) => true,
+            Err(SaveBloomFilterError::Step(step, err)) => false,
         };
 
         assert!(io_error_received);
@@ -654,4 +1317,409 @@ mod tests {
 
         assert_eq!(probably_present, false);
     }
+
+    #[test]
+    fn test_union() {
+        let item: &str = "John Green";
+        let other_item: &str = "John White";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+        let mut other_bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        bloom_filter.insert(item);
+        other_bloom_filter.insert(other_item);
+
+        bloom_filter.union(&other_bloom_filter).unwrap();
+
+        assert!(bloom_filter.is_probably_present(item));
+        assert!(bloom_filter.is_probably_present(other_item));
+        assert_eq!(bloom_filter.items_added(), 2);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "The bloom filters should have the same number of bits and hashes to be merged."
+    )]
+    fn test_union_incompatible() {
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+        let other_bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 2000).unwrap();
+
+        match bloom_filter.union(&other_bloom_filter) {
+            Ok(_) => (),
+            Err(msg) => panic!("{}", msg),
+        };
+    }
+
+    #[test]
+    fn test_stats() {
+        let items: [&str; 3] = ["John Green", "Steve Red", "Mark Adams"];
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        assert_eq!(bloom_filter.count_set_bits(), 0);
+        assert_eq!(bloom_filter.fill_ratio(), 0.0);
+        assert_eq!(bloom_filter.estimated_items_count(), 0.0);
+        assert_eq!(bloom_filter.current_false_positive_probability(), 0.0);
+
+        for item in items {
+            bloom_filter.insert(item);
+        }
+
+        assert!(bloom_filter.count_set_bits() > 0);
+        assert!(bloom_filter.count_set_bits() <= 3 * bloom_filter.number_of_hashes() as u64);
+        assert!((bloom_filter.estimated_items_count() - 3.0).abs() < 0.5);
+        assert!(bloom_filter.current_false_positive_probability() < 0.01);
+    }
+
+    #[test]
+    fn test_keyed_item_present() {
+        let item: &str = "John Green";
+        let wrong_item: &str = "John White";
+        let key: HashKey = *b"0123456789abcdef";
+        let other_key: HashKey = *b"fedcba9876543210";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_key(key)
+            .unwrap();
+        let mut other_bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_key(other_key)
+            .unwrap();
+
+        bloom_filter.insert(item);
+
+        assert!(bloom_filter.is_keyed());
+        assert!(bloom_filter.is_probably_present(item));
+        assert!(!bloom_filter.is_probably_present(wrong_item));
+
+        let indexes: Vec<usize> = (0..bloom_filter.number_of_hashes())
+            .map(|seed| bloom_filter._calc_random_bit_array_index(item, seed))
+            .collect();
+        let other_indexes: Vec<usize> = (0..other_bloom_filter.number_of_hashes())
+            .map(|seed| other_bloom_filter._calc_random_bit_array_index(item, seed))
+            .collect();
+
+        assert_ne!(indexes, other_indexes);
+        assert!(bloom_filter.union(&other_bloom_filter).is_err());
+    }
+
+    #[test]
+    fn test_hashing_changed_after_insert() {
+        let filled = || {
+            let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+            bloom_filter.insert("John Green");
+            bloom_filter
+        };
+
+        assert!(filled().with_key([0; 16]).is_err());
+        assert!(filled().with_salt(42).is_err());
+        assert!(filled().with_random_salt().is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "The keyed bloom filter has no key")]
+    fn test_keyed_without_key() {
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_key([0; 16])
+            .unwrap();
+
+        bloom_filter.key = None;
+        bloom_filter.is_probably_present("John Green");
+    }
+
+    #[test]
+    fn test_keyed_serialize_deserialize() {
+        let item: &str = "John Green";
+        let key: HashKey = *b"0123456789abcdef";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_key(key)
+            .unwrap();
+
+        bloom_filter.insert(item);
+
+        let tmp_save_path: &Path = std::path::Path::new("./bfilter_keyed.json");
+
+        bloom_filter.save(tmp_save_path).unwrap();
+
+        let saved: String = fs::read_to_string(tmp_save_path).unwrap();
+        let missing_key_received: bool = matches!(
+            BloomFilter::from_file(tmp_save_path),
+            Err(LoadBloomFilterError::MissingKey)
+        );
+        let mut loaded_bloom_filter: BloomFilter =
+            BloomFilter::from_file_with_key(tmp_save_path, key).unwrap();
+
+        bloom_filter.save_with_key(tmp_save_path).unwrap();
+
+        let mut loaded_with_key_bloom_filter: BloomFilter =
+            BloomFilter::from_file(tmp_save_path).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert!(!saved.contains("\"key\""));
+        assert!(missing_key_received);
+        assert!(loaded_bloom_filter.is_probably_present(item));
+        assert!(loaded_with_key_bloom_filter.is_probably_present(item));
+    }
+
+    #[test]
+    fn test_unkeyed_deserialize_with_key() {
+        let bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        let tmp_save_path: &Path = std::path::Path::new("./bfilter_unkeyed.json");
+
+        bloom_filter.save(tmp_save_path).unwrap();
+
+        let unexpected_key_received: bool = matches!(
+            BloomFilter::from_file_with_key(tmp_save_path, [0; 16]),
+            Err(LoadBloomFilterError::UnexpectedKey)
+        );
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert!(unexpected_key_received);
+    }
+
+    #[test]
+    fn test_salted_item_present() {
+        let item: &str = "John Green";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_salt(42)
+            .unwrap();
+        let mut other_bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_random_salt()
+            .unwrap();
+
+        bloom_filter.insert(item);
+        other_bloom_filter.insert(item);
+
+        assert!(bloom_filter.is_probably_present(item));
+        assert!(other_bloom_filter.is_probably_present(item));
+        assert_ne!(bloom_filter.salt(), other_bloom_filter.salt());
+        assert_ne!(
+            bloom_filter.bit_array.bit_array,
+            other_bloom_filter.bit_array.bit_array
+        );
+        assert!(bloom_filter.union(&other_bloom_filter).is_err());
+    }
+
+    #[test]
+    fn test_salted_serialize_deserialize() {
+        let item: &str = "John Green";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_random_salt()
+            .unwrap();
+
+        bloom_filter.insert(item);
+
+        let tmp_save_path: &Path = std::path::Path::new("./bfilter_salted.json");
+
+        bloom_filter.save(tmp_save_path).unwrap();
+
+        let mut loaded_bloom_filter: BloomFilter = BloomFilter::from_file(tmp_save_path).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert_eq!(loaded_bloom_filter.salt(), bloom_filter.salt());
+        assert!(loaded_bloom_filter.is_probably_present(item));
+        assert!(loaded_bloom_filter.union(&bloom_filter).is_ok());
+    }
+
+    #[test]
+    fn test_deserialize_unsupported_hash_version() {
+        let bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        let tmp_save_path: &Path = std::path::Path::new("./bfilter_hash_version.json");
+
+        let serialized: String = serde_json::to_string(&bloom_filter)
+            .unwrap()
+            .replace("\"hash_version\":1", "\"hash_version\":2");
+
+        fs::write(tmp_save_path, serialized).unwrap();
+
+        let unsupported_hash_version_received: bool = matches!(
+            BloomFilter::from_file(tmp_save_path),
+            Err(LoadBloomFilterError::UnsupportedHashVersion(2))
+        );
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert!(unsupported_hash_version_received);
+    }
+
+    #[test]
+    fn test_deserialize_without_hash_version() {
+        let item: &str = "John Green";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        bloom_filter.insert(item);
+
+        let tmp_save_path: &Path = std::path::Path::new("./bfilter_no_hash_version.json");
+
+        let serialized: String =
+            String::from_utf8(bloom_filter.serialize(FileFormat::Json).unwrap())
+                .unwrap()
+                .replace(",\"hash_version\":1", "");
+
+        fs::write(tmp_save_path, serialized).unwrap();
+
+        let mut loaded_bloom_filter: BloomFilter = BloomFilter::from_file(tmp_save_path).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert!(loaded_bloom_filter.is_probably_present(item));
+    }
+
+    #[test]
+    fn test_deserialize_checksum_mismatch() {
+        let item: &str = "John Green";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        bloom_filter.insert(item);
+
+        let tmp_save_path: &Path = std::path::Path::new("./bfilter_checksum.json");
+
+        bloom_filter.save(tmp_save_path).unwrap();
+
+        // Flip the items added counter, which is covered by the checksum as well as the bits.
+        let corrupted: String = fs::read_to_string(tmp_save_path)
+            .unwrap()
+            .replace("\"items_added\":1", "\"items_added\":2");
+
+        fs::write(tmp_save_path, corrupted).unwrap();
+
+        let checksum_mismatch_received: bool = matches!(
+            BloomFilter::from_file(tmp_save_path),
+            Err(LoadBloomFilterError::ChecksumMismatch)
+        );
+        let loaded_bloom_filter: BloomFilter = BloomFilter::from_file_with_options(
+            tmp_save_path,
+            LoadOptions::new().with_checksum_verification(false),
+        )
+        .unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert!(checksum_mismatch_received);
+        assert_eq!(loaded_bloom_filter.items_added(), 2);
+
+        // A flipped bit in the binary payload.
+        let mut bytes: Vec<u8> = bloom_filter.to_bytes();
+        let last: usize = bytes.len() - 1;
+
+        bytes[last] ^= 1;
+
+        assert!(matches!(
+            BloomFilter::from_bytes(&bytes),
+            Err(LoadBloomFilterError::ChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_deserialize_missing_checksum() {
+        let options: LoadOptions = LoadOptions::new().with_checksum_verification(false);
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        bloom_filter.insert("John Green");
+
+        // The checksum flag cleared in the binary header.
+        let mut bytes: Vec<u8> = bloom_filter.to_bytes();
+
+        bytes[format::FLAGS_OFFSET] &= !(format::FLAG_CHECKSUM as u8);
+
+        assert!(matches!(
+            BloomFilter::from_bytes(&bytes),
+            Err(LoadBloomFilterError::MissingChecksum)
+        ));
+        assert!(BloomFilter::from_bytes_with_options(&bytes, options).is_ok());
+
+        // The checksum field removed from the JSON.
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&bloom_filter.serialize(FileFormat::Json).unwrap()).unwrap();
+
+        json.as_object_mut().unwrap().remove("checksum");
+
+        let bytes: Vec<u8> = serde_json::to_vec(&json).unwrap();
+
+        assert!(matches!(
+            BloomFilter::from_bytes(&bytes),
+            Err(LoadBloomFilterError::MissingChecksum)
+        ));
+        assert!(BloomFilter::from_bytes_with_options(&bytes, options).is_ok());
+    }
+
+    #[test]
+    fn test_signed_serialize_deserialize() {
+        let item: &str = "John Green";
+        let signing_key: SigningKey = [7; 32];
+        let options: LoadOptions = LoadOptions::new().with_signing_key(signing_key);
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_signing_key(signing_key);
+
+        bloom_filter.insert(item);
+
+        let tmp_save_path: &Path = std::path::Path::new("./bfilter_signed.json");
+
+        bloom_filter.save(tmp_save_path).unwrap();
+
+        let mut loaded_bloom_filter: BloomFilter =
+            BloomFilter::from_file_with_options(tmp_save_path, options).unwrap();
+
+        // Clear the set bits and fix the checksum, as anyone without the signing key could do.
+        let mut tampered_bloom_filter: BloomFilter = BloomFilter::from_file(tmp_save_path).unwrap();
+
+        tampered_bloom_filter.bit_array =
+            BitArray::new(tampered_bloom_filter.number_of_bits as i64);
+        tampered_bloom_filter.signature = loaded_bloom_filter.calc_signature();
+        tampered_bloom_filter.signing_key = None;
+
+        let tampered: String = serde_json::to_string(&SavedBloomFilter {
+            bloom_filter: &tampered_bloom_filter,
+            key: None,
+            checksum: tampered_bloom_filter
+                .header()
+                .calc_checksum(tampered_bloom_filter.payload()),
+            signature: tampered_bloom_filter.signature,
+        })
+        .unwrap();
+
+        fs::write(tmp_save_path, tampered).unwrap();
+
+        let invalid_signature_received: bool = matches!(
+            BloomFilter::from_file_with_options(tmp_save_path, options),
+            Err(LoadBloomFilterError::InvalidSignature)
+        );
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert!(loaded_bloom_filter.is_probably_present(item));
+        assert!(invalid_signature_received);
+
+        // The binary format and the bloom filters loaded with the signing key keep signing.
+        let bytes: Vec<u8> = loaded_bloom_filter.to_bytes();
+
+        assert!(BloomFilter::from_bytes_with_options(&bytes, options).is_ok());
+        assert!(matches!(
+            BloomFilter::from_bytes_with_options(
+                &BloomFilter::new(Some(0.01), 1000).unwrap().to_bytes(),
+                options
+            ),
+            Err(LoadBloomFilterError::MissingSignature)
+        ));
+    }
 }
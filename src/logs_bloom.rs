@@ -0,0 +1,301 @@
+//! The `logsBloom` of the Ethereum block headers and transaction receipts.
+//!
+//! The bloom filter is 2048 bits stored in 256 bytes. The address of every log and each of its topics are inserted
+//! by hashing them with Keccak-256 and setting three bits: the lower 11 bits of the first, second and third
+//! big endian 16 bit words of the hash, counted from the least significant bit of the last byte.
+//! The bloom of a block is the union of the blooms of its receipts.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha3::{Digest, Keccak256};
+
+use crate::LoadBloomFilterError;
+
+/// The size of the logs bloom in bytes.
+pub const LOGS_BLOOM_SIZE: usize = 256;
+
+/// A 2048 bit Ethereum logs bloom with the standard bit layout.
+///
+/// ```rust
+/// use bfilters::LogsBloom;
+///
+/// let transfer: [u8; 32] = [
+///     0xdd, 0xf2, 0x52, 0xad, 0x1b, 0xe2, 0xc8, 0x9b, 0x69, 0xc2, 0xb0, 0x68, 0xfc, 0x37, 0x8d, 0xaa,
+///     0x95, 0x2b, 0xa7, 0xf1, 0x63, 0xc4, 0xa1, 0x16, 0x28, 0xf5, 0x5a, 0x4d, 0xf5, 0x23, 0xb3, 0xef,
+/// ];
+/// let mut logs_bloom: LogsBloom = LogsBloom::new();
+///
+/// logs_bloom.insert(&transfer);
+///
+/// let parsed_logs_bloom: LogsBloom = LogsBloom::from_hex(&logs_bloom.to_hex()).unwrap();
+///
+/// assert!(parsed_logs_bloom.is_probably_present(&transfer));
+/// assert!(!parsed_logs_bloom.is_probably_present(&[0; 20]));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LogsBloom([u8; LOGS_BLOOM_SIZE]);
+
+impl LogsBloom {
+    /// Creates an empty logs bloom.
+    pub fn new() -> Self {
+        Self([0; LOGS_BLOOM_SIZE])
+    }
+
+    /// Wraps the 256 bytes of the logs bloom.
+    pub fn from_bytes(bytes: [u8; LOGS_BLOOM_SIZE]) -> Self {
+        Self(bytes)
+    }
+
+    /// Returns the 256 bytes of the logs bloom.
+    pub fn as_bytes(&self) -> &[u8; LOGS_BLOOM_SIZE] {
+        &self.0
+    }
+
+    /// Parses the logs bloom from 512 hex digits, with or without the `0x` prefix,
+    /// as the `logsBloom` field is returned by the Ethereum JSON-RPC.
+    pub fn from_hex(hex: &str) -> Result<Self, LoadBloomFilterError> {
+        let digits: &[u8] = hex
+            .strip_prefix("0x")
+            .or_else(|| hex.strip_prefix("0X"))
+            .unwrap_or(hex)
+            .as_bytes();
+
+        if digits.len() != 2 * LOGS_BLOOM_SIZE {
+            return Err(LoadBloomFilterError::Format(format!(
+                "The logs bloom should have {} hex digits, found {}.",
+                2 * LOGS_BLOOM_SIZE,
+                digits.len()
+            )));
+        }
+
+        let mut bytes: [u8; LOGS_BLOOM_SIZE] = [0; LOGS_BLOOM_SIZE];
+
+        for (byte, pair) in bytes.iter_mut().zip(digits.chunks_exact(2)) {
+            *byte = (hex_digit(pair[0])? << 4) | hex_digit(pair[1])?;
+        }
+
+        Ok(Self(bytes))
+    }
+
+    /// Returns the logs bloom as the `0x` prefixed lowercase hex used by the Ethereum JSON-RPC.
+    pub fn to_hex(&self) -> String {
+        self.to_string()
+    }
+
+    /// Inserts the log address or topic.
+    pub fn insert(&mut self, data: &[u8]) {
+        for (index, mask) in Self::bits(data) {
+            self.0[index] |= mask;
+        }
+    }
+
+    /// Inserts the address and all the topics of the log.
+    pub fn insert_log<T: AsRef<[u8]>>(&mut self, address: &[u8], topics: &[T]) {
+        self.insert(address);
+
+        for topic in topics {
+            self.insert(topic.as_ref());
+        }
+    }
+
+    /// Given the negative or false positive answer about the presence of the log address or topic.
+    pub fn is_probably_present(&self, data: &[u8]) -> bool {
+        Self::bits(data)
+            .iter()
+            .all(|&(index, mask)| self.0[index] & mask != 0)
+    }
+
+    /// Checks that all the bits of the other logs bloom are set in this one, e.g. that a block could contain
+    /// a log matching all the items inserted into the other bloom.
+    pub fn contains_bloom(&self, other: &LogsBloom) -> bool {
+        self.0
+            .iter()
+            .zip(other.0.iter())
+            .all(|(byte, other_byte)| byte & other_byte == *other_byte)
+    }
+
+    /// Merges the other logs bloom into this one, as the bloom of a block is built from the blooms of its receipts.
+    pub fn union(&mut self, other: &LogsBloom) {
+        for (byte, other_byte) in self.0.iter_mut().zip(other.0.iter()) {
+            *byte |= other_byte;
+        }
+    }
+
+    /// Returns the number of bits set.
+    pub fn count_set_bits(&self) -> u32 {
+        self.0.iter().map(|byte| byte.count_ones()).sum()
+    }
+
+    /// Returns the byte index and the bit mask of the three bits of the data.
+    fn bits(data: &[u8]) -> [(usize, u8); 3] {
+        let hash = Keccak256::digest(data);
+        let mut bits: [(usize, u8); 3] = [(0, 0); 3];
+
+        for (i, bit) in bits.iter_mut().enumerate() {
+            let position: usize =
+                (usize::from(hash[2 * i]) << 8 | usize::from(hash[2 * i + 1])) & 2047;
+
+            *bit = (LOGS_BLOOM_SIZE - 1 - position / 8, 1 << (position % 8));
+        }
+
+        bits
+    }
+}
+
+impl Default for LogsBloom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for LogsBloom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("0x")?;
+
+        for byte in self.0.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for LogsBloom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LogsBloom({})", self)
+    }
+}
+
+impl FromStr for LogsBloom {
+    type Err = LoadBloomFilterError;
+
+    fn from_str(hex: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(hex)
+    }
+}
+
+/// Serialized as the `0x` prefixed hex string, the same as the `logsBloom` field of the JSON-RPC responses.
+impl Serialize for LogsBloom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for LogsBloom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct LogsBloomVisitor;
+
+        impl Visitor<'_> for LogsBloomVisitor {
+            type Value = LogsBloom;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("the 256 bytes logs bloom hex")
+            }
+
+            fn visit_str<E: de::Error>(self, hex: &str) -> Result<LogsBloom, E> {
+                LogsBloom::from_hex(hex).map_err(|err| E::custom(format!("{:?}", err)))
+            }
+        }
+
+        deserializer.deserialize_str(LogsBloomVisitor)
+    }
+}
+
+fn hex_digit(digit: u8) -> Result<u8, LoadBloomFilterError> {
+    match digit {
+        b'0'..=b'9' => Ok(digit - b'0'),
+        b'a'..=b'f' => Ok(digit - b'a' + 10),
+        b'A'..=b'F' => Ok(digit - b'A' + 10),
+        _ => Err(LoadBloomFilterError::Format(format!(
+            "The logs bloom has an invalid hex digit {:?}.",
+            digit as char
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sha3::{Digest, Keccak256};
+
+    use super::LogsBloom;
+    use crate::LoadBloomFilterError;
+
+    #[test]
+    fn test_logs_bloom_reference_vectors() {
+        let mut logs_bloom: LogsBloom = LogsBloom::new();
+
+        for item in ["testtest", "test", "hallo", "other"] {
+            logs_bloom.insert(item.as_bytes());
+        }
+
+        assert!(logs_bloom.is_probably_present(b"hallo"));
+        assert!(!logs_bloom.is_probably_present(b"tes"));
+        assert!(!logs_bloom.is_probably_present(b"lo"));
+
+        // The Keccak-256 of the bloom built by go-ethereum from the same items (TestBloomExtensively).
+        let mut logs_bloom: LogsBloom = LogsBloom::new();
+
+        for i in 0..100 {
+            logs_bloom.insert(format!("xxxxxxxxxx data {} yyyyyyyyyyyyyy", i).as_bytes());
+        }
+
+        let hash: String = Keccak256::digest(logs_bloom.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        assert_eq!(
+            hash,
+            "c8d3ca65cdb4874300a9e39475508f23ed6da09fdbc487f89a2dcf50b09eb263"
+        );
+
+        let hex: String = logs_bloom.to_hex();
+        let parsed: LogsBloom = hex.parse().unwrap();
+
+        assert_eq!(hex.len(), 514);
+        assert_eq!(parsed, logs_bloom);
+        assert_eq!(
+            LogsBloom::from_hex(&hex[2..].to_uppercase()).unwrap(),
+            logs_bloom
+        );
+        assert_eq!(
+            serde_json::from_str::<LogsBloom>(&serde_json::to_string(&logs_bloom).unwrap())
+                .unwrap(),
+            logs_bloom
+        );
+        assert!(matches!(
+            LogsBloom::from_hex(&hex[..hex.len() - 2]),
+            Err(LoadBloomFilterError::Format(_))
+        ));
+        assert!(matches!(
+            LogsBloom::from_hex(&format!("0xzz{}", &hex[4..])),
+            Err(LoadBloomFilterError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn test_logs_bloom_union() {
+        let address: [u8; 20] = [0xab; 20];
+        let topics: [[u8; 32]; 2] = [[1; 32], [2; 32]];
+
+        let mut receipt: LogsBloom = LogsBloom::new();
+        let mut other_receipt: LogsBloom = LogsBloom::new();
+        let mut block: LogsBloom = LogsBloom::new();
+
+        receipt.insert_log(&address, &topics);
+        other_receipt.insert(&[0xcd; 20]);
+
+        block.union(&receipt);
+        block.union(&other_receipt);
+
+        assert!(block.is_probably_present(&address));
+        assert!(block.is_probably_present(&topics[1]));
+        assert!(block.is_probably_present(&[0xcd; 20]));
+        assert!(block.contains_bloom(&receipt));
+        assert!(!receipt.contains_bloom(&block));
+        assert!(block.count_set_bits() <= 12);
+    }
+}
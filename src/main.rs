@@ -0,0 +1,861 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::process::ExitCode;
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+use bfilters::Compression;
+use bfilters::{BloomFilter, FileFormat, HashKey, LoadOptions, SigningKey};
+
+const USAGE: &str = "Usage:
+    bfilters create <filter> --items <count> [--fpp <probability>] [--bits <count>] [--hashes <count>] [--salt <number|random>] [--format <json|binary>] [<secrets>]
+    bfilters insert <filter> [<key>...] [--file <path>] [<secrets>]
+    bfilters query <filter> [<key>...] [--file <path>] [<secrets>]
+    bfilters stats <filter> [<secrets>]
+    bfilters merge <output> <filter>... [--format <json|binary>] [<secrets>]
+    bfilters upgrade <filter> [<output>] [--format <json|binary>] [<secrets>]
+
+The secrets are --key <32 hex digits>, the key of the keyed filters, and --signing-key <64 hex digits>,
+the key the signed filters are verified and signed with. The create command makes a keyed and/or signed filter with them.
+
+Keys are read one per line from --file or from the standard input when none are given.
+The filters are read in any format, insert keeps the format and the compression of the filter and merge
+writes the format of the first filter unless --format is given. The signed filters are rewritten only with --signing-key.
+The upgrade command rewrites a filter saved by an older version (in place if no output is given)
+in the current version of its format or of the --format one.
+The query command exits with 0 if all the keys are probably present, 1 if any key is absent
+and 2 on error.";
+
+/// The exit code returned by the query command if at least one key is absent.
+const EXIT_ABSENT: u8 = 1;
+
+/// The exit code returned on any usage, filter or io error.
+const EXIT_ERROR: u8 = 2;
+
+/// A parsed command line of the bfilters tool.
+#[derive(Debug, PartialEq)]
+enum Command {
+    Create {
+        path: String,
+        items_count: u32,
+        false_positive_probability: Option<f32>,
+        number_of_bits: Option<u32>,
+        number_of_hashes: Option<u32>,
+        salt: Option<Salt>,
+        format: FileFormat,
+        secrets: Secrets,
+    },
+    Insert {
+        path: String,
+        keys: KeysSource,
+        secrets: Secrets,
+    },
+    Query {
+        path: String,
+        keys: KeysSource,
+        secrets: Secrets,
+    },
+    Stats {
+        path: String,
+        secrets: Secrets,
+    },
+    Merge {
+        output: String,
+        inputs: Vec<String>,
+        format: Option<FileFormat>,
+        secrets: Secrets,
+    },
+    Upgrade {
+        path: String,
+        output: Option<String>,
+        format: Option<FileFormat>,
+        secrets: Secrets,
+    },
+}
+
+/// The secret keys of the keyed and the signed bloom filters given on the command line.
+#[derive(Debug, Default, PartialEq)]
+struct Secrets {
+    key: Option<HashKey>,
+    signing_key: Option<SigningKey>,
+}
+
+impl Secrets {
+    /// Parses the option if it is one of the secrets, returns false for any other argument.
+    fn parse_option<I: Iterator<Item = String>>(
+        &mut self,
+        arg: &str,
+        args: &mut I,
+    ) -> Result<bool, String> {
+        match arg {
+            "--key" => self.key = Some(parse_hex("--key", args.next())?),
+            "--signing-key" => self.signing_key = Some(parse_hex("--signing-key", args.next())?),
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    fn load_options(&self) -> LoadOptions {
+        let mut options: LoadOptions = LoadOptions::new();
+
+        if let Some(key) = self.key {
+            options = options.with_key(key);
+        }
+
+        if let Some(signing_key) = self.signing_key {
+            options = options.with_signing_key(signing_key);
+        }
+
+        options
+    }
+}
+
+/// The salt of the created bloom filter, either given explicitly or a random one.
+#[derive(Debug, PartialEq)]
+enum Salt {
+    Random,
+    Value(u64),
+}
+
+impl std::str::FromStr for Salt {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "random" => Ok(Salt::Random),
+            _ => value.parse::<u64>().map(Salt::Value),
+        }
+    }
+}
+
+/// Describes where the insert and query commands take their keys from.
+#[derive(Debug, PartialEq)]
+enum KeysSource {
+    Args(Vec<String>),
+    File(String),
+    Stdin,
+}
+
+impl KeysSource {
+    /// Reads all the keys one per line, the trailing line breaks are dropped.
+    fn read(&self) -> io::Result<Vec<String>> {
+        match self {
+            KeysSource::Args(keys) => Ok(keys.clone()),
+            KeysSource::File(path) => BufReader::new(File::open(path)?).lines().collect(),
+            KeysSource::Stdin => io::stdin().lock().lines().collect(),
+        }
+    }
+}
+
+/// Parses a value of the command line option or returns a readable error.
+fn parse_value<T: std::str::FromStr>(option: &str, value: Option<String>) -> Result<T, String> {
+    let value: String = value.ok_or(format!("The option {} requires a value.", option))?;
+
+    value
+        .parse::<T>()
+        .map_err(|_| format!("The option {} has an invalid value: {}.", option, value))
+}
+
+/// Parses the hex value of the secret key options.
+fn parse_hex<const N: usize>(option: &str, value: Option<String>) -> Result<[u8; N], String> {
+    let value: String = value.ok_or(format!("The option {} requires a value.", option))?;
+    let invalid = || format!("The option {} requires {} hex digits.", option, N * 2);
+
+    if value.len() != N * 2 || !value.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut bytes: [u8; N] = [0; N];
+
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+
+    Ok(bytes)
+}
+
+/// Parses the value of the --format option.
+fn parse_format(value: Option<String>) -> Result<FileFormat, String> {
+    match value.as_deref() {
+        Some("json") => Ok(FileFormat::Json),
+        Some("binary") => Ok(FileFormat::Binary),
+        Some(value) => Err(format!(
+            "The option --format has an invalid value: {}.",
+            value
+        )),
+        None => Err("The option --format requires a value.".to_owned()),
+    }
+}
+
+/// Parses the keys given either as positional arguments or with the --file option, and the secrets.
+fn parse_keys_source<I: Iterator<Item = String>>(
+    mut args: I,
+) -> Result<(KeysSource, Secrets), String> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut file: Option<String> = None;
+    let mut secrets: Secrets = Secrets::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--file" => file = Some(parse_value("--file", args.next())?),
+            "--" => keys.extend(args.by_ref()),
+            _ if secrets.parse_option(&arg, &mut args)? => {}
+            _ => keys.push(arg),
+        }
+    }
+
+    let keys: KeysSource = match (file, keys.is_empty()) {
+        (Some(_), false) => {
+            return Err("The keys could be given either as arguments or --file.".to_owned())
+        }
+        (Some(file), true) => KeysSource::File(file),
+        (None, false) => KeysSource::Args(keys),
+        (None, true) => KeysSource::Stdin,
+    };
+
+    Ok((keys, secrets))
+}
+
+/// Parses the command line arguments (without the program name) into a command.
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let command: String = args.next().ok_or("The command is missing.")?;
+
+    match command.as_str() {
+        "create" => {
+            let path: String = args.next().ok_or("The filter path is missing.")?;
+            let mut items_count: Option<u32> = None;
+            let mut false_positive_probability: Option<f32> = None;
+            let mut number_of_bits: Option<u32> = None;
+            let mut number_of_hashes: Option<u32> = None;
+            let mut salt: Option<Salt> = None;
+            let mut format: FileFormat = FileFormat::Json;
+            let mut secrets: Secrets = Secrets::default();
+
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--items" => items_count = Some(parse_value("--items", args.next())?),
+                    "--fpp" => {
+                        false_positive_probability = Some(parse_value("--fpp", args.next())?)
+                    }
+                    "--bits" => number_of_bits = Some(parse_value("--bits", args.next())?),
+                    "--hashes" => number_of_hashes = Some(parse_value("--hashes", args.next())?),
+                    "--salt" => salt = Some(parse_value("--salt", args.next())?),
+                    "--format" => format = parse_format(args.next())?,
+                    _ if secrets.parse_option(&arg, &mut args)? => {}
+                    _ => return Err(format!("Unknown argument: {}.", arg)),
+                }
+            }
+
+            Ok(Command::Create {
+                path,
+                items_count: items_count.ok_or("The option --items is required.")?,
+                false_positive_probability,
+                number_of_bits,
+                number_of_hashes,
+                salt,
+                format,
+                secrets,
+            })
+        }
+        "insert" => {
+            let path: String = args.next().ok_or("The filter path is missing.")?;
+            let (keys, secrets) = parse_keys_source(args)?;
+
+            Ok(Command::Insert {
+                path,
+                keys,
+                secrets,
+            })
+        }
+        "query" => {
+            let path: String = args.next().ok_or("The filter path is missing.")?;
+            let (keys, secrets) = parse_keys_source(args)?;
+
+            Ok(Command::Query {
+                path,
+                keys,
+                secrets,
+            })
+        }
+        "stats" => {
+            let path: String = args.next().ok_or("The filter path is missing.")?;
+            let mut secrets: Secrets = Secrets::default();
+
+            while let Some(arg) = args.next() {
+                if !secrets.parse_option(&arg, &mut args)? {
+                    return Err(format!("Unknown argument: {}.", arg));
+                }
+            }
+
+            Ok(Command::Stats { path, secrets })
+        }
+        "merge" => {
+            let output: String = args.next().ok_or("The output path is missing.")?;
+            let mut inputs: Vec<String> = Vec::new();
+            let mut format: Option<FileFormat> = None;
+            let mut secrets: Secrets = Secrets::default();
+
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--format" => format = Some(parse_format(args.next())?),
+                    _ if secrets.parse_option(&arg, &mut args)? => {}
+                    _ => inputs.push(arg),
+                }
+            }
+
+            if inputs.is_empty() {
+                return Err("At least one filter to merge is required.".to_owned());
+            }
+
+            Ok(Command::Merge {
+                output,
+                inputs,
+                format,
+                secrets,
+            })
+        }
+        "upgrade" => {
+            let path: String = args.next().ok_or("The filter path is missing.")?;
+            let mut output: Option<String> = None;
+            let mut format: Option<FileFormat> = None;
+            let mut secrets: Secrets = Secrets::default();
+
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--format" => format = Some(parse_format(args.next())?),
+                    _ if secrets.parse_option(&arg, &mut args)? => {}
+                    _ if output.is_none() => output = Some(arg),
+                    _ => return Err(format!("Unknown argument: {}.", arg)),
+                }
+            }
+
+            Ok(Command::Upgrade {
+                path,
+                output,
+                format,
+                secrets,
+            })
+        }
+        _ => Err(format!("Unknown command: {}.", command)),
+    }
+}
+
+/// The way the bloom filter file was written, kept when the file is rewritten.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Layout {
+    format: FileFormat,
+    #[cfg(any(feature = "zstd", feature = "lz4"))]
+    compression: Compression,
+}
+
+impl Layout {
+    fn new(format: FileFormat) -> Self {
+        Self {
+            format,
+            #[cfg(any(feature = "zstd", feature = "lz4"))]
+            compression: Compression::None,
+        }
+    }
+}
+
+/// Loads the bloom filter in any format with the secrets and returns it together with the layout it was saved in.
+/// The signed bloom filters are verified if the signing key is given.
+fn load(path: &str, secrets: &Secrets) -> Result<(BloomFilter, Layout), String> {
+    let bytes: Vec<u8> =
+        fs::read(path).map_err(|err| format!("Can not read the bloom filter {}: {}", path, err))?;
+    let bloom_filter: BloomFilter =
+        BloomFilter::from_bytes_with_options(&bytes, secrets.load_options())
+            .map_err(|err| format!("Can not load the bloom filter {}: {:?}", path, err))?;
+
+    let layout: Layout = Layout {
+        #[cfg(any(feature = "zstd", feature = "lz4"))]
+        compression: Compression::detect(&bytes),
+        ..Layout::new(FileFormat::detect(&bytes))
+    };
+
+    Ok((bloom_filter, layout))
+}
+
+/// Loads the bloom filter to be rewritten, the signed ones only with their signing key so the signature is kept.
+fn load_for_rewrite(path: &str, secrets: &Secrets) -> Result<(BloomFilter, Layout), String> {
+    let (bloom_filter, layout) = load(path, secrets)?;
+
+    if bloom_filter.is_signed() && secrets.signing_key.is_none() {
+        return Err(format!(
+            "The bloom filter {} is signed, it could be rewritten only with --signing-key.",
+            path
+        ));
+    }
+
+    Ok((bloom_filter, layout))
+}
+
+/// Detects the format of the bloom filter by the first bytes of the file.
+fn detect_format(path: &str) -> Result<FileFormat, String> {
+    let mut magic: Vec<u8> = Vec::new();
+
+    File::open(path)
+        .and_then(|file| file.take(4).read_to_end(&mut magic))
+        .map_err(|err| format!("Can not read the bloom filter {}: {}", path, err))?;
+
+    Ok(FileFormat::detect(&magic))
+}
+
+fn save(bloom_filter: &BloomFilter, path: &str, layout: Layout) -> Result<(), String> {
+    match layout.format {
+        FileFormat::Json => bloom_filter.save(path),
+        #[cfg(any(feature = "zstd", feature = "lz4"))]
+        FileFormat::Binary if layout.compression != Compression::None => {
+            bloom_filter.save_compressed(path, layout.compression)
+        }
+        FileFormat::Binary => bloom_filter.save_binary(path),
+    }
+    .map_err(|err| format!("Can not save the bloom filter {}: {:?}", path, err))
+}
+
+/// Runs the command and returns the exit code on success.
+fn run(command: Command) -> Result<u8, String> {
+    match command {
+        Command::Create {
+            path,
+            items_count,
+            false_positive_probability,
+            number_of_bits,
+            number_of_hashes,
+            salt,
+            format,
+            secrets,
+        } => {
+            let mut bloom_filter: BloomFilter = BloomFilter::custom(
+                items_count,
+                false_positive_probability,
+                number_of_bits,
+                number_of_hashes,
+            )?;
+
+            bloom_filter = match salt {
+                Some(Salt::Random) => bloom_filter.with_random_salt()?,
+                Some(Salt::Value(salt)) => bloom_filter.with_salt(salt)?,
+                None => bloom_filter,
+            };
+
+            if let Some(key) = secrets.key {
+                bloom_filter = bloom_filter.with_key(key)?;
+            }
+
+            if let Some(signing_key) = secrets.signing_key {
+                bloom_filter = bloom_filter.with_signing_key(signing_key);
+            }
+
+            save(&bloom_filter, &path, Layout::new(format))?;
+
+            Ok(0)
+        }
+        Command::Insert {
+            path,
+            keys,
+            secrets,
+        } => {
+            let (mut bloom_filter, layout): (BloomFilter, Layout) =
+                load_for_rewrite(&path, &secrets)?;
+            let keys: Vec<String> = keys.read().map_err(|err| err.to_string())?;
+            let mut rejected: usize = 0;
+
+            for key in keys.iter() {
+                if !bloom_filter.insert(key) {
+                    rejected += 1;
+                }
+            }
+
+            save(&bloom_filter, &path, layout)?;
+
+            if rejected > 0 {
+                return Err(format!(
+                    "The bloom filter is full, {} of {} keys were not inserted.",
+                    rejected,
+                    keys.len()
+                ));
+            }
+
+            Ok(0)
+        }
+        Command::Query {
+            path,
+            keys,
+            secrets,
+        } => {
+            let (mut bloom_filter, _): (BloomFilter, Layout) = load(&path, &secrets)?;
+            let keys: Vec<String> = keys.read().map_err(|err| err.to_string())?;
+            let mut exit_code: u8 = 0;
+
+            for key in keys.iter() {
+                if bloom_filter.is_probably_present(key) {
+                    println!("{}\tpresent", key);
+                } else {
+                    println!("{}\tabsent", key);
+                    exit_code = EXIT_ABSENT;
+                }
+            }
+
+            Ok(exit_code)
+        }
+        Command::Stats { path, secrets } => {
+            let (bloom_filter, layout): (BloomFilter, Layout) = load(&path, &secrets)?;
+
+            println!("format: {:?}", layout.format);
+            #[cfg(any(feature = "zstd", feature = "lz4"))]
+            println!("compression: {:?}", layout.compression);
+            println!("keyed: {}", bloom_filter.is_keyed());
+            println!("signed: {}", bloom_filter.is_signed());
+            println!(
+                "false_positive_probability: {}",
+                bloom_filter.false_positive_probability()
+            );
+            println!("number_of_bits: {}", bloom_filter.number_of_bits());
+            println!("number_of_hashes: {}", bloom_filter.number_of_hashes());
+            println!("hash_version: {}", bloom_filter.hash_version());
+            println!("items_count: {}", bloom_filter.items_count());
+            println!("items_added: {}", bloom_filter.items_added());
+            match bloom_filter.salt() {
+                Some(salt) => println!("salt: {}", salt),
+                None => println!("salt: none"),
+            }
+            println!("set_bits: {}", bloom_filter.count_set_bits());
+            println!("fill_ratio: {:.6}", bloom_filter.fill_ratio());
+            println!(
+                "estimated_items_count: {:.0}",
+                bloom_filter.estimated_items_count()
+            );
+            println!(
+                "current_false_positive_probability: {:.6}",
+                bloom_filter.current_false_positive_probability()
+            );
+
+            Ok(0)
+        }
+        Command::Merge {
+            output,
+            inputs,
+            format,
+            secrets,
+        } => {
+            let (mut merged, first_layout): (BloomFilter, Layout) =
+                load_for_rewrite(&inputs[0], &secrets)?;
+
+            for input in inputs.iter().skip(1) {
+                merged
+                    .union(&load_for_rewrite(input, &secrets)?.0)
+                    .map_err(|msg| format!("Can not merge {}: {}", input, msg))?;
+            }
+
+            let layout: Layout = match format {
+                Some(format) if format != first_layout.format => Layout::new(format),
+                _ => first_layout,
+            };
+
+            save(&merged, &output, layout)?;
+
+            Ok(0)
+        }
+        Command::Upgrade {
+            path,
+            output,
+            format,
+            secrets,
+        } => {
+            let format: FileFormat = match format {
+                Some(format) => format,
+                None => detect_format(&path)?,
+            };
+            let output: String = output.unwrap_or_else(|| path.clone());
+
+            load_for_rewrite(&path, &secrets)?;
+
+            let bloom_filter: BloomFilter =
+                BloomFilter::upgrade_file(&path, &output, format, secrets.load_options()).map_err(
+                    |err| format!("Can not upgrade the bloom filter {}: {:?}", path, err),
+                )?;
+
+            if bloom_filter.is_legacy() {
+                println!("{}: upgraded from the legacy format", path);
+            }
+
+            Ok(0)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let command: Command = match parse_args(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(msg) => {
+            eprintln!("{}\n\n{}", msg, USAGE);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+
+    match run(command) {
+        Ok(exit_code) => ExitCode::from(exit_code),
+        Err(msg) => {
+            eprintln!("{}", msg);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::{parse_args, run, Command, KeysSource, Salt, Secrets, EXIT_ABSENT};
+    use bfilters::{BloomFilter, FileFormat, LoadOptions};
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(|arg| arg.to_owned())
+    }
+
+    #[test]
+    fn test_parse_create() {
+        let command: Command = parse_args(args("create f.json --items 100 --fpp 0.01")).unwrap();
+
+        assert_eq!(
+            command,
+            Command::Create {
+                path: "f.json".to_owned(),
+                items_count: 100,
+                false_positive_probability: Some(0.01),
+                number_of_bits: None,
+                number_of_hashes: None,
+                salt: None,
+                format: FileFormat::Json,
+                secrets: Secrets::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_create_with_salt() {
+        let random: Command = parse_args(args("create f.json --items 1 --salt random")).unwrap();
+        let value: Command = parse_args(args("create f.json --items 1 --salt 42")).unwrap();
+
+        assert!(matches!(
+            random,
+            Command::Create {
+                salt: Some(Salt::Random),
+                ..
+            }
+        ));
+        assert!(matches!(
+            value,
+            Command::Create {
+                salt: Some(Salt::Value(42)),
+                ..
+            }
+        ));
+        assert!(parse_args(args("create f.json --items 1 --salt some")).is_err());
+    }
+
+    #[test]
+    fn test_parse_create_without_items() {
+        assert!(parse_args(args("create f.json --fpp 0.01")).is_err());
+    }
+
+    #[test]
+    fn test_parse_keys_source() {
+        let from_args: Command = parse_args(args("query f.json Coke Vinegar")).unwrap();
+        let from_file: Command = parse_args(args("insert f.json --file keys.txt")).unwrap();
+        let from_stdin: Command = parse_args(args("query f.json")).unwrap();
+
+        assert_eq!(
+            from_args,
+            Command::Query {
+                path: "f.json".to_owned(),
+                keys: KeysSource::Args(vec!["Coke".to_owned(), "Vinegar".to_owned()]),
+                secrets: Secrets::default(),
+            }
+        );
+        assert_eq!(
+            from_file,
+            Command::Insert {
+                path: "f.json".to_owned(),
+                keys: KeysSource::File("keys.txt".to_owned()),
+                secrets: Secrets::default(),
+            }
+        );
+        assert_eq!(
+            from_stdin,
+            Command::Query {
+                path: "f.json".to_owned(),
+                keys: KeysSource::Stdin,
+                secrets: Secrets::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_format() {
+        let create: Command = parse_args(args("create f.bin --items 1 --format binary")).unwrap();
+        let merge: Command =
+            parse_args(args("merge out.bin a.json --format binary b.json")).unwrap();
+
+        assert!(matches!(
+            create,
+            Command::Create {
+                format: FileFormat::Binary,
+                ..
+            }
+        ));
+        assert_eq!(
+            merge,
+            Command::Merge {
+                output: "out.bin".to_owned(),
+                inputs: vec!["a.json".to_owned(), "b.json".to_owned()],
+                format: Some(FileFormat::Binary),
+                secrets: Secrets::default(),
+            }
+        );
+        assert!(parse_args(args("create f.bin --items 1 --format xml")).is_err());
+    }
+
+    #[test]
+    fn test_parse_upgrade() {
+        assert_eq!(
+            parse_args(args("upgrade old.json")).unwrap(),
+            Command::Upgrade {
+                path: "old.json".to_owned(),
+                output: None,
+                format: None,
+                secrets: Secrets::default(),
+            }
+        );
+        assert_eq!(
+            parse_args(args("upgrade old.json new.bin --format binary")).unwrap(),
+            Command::Upgrade {
+                path: "old.json".to_owned(),
+                output: Some("new.bin".to_owned()),
+                format: Some(FileFormat::Binary),
+                secrets: Secrets::default(),
+            }
+        );
+        assert!(parse_args(args("upgrade old.json a.json b.json")).is_err());
+    }
+
+    #[test]
+    fn test_parse_merge_without_inputs() {
+        assert!(parse_args(args("merge out.json")).is_err());
+        assert!(parse_args(args("unknown f.json")).is_err());
+    }
+
+    #[test]
+    fn test_parse_secrets() {
+        let command: Command = parse_args(args(
+            "query f.bin Coke --key 000102030405060708090a0b0c0d0e0f --signing-key 0707070707070707070707070707070707070707070707070707070707070707",
+        ))
+        .unwrap();
+
+        assert_eq!(
+            command,
+            Command::Query {
+                path: "f.bin".to_owned(),
+                keys: KeysSource::Args(vec!["Coke".to_owned()]),
+                secrets: Secrets {
+                    key: Some([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]),
+                    signing_key: Some([7; 32]),
+                },
+            }
+        );
+        assert!(parse_args(args("stats f.bin --key 0001")).is_err());
+        assert!(parse_args(args("stats f.bin --key 000102030405060708090a0b0c0d0e0g")).is_err());
+        assert!(parse_args(args("stats f.bin --signing-key")).is_err());
+    }
+
+    /// Runs the command line, failing the test on the parsing errors.
+    fn run_line(line: &str) -> Result<u8, String> {
+        run(parse_args(args(line)).unwrap())
+    }
+
+    #[test]
+    fn test_run_create_insert_query_stats() {
+        let tmp_save_path: &Path = Path::new("./bfilter_cli.bin");
+
+        let results: Vec<Result<u8, String>> = vec![
+            run_line("create ./bfilter_cli.bin --items 100 --salt 42 --format binary"),
+            run_line("insert ./bfilter_cli.bin Vinegar John_Green"),
+            run_line("query ./bfilter_cli.bin Vinegar John_Green"),
+            run_line("query ./bfilter_cli.bin Vinegar Coke"),
+            run_line("stats ./bfilter_cli.bin"),
+        ];
+        let mut bloom_filter: BloomFilter = BloomFilter::from_file(tmp_save_path).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert_eq!(results, vec![Ok(0), Ok(0), Ok(0), Ok(EXIT_ABSENT), Ok(0)]);
+        assert!(bloom_filter.is_probably_present("John_Green"));
+        assert_eq!(bloom_filter.items_added(), 2);
+        assert_eq!(bloom_filter.salt(), Some(42));
+    }
+
+    #[test]
+    fn test_run_keyed_signed() {
+        let tmp_save_path: &Path = Path::new("./bfilter_cli_signed.json");
+        let key: &str = "--key 000102030405060708090a0b0c0d0e0f";
+        let signing_key: &str =
+            "--signing-key 0707070707070707070707070707070707070707070707070707070707070707";
+
+        let created: Result<u8, String> = run_line(&format!(
+            "create ./bfilter_cli_signed.json --items 100 {} {}",
+            key, signing_key
+        ));
+        let without_key: Result<u8, String> = run_line("query ./bfilter_cli_signed.json Vinegar");
+        let without_signing_key: Result<u8, String> =
+            run_line(&format!("insert ./bfilter_cli_signed.json Vinegar {}", key));
+        let inserted: Result<u8, String> = run_line(&format!(
+            "insert ./bfilter_cli_signed.json Vinegar {} {}",
+            key, signing_key
+        ));
+        let queried: Result<u8, String> =
+            run_line(&format!("query ./bfilter_cli_signed.json Vinegar {}", key));
+
+        let options: LoadOptions = LoadOptions::new()
+            .with_key([0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15])
+            .with_signing_key([7; 32]);
+        let mut bloom_filter: BloomFilter =
+            BloomFilter::from_file_with_options(tmp_save_path, options).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert_eq!(created, Ok(0));
+        assert!(without_key.is_err());
+        assert!(without_signing_key.is_err());
+        assert_eq!(inserted, Ok(0));
+        assert_eq!(queried, Ok(0));
+        assert!(bloom_filter.is_signed());
+        assert!(bloom_filter.is_probably_present("Vinegar"));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_run_insert_keeps_compression() {
+        use bfilters::Compression;
+
+        let tmp_save_path: &Path = Path::new("./bfilter_cli.zst.bin");
+
+        BloomFilter::new(Some(0.01), 100_000)
+            .unwrap()
+            .save_compressed(tmp_save_path, Compression::Zstd(19))
+            .unwrap();
+
+        let inserted: Result<u8, String> = run_line("insert ./bfilter_cli.zst.bin Vinegar");
+        let bytes: Vec<u8> = fs::read(tmp_save_path).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert_eq!(inserted, Ok(0));
+        assert_eq!(Compression::detect(&bytes), Compression::Zstd(0));
+        assert!(BloomFilter::from_bytes(&bytes)
+            .unwrap()
+            .is_probably_present("Vinegar"));
+    }
+}
@@ -0,0 +1,536 @@
+//! Memory mapped bloom filters over the binary layout written by bloom_filter.save_binary(...).
+//!
+//! Opening a mapped bloom filter reads only its header, the bits are paged in by the OS on demand,
+//! and all the processes mapping the same file share a single page cache copy of it.
+
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+
+use memmap2::{Mmap, MmapMut};
+
+use crate::format::{
+    Header, CHECKSUM_OFFSET, FLAGS_OFFSET, FLAG_CHECKSUM, FLAG_SIGNED, HEADER_SIZE, INSERTS_OFFSET,
+    ITEMS_ADDED_OFFSET,
+};
+use crate::hash;
+use crate::stream::CHUNK_SIZE;
+use crate::{BloomFilter, BloomFilterRef, HashKey, LoadBloomFilterError, LoadOptions};
+
+/// A read-only bloom filter memory mapped from a binary file.
+///
+/// The file should not be modified in place while it is mapped (except through MmapBloomFilterMut),
+/// replacing it with a rename is safe as the mapping keeps the old file alive.
+///
+/// ```rust
+/// use std::fs;
+/// use bfilters::{BloomFilter, MmapBloomFilter};
+///
+/// let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+///
+/// bloom_filter.insert("Vinegar");
+/// bloom_filter.save_binary("./bfilter_mmap_doc.bin").unwrap();
+///
+/// let mapped_bloom_filter: MmapBloomFilter = MmapBloomFilter::open("./bfilter_mmap_doc.bin").unwrap();
+///
+/// assert!(mapped_bloom_filter.is_probably_present("Vinegar"));
+/// assert!(!mapped_bloom_filter.is_probably_present("Coke"));
+///
+/// fs::remove_file("./bfilter_mmap_doc.bin").unwrap();
+/// ```
+pub struct MmapBloomFilter {
+    mmap: Mmap,
+    header: Header,
+    key: Option<HashKey>,
+}
+
+impl MmapBloomFilter {
+    /// Maps the binary bloom filter file read-only.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, LoadBloomFilterError> {
+        Self::open_with_options(path, LoadOptions::new())
+    }
+
+    /// Maps the keyed binary bloom filter file read-only with the secret key it was created with.
+    pub fn open_with_key<P: AsRef<Path>>(
+        path: P,
+        key: HashKey,
+    ) -> Result<Self, LoadBloomFilterError> {
+        Self::open_with_options(path, LoadOptions::new().with_key(key))
+    }
+
+    /// Maps the binary bloom filter file read-only with the given options.
+    /// Verifying the checksum reads the whole file, it could be disabled in the options to open the file instantly.
+    pub fn open_with_options<P: AsRef<Path>>(
+        path: P,
+        options: LoadOptions,
+    ) -> Result<Self, LoadBloomFilterError> {
+        let file: File = File::open(path)?;
+
+        // Safety: the file is expected not to be truncated or modified in place while mapped,
+        // the header and the payload length are validated below.
+        let mmap: Mmap = unsafe { Mmap::map(&file)? };
+        let header: Header = BloomFilterRef::new_with_options(&mmap, options)?.into_header();
+
+        Ok(Self {
+            mmap,
+            header,
+            key: options.key,
+        })
+    }
+
+    /// Returns the zero-copy view over the mapped bytes, e.g. to get the fill ratio statistics.
+    pub fn view(&self) -> BloomFilterRef<'_> {
+        BloomFilterRef::from_parts(
+            self.header.clone(),
+            &self.mmap[HEADER_SIZE..HEADER_SIZE + self.header.payload_len()],
+            self.key,
+        )
+    }
+
+    /// Given the negative or false positive answer about the item presence in the bloom filter.
+    pub fn is_probably_present(&self, item: &str) -> bool {
+        self.view().is_probably_present(item)
+    }
+
+    /// Returns the number of bits in the bloom filter's bit array.
+    pub fn number_of_bits(&self) -> u32 {
+        self.header.number_of_bits
+    }
+
+    /// Returns the number of hash functions used to store the single string item.
+    pub fn number_of_hashes(&self) -> u32 {
+        self.header.number_of_hashes
+    }
+
+    /// Returns the number of items the bloom filter was sized for (also known as capacity).
+    pub fn items_count(&self) -> u32 {
+        self.header.items_count
+    }
+
+    /// Returns the number of items inserted into the bloom filter when it was mapped.
+    pub fn items_added(&self) -> u32 {
+        self.header.items_added
+    }
+
+    /// Copies the mapped bloom filter into memory.
+    pub fn to_bloom_filter(&self) -> BloomFilter {
+        self.view().to_owned()
+    }
+}
+
+/// A writable bloom filter memory mapped from a binary file with MAP_SHARED,
+/// so the inserted items are written straight to the file and are visible to all the processes mapping it.
+///
+/// The bits and the items added counter are updated with atomic operations, so several threads
+/// and processes could insert and query the same file concurrently.
+pub struct MmapBloomFilterMut {
+    mmap: MmapMut,
+    ptr: *mut u8,
+    header: Header,
+    key: Option<HashKey>,
+}
+
+// Safety: the mapped memory is only accessed through atomic operations.
+unsafe impl Send for MmapBloomFilterMut {}
+unsafe impl Sync for MmapBloomFilterMut {}
+
+impl MmapBloomFilterMut {
+    /// Maps the binary bloom filter file for reading and writing.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, LoadBloomFilterError> {
+        Self::open_with_options(path, LoadOptions::new())
+    }
+
+    /// Maps the keyed binary bloom filter file for reading and writing with the secret key it was created with.
+    pub fn open_with_key<P: AsRef<Path>>(
+        path: P,
+        key: HashKey,
+    ) -> Result<Self, LoadBloomFilterError> {
+        Self::open_with_options(path, LoadOptions::new().with_key(key))
+    }
+
+    /// Maps the binary bloom filter file for reading and writing with the given options.
+    ///
+    /// The checksum and the signature could not be kept up to date by the concurrent writers, so they are
+    /// removed from the file on the first insert and mapping_bloom_filter.flush() writes the new checksum.
+    /// The signature could not be restored without the signing key, saving the loaded bloom filter signs it again.
+    pub fn open_with_options<P: AsRef<Path>>(
+        path: P,
+        options: LoadOptions,
+    ) -> Result<Self, LoadBloomFilterError> {
+        let file: File = OpenOptions::new().read(true).write(true).open(path)?;
+
+        // Safety: the file is expected not to be truncated while mapped, the header and
+        // the payload length are validated below and the memory is only accessed atomically.
+        let mut mmap: MmapMut = unsafe { MmapMut::map_mut(&file)? };
+
+        // The writers mapping the file remove its checksum, so the bits are verified only if it is still there.
+        let options: LoadOptions = match Header::parse(&mmap) {
+            Ok(header) if header.checksum.is_none() => options.with_checksum_verification(false),
+            _ => options,
+        };
+        let header: Header = BloomFilterRef::new_with_options(&mmap, options)?.into_header();
+
+        let ptr: *mut u8 = mmap.as_mut_ptr();
+
+        Ok(Self {
+            mmap,
+            ptr,
+            header,
+            key: options.key,
+        })
+    }
+
+    fn has_checksum(&self) -> bool {
+        self.atomic_byte(FLAGS_OFFSET).load(Ordering::SeqCst) & FLAG_CHECKSUM as u8 != 0
+    }
+
+    /// Removes the checksum only if it is there, so the inserts do not contend on the header otherwise.
+    fn remove_checksum_if_present(&self) {
+        if self.has_checksum() {
+            self.remove_checksum();
+        }
+    }
+
+    /// Clears the checksum and signed flags before the checksum itself, so the other processes never see
+    /// a stale checksum. The signature could not be updated without the signing key, so it is dropped as well.
+    fn remove_checksum(&self) {
+        self.atomic_byte(FLAGS_OFFSET)
+            .fetch_and(!((FLAG_CHECKSUM | FLAG_SIGNED) as u8), Ordering::SeqCst);
+
+        for offset in CHECKSUM_OFFSET..CHECKSUM_OFFSET + 4 {
+            self.atomic_byte(offset).store(0, Ordering::SeqCst);
+        }
+    }
+
+    /// Writes the checksum of the current bits before setting its flag, so the other processes never see
+    /// a partially written checksum. The payload is read atomically as the other writers could be inserting.
+    fn write_checksum(&self) {
+        let header: Header = Header {
+            items_added: self.items_added(),
+            ..self.header.clone()
+        };
+        let payload_len: usize = header.payload_len();
+        let mut chunk: Vec<u8> = Vec::with_capacity(CHUNK_SIZE.min(payload_len));
+        let mut checksum: u32 = crc32c::crc32c(&header.canonical_bytes());
+
+        for start in (0..payload_len).step_by(CHUNK_SIZE) {
+            chunk.clear();
+            chunk.extend((start..payload_len.min(start + CHUNK_SIZE)).map(|offset| {
+                self.atomic_byte(HEADER_SIZE + offset)
+                    .load(Ordering::SeqCst)
+            }));
+            checksum = crc32c::crc32c_append(checksum, &chunk);
+        }
+
+        for (offset, byte) in (CHECKSUM_OFFSET..).zip(checksum.to_le_bytes()) {
+            self.atomic_byte(offset).store(byte, Ordering::SeqCst);
+        }
+
+        self.atomic_byte(FLAGS_OFFSET)
+            .fetch_or(FLAG_CHECKSUM as u8, Ordering::SeqCst);
+    }
+
+    fn atomic_byte(&self, offset: usize) -> &AtomicU8 {
+        debug_assert!(offset < HEADER_SIZE + self.header.payload_len());
+
+        // Safety: the offset is within the validated mapping, which lives as long as self.
+        unsafe { AtomicU8::from_ptr(self.ptr.add(offset)) }
+    }
+
+    fn atomic_items_added(&self) -> &AtomicU32 {
+        // Safety: the mapping is page aligned, so the counter offset is aligned to 4 bytes.
+        unsafe { AtomicU32::from_ptr(self.ptr.add(ITEMS_ADDED_OFFSET) as *mut u32) }
+    }
+
+    fn atomic_inserts(&self) -> &AtomicU64 {
+        // Safety: the mapping is page aligned, so the counter offset is aligned to 8 bytes.
+        unsafe { AtomicU64::from_ptr(self.ptr.add(INSERTS_OFFSET) as *mut u64) }
+    }
+
+    fn bit_index(&self, item: &str, seed: u32) -> u64 {
+        hash::calc_bit_index(
+            item.as_bytes(),
+            seed,
+            self.header.number_of_bits,
+            self.key.as_ref(),
+            self.header.salt,
+        )
+    }
+
+    /// Saving a given item to the mapped bloom filter.
+    /// Returning false if the bloom filter is full.
+    /// Returning true if the insertion was successful.
+    pub fn insert(&self, item: &str) -> bool {
+        let items_count: u32 = self.header.items_count;
+
+        if self.items_added() >= items_count {
+            return false;
+        }
+
+        // The checksum is marked invalid before the counter and the bits change,
+        // so the readers never see them with the previous checksum.
+        self.remove_checksum_if_present();
+
+        // The counter is stored as little endian in the file.
+        let reserved = self.atomic_items_added().fetch_update(
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+            |items_added| {
+                let items_added: u32 = u32::from_le(items_added);
+
+                (items_added < items_count).then(|| (items_added + 1).to_le())
+            },
+        );
+
+        if reserved.is_err() {
+            return false;
+        }
+
+        for seed in 0..self.header.number_of_hashes {
+            let index: u64 = self.bit_index(item, seed);
+
+            self.atomic_byte(HEADER_SIZE + (index / 8) as usize)
+                .fetch_or(1 << (index % 8), Ordering::SeqCst);
+        }
+
+        // Tells the flush racing with this insert that its checksum could miss these bits,
+        // and removes the checksum such a flush could have written meanwhile.
+        self.atomic_inserts().fetch_add(1, Ordering::SeqCst);
+        self.remove_checksum_if_present();
+
+        true
+    }
+
+    /// Given the negative or false positive answer about the item presence in the bloom filter.
+    pub fn is_probably_present(&self, item: &str) -> bool {
+        (0..self.header.number_of_hashes).all(|seed| {
+            let index: u64 = self.bit_index(item, seed);
+
+            self.atomic_byte(HEADER_SIZE + (index / 8) as usize)
+                .load(Ordering::Acquire)
+                & (1 << (index % 8))
+                != 0
+        })
+    }
+
+    /// Returns the number of items inserted into the bloom filter by all the processes mapping it.
+    pub fn items_added(&self) -> u32 {
+        u32::from_le(self.atomic_items_added().load(Ordering::SeqCst))
+    }
+
+    /// Returns the number of bits in the bloom filter's bit array.
+    pub fn number_of_bits(&self) -> u32 {
+        self.header.number_of_bits
+    }
+
+    /// Returns the number of hash functions used to store the single string item.
+    pub fn number_of_hashes(&self) -> u32 {
+        self.header.number_of_hashes
+    }
+
+    /// Returns the number of items the bloom filter was sized for (also known as capacity).
+    pub fn items_count(&self) -> u32 {
+        self.header.items_count
+    }
+
+    /// Synchronously flushes the inserted items to the file with the checksum of the bits.
+    /// If an insert races with the flush, the file is flushed without the checksum and the next flush writes it.
+    pub fn flush(&self) -> std::io::Result<()> {
+        if !self.has_checksum() {
+            let inserts: u64 = self.atomic_inserts().load(Ordering::SeqCst);
+
+            self.write_checksum();
+
+            // The inserts which completed since the checksum started could be missing from it,
+            // the ones still in progress remove it themselves once their bits are set.
+            if self.atomic_inserts().load(Ordering::SeqCst) != inserts {
+                self.remove_checksum();
+            }
+        }
+
+        self.mmap.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::{MmapBloomFilter, MmapBloomFilterMut};
+    use crate::{BloomFilter, BloomFilterRef, HashKey, LoadBloomFilterError, LoadOptions};
+
+    #[test]
+    fn test_mmap_item_present() {
+        let item: &str = "John Green";
+        let wrong_item: &str = "John White";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_salt(42)
+            .unwrap();
+
+        bloom_filter.insert(item);
+
+        let tmp_save_path: &Path = std::path::Path::new("./bfilter_mmap.bin");
+
+        bloom_filter.save_binary(tmp_save_path).unwrap();
+
+        let mapped_bloom_filter: MmapBloomFilter = MmapBloomFilter::open(tmp_save_path).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert!(mapped_bloom_filter.is_probably_present(item));
+        assert!(!mapped_bloom_filter.is_probably_present(wrong_item));
+        assert_eq!(mapped_bloom_filter.items_added(), 1);
+
+        let view: BloomFilterRef = mapped_bloom_filter.view();
+
+        assert_eq!(view.count_set_bits(), bloom_filter.count_set_bits());
+        assert_eq!(
+            mapped_bloom_filter.to_bloom_filter().to_bytes(),
+            bloom_filter.to_bytes()
+        );
+    }
+
+    #[test]
+    fn test_mmap_mut_shared_insert() {
+        let item: &str = "John Green";
+        let other_item: &str = "John White";
+        let key: HashKey = *b"0123456789abcdef";
+
+        let bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_key(key)
+            .unwrap();
+
+        let tmp_save_path: &Path = std::path::Path::new("./bfilter_mmap_mut.bin");
+
+        bloom_filter.save_binary(tmp_save_path).unwrap();
+
+        let missing_key_received: bool = matches!(
+            MmapBloomFilterMut::open(tmp_save_path),
+            Err(LoadBloomFilterError::MissingKey)
+        );
+        let writer: MmapBloomFilterMut =
+            MmapBloomFilterMut::open_with_key(tmp_save_path, key).unwrap();
+        let other_writer: MmapBloomFilterMut =
+            MmapBloomFilterMut::open_with_key(tmp_save_path, key).unwrap();
+
+        assert!(writer.insert(item));
+        assert!(other_writer.insert(other_item));
+
+        let missing_checksum_received: bool = matches!(
+            BloomFilter::from_file_with_key(tmp_save_path, key),
+            Err(LoadBloomFilterError::MissingChecksum)
+        );
+
+        writer.flush().unwrap();
+        other_writer.flush().unwrap();
+
+        let mut loaded_bloom_filter: BloomFilter =
+            BloomFilter::from_file_with_key(tmp_save_path, key).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert!(missing_key_received);
+        assert!(missing_checksum_received);
+        assert!(writer.is_probably_present(other_item));
+        assert!(other_writer.is_probably_present(item));
+        assert_eq!(writer.items_added(), 2);
+        assert!(loaded_bloom_filter.is_probably_present(item));
+        assert!(loaded_bloom_filter.is_probably_present(other_item));
+        assert_eq!(loaded_bloom_filter.items_added(), 2);
+    }
+
+    #[test]
+    fn test_mmap_mut_open_keeps_file() {
+        let signing_key: [u8; 32] = [7; 32];
+        let options: LoadOptions = LoadOptions::new().with_signing_key(signing_key);
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_signing_key(signing_key);
+
+        bloom_filter.insert("John Green");
+
+        let tmp_save_path: &Path = std::path::Path::new("./bfilter_mmap_mut_open.bin");
+
+        bloom_filter.save_binary(tmp_save_path).unwrap();
+
+        let writer: MmapBloomFilterMut = MmapBloomFilterMut::open(tmp_save_path).unwrap();
+
+        assert!(writer.is_probably_present("John Green"));
+        writer.flush().unwrap();
+        drop(writer);
+
+        let contents: Vec<u8> = fs::read(tmp_save_path).unwrap();
+        let signed_loaded: bool =
+            BloomFilter::from_file_with_options(tmp_save_path, options).is_ok();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert_eq!(contents, bloom_filter.to_bytes());
+        assert!(signed_loaded);
+    }
+
+    #[test]
+    fn test_mmap_mut_flush_racing_inserts() {
+        let tmp_save_path: &Path = std::path::Path::new("./bfilter_mmap_mut_race.bin");
+
+        BloomFilter::new(Some(0.01), 100_000)
+            .unwrap()
+            .save_binary(tmp_save_path)
+            .unwrap();
+
+        let writer: MmapBloomFilterMut = MmapBloomFilterMut::open(tmp_save_path).unwrap();
+
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let writer: &MmapBloomFilterMut = &writer;
+
+                scope.spawn(move || {
+                    for i in 0..5000 {
+                        assert!(writer.insert(&format!("item-{}-{}", thread, i)));
+                    }
+                });
+            }
+
+            for _ in 0..20 {
+                writer.flush().unwrap();
+            }
+        });
+
+        // The checksum left by the flushes racing with the inserts is either removed or up to date.
+        let racing_result: Result<BloomFilter, LoadBloomFilterError> =
+            BloomFilter::from_file(tmp_save_path);
+
+        writer.flush().unwrap();
+
+        let loaded_bloom_filter: BloomFilter = BloomFilter::from_file(tmp_save_path).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert!(matches!(
+            racing_result,
+            Ok(_) | Err(LoadBloomFilterError::MissingChecksum)
+        ));
+        assert_eq!(loaded_bloom_filter.items_added(), 20_000);
+    }
+
+    #[test]
+    fn test_mmap_mut_insert_over_capacity() {
+        let bloom_filter: BloomFilter = BloomFilter::new(Some(0.35), 1).unwrap();
+
+        let tmp_save_path: &Path = std::path::Path::new("./bfilter_mmap_full.bin");
+
+        bloom_filter.save_binary(tmp_save_path).unwrap();
+
+        let writer: MmapBloomFilterMut = MmapBloomFilterMut::open(tmp_save_path).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert!(writer.insert("John Green"));
+        assert!(!writer.insert("John White"));
+        assert_eq!(writer.items_added(), 1);
+    }
+}
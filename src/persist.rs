@@ -0,0 +1,199 @@
+//! Atomic and durable replacement of the saved bloom filter files.
+//!
+//! The bytes are written to a temporary sibling file which is synced and renamed over the target,
+//! then the directory is synced so the rename itself survives a crash. A reader sees either
+//! the old file or the new one, never a truncated one. The new file keeps the permissions of the replaced one.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::SaveBloomFilterError;
+
+/// The step of the atomic save the error happened at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaveStep {
+    /// Creating the temporary file next to the target one with the permissions of the target.
+    CreateTemp,
+    /// Writing the serialized bloom filter to the temporary file.
+    Write,
+    /// Flushing the temporary file to the disk.
+    SyncFile,
+    /// Renaming the temporary file over the target one.
+    Rename,
+    /// Flushing the directory entry of the renamed file to the disk. Unlike the other steps, the rename
+    /// has already happened, so the target holds the new file, but it may not survive a crash.
+    SyncDir,
+}
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns a unique path for the temporary file in the same directory as the target,
+/// so the rename never crosses the file systems.
+fn temp_path(path: &Path) -> PathBuf {
+    let file_name: String = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        file_name,
+        process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Syncs the directory containing the path, the directories could not be opened for syncing on Windows.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    let dir: &Path = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn at(step: SaveStep) -> impl FnOnce(io::Error) -> SaveBloomFilterError {
+    move |err| SaveBloomFilterError::Step(step, err)
+}
+
+/// Writes the temporary file through a bounded buffer, syncs it and renames it over the target.
+fn write_temp<F>(temp: &Path, path: &Path, write: F) -> Result<(), SaveBloomFilterError>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), SaveBloomFilterError>,
+{
+    let file: File = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp)
+        .map_err(at(SaveStep::CreateTemp))?;
+
+    // The replaced file keeps its mode instead of getting the default one of the new files.
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())
+            .map_err(at(SaveStep::CreateTemp))?;
+    }
+
+    let mut writer: BufWriter<File> = BufWriter::new(file);
+
+    write(&mut writer).map_err(|err| match err {
+        SaveBloomFilterError::Io(err) => SaveBloomFilterError::Step(SaveStep::Write, err),
+        err => err,
+    })?;
+
+    let file: File = writer
+        .into_inner()
+        .map_err(|err| SaveBloomFilterError::Step(SaveStep::Write, err.into_error()))?;
+
+    file.sync_all().map_err(at(SaveStep::SyncFile))?;
+
+    fs::rename(temp, path).map_err(at(SaveStep::Rename))
+}
+
+/// Atomically replaces the file at the path with the given bytes.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<(), SaveBloomFilterError> {
+    write_atomic_with(path, |writer| Ok(writer.write_all(bytes)?))
+}
+
+/// Atomically replaces the file at the path with the bytes written by the given function.
+pub(crate) fn write_atomic_with<F>(path: &Path, write: F) -> Result<(), SaveBloomFilterError>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), SaveBloomFilterError>,
+{
+    let temp: PathBuf = temp_path(path);
+
+    if let Err(err) = write_temp(&temp, path, write) {
+        // Nothing to clean up if the temporary file was not created.
+        let _ = fs::remove_file(&temp);
+
+        return Err(err);
+    }
+
+    sync_dir(path).map_err(at(SaveStep::SyncDir))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::{write_atomic, SaveStep};
+    use crate::SaveBloomFilterError;
+
+    #[test]
+    fn test_write_atomic_replaces_file() {
+        let tmp_save_path: &Path = Path::new("./bfilter_atomic.bin");
+
+        fs::write(tmp_save_path, b"old contents").unwrap();
+        write_atomic(tmp_save_path, b"new").unwrap();
+
+        let contents: Vec<u8> = fs::read(tmp_save_path).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert_eq!(contents, b"new");
+
+        // No temporary files are left behind.
+        let leftovers: usize = fs::read_dir(".")
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(".bfilter_atomic.bin.")
+            })
+            .count();
+
+        assert_eq!(leftovers, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_save_path: &Path = Path::new("./bfilter_atomic_mode.bin");
+
+        fs::write(tmp_save_path, b"old contents").unwrap();
+        fs::set_permissions(tmp_save_path, fs::Permissions::from_mode(0o640)).unwrap();
+        write_atomic(tmp_save_path, b"new").unwrap();
+
+        let mode: u32 = fs::metadata(tmp_save_path).unwrap().permissions().mode();
+
+        fs::remove_file(tmp_save_path).unwrap();
+
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[test]
+    fn test_write_atomic_failing_step() {
+        let tmp_save_path: &Path = Path::new("./bfilter_atomic_dir");
+
+        // A directory could not be replaced with a file, so the rename fails.
+        fs::create_dir_all(tmp_save_path.join("child")).unwrap();
+
+        let result: Result<(), SaveBloomFilterError> = write_atomic(tmp_save_path, b"new");
+
+        fs::remove_dir_all(tmp_save_path).unwrap();
+
+        assert!(matches!(
+            result,
+            Err(SaveBloomFilterError::Step(SaveStep::Rename, _))
+        ));
+        assert!(matches!(
+            write_atomic(Path::new("./.test/bfilter_atomic.bin"), b"new"),
+            Err(SaveBloomFilterError::Step(SaveStep::CreateTemp, _))
+        ));
+    }
+}
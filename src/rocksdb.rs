@@ -0,0 +1,460 @@
+//! The full filter blocks of RocksDB with the FastLocalBloom implementation (`format_version` 5 and later).
+//!
+//! A filter block is the bloom filter data followed by 5 bytes of metadata: the -1 (0xff) marker of the new
+//! bloom implementations, the 0 marker of FastLocalBloom, the number of probes (the upper 3 bits are 0 for
+//! the 64 byte cache lines) and two zero bytes. A block of 5 bytes or less matches no key.
+//!
+//! The data is a sequence of 64 byte cache lines. A key is hashed with the 64 bit XXH3 preview RocksDB
+//! keeps as `GetSliceHash64`, the lower 32 bits `h1` select the cache line `(h1 * number_of_lines) >> 32`
+//! and the upper 32 bits `h2` set the bits `h >> 23` of the cache line, starting from `h = h2`
+//! and multiplying `h` by 0x9e3779b9 for every next probe. The bit `i` is the bit `i % 8` of the byte `i / 8`.
+
+use crate::hash::xxph3_64;
+use crate::LoadBloomFilterError;
+
+/// The length of the metadata trailer.
+const METADATA_LEN: usize = 5;
+
+/// The size of the cache lines, the blocks of the FastLocalBloom filters.
+const CACHE_LINE_SIZE: usize = 64;
+
+/// The largest filter data RocksDB builds.
+const MAX_DATA_LEN: u64 = 0xffffffc0;
+
+/// Builds the full filter blocks the same as RocksDB's `NewBloomFilterPolicy(bits_per_key)`
+/// with `format_version` 5 and `optimize_filters_for_memory` off.
+///
+/// ```rust
+/// use bfilters::{RocksDbFilterBuilder, RocksDbFilterReader};
+///
+/// let block: Vec<u8> = RocksDbFilterBuilder::build(10.0, ["Vinegar", "John Green"]).unwrap();
+/// let reader: RocksDbFilterReader = RocksDbFilterReader::from_bytes(&block).unwrap();
+///
+/// assert!(reader.key_may_match(b"Vinegar"));
+/// assert!(!reader.key_may_match(b"Coke"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RocksDbFilterBuilder {
+    millibits_per_key: u32,
+    hashes: Vec<u64>,
+}
+
+impl RocksDbFilterBuilder {
+    /// Creates an empty builder, the bits per key are rounded and limited the same way as RocksDB does.
+    /// Fails for less than 0.5 bits per key, as RocksDB builds no filter then.
+    pub fn new(bits_per_key: f64) -> Result<Self, String> {
+        if bits_per_key.is_nan() || bits_per_key < 0.5 {
+            return Err("The filter should have at least 0.5 bits per key.".to_owned());
+        }
+
+        let bits_per_key: f64 = bits_per_key.clamp(1.0, 100.0);
+
+        Ok(Self {
+            millibits_per_key: (bits_per_key * 1000.0 + 0.500001) as u32,
+            hashes: Vec::new(),
+        })
+    }
+
+    /// Builds the filter block of the given keys.
+    pub fn build<I, K>(bits_per_key: f64, keys: I) -> Result<Vec<u8>, String>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<[u8]>,
+    {
+        let mut builder: Self = Self::new(bits_per_key)?;
+
+        for key in keys {
+            builder.add_key(key.as_ref());
+        }
+
+        Ok(builder.finish())
+    }
+
+    /// Adds the key, the repetitions of the previous key are skipped.
+    pub fn add_key(&mut self, key: &[u8]) {
+        let hash: u64 = xxph3_64(key);
+
+        if self.hashes.last() != Some(&hash) {
+            self.hashes.push(hash);
+        }
+    }
+
+    /// Returns the number of keys added, without the repetitions.
+    pub fn num_entries(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Returns the number of probes of the filters built.
+    pub fn num_probes(&self) -> u8 {
+        match self.millibits_per_key {
+            0..=2080 => 1,
+            2081..=3580 => 2,
+            3581..=5100 => 3,
+            5101..=6640 => 4,
+            6641..=8300 => 5,
+            8301..=10070 => 6,
+            10071..=11720 => 7,
+            11721..=14001 => 8,
+            14002..=16050 => 9,
+            16051..=18300 => 10,
+            18301..=22001 => 11,
+            22002..=25501 => 12,
+            50001.. => 24,
+            millibits_per_key => ((millibits_per_key - 1) / 2000 - 1) as u8,
+        }
+    }
+
+    /// Returns the filter block of the keys added, empty if there are none.
+    pub fn finish(&self) -> Vec<u8> {
+        if self.hashes.is_empty() {
+            return Vec::new();
+        }
+
+        let target_len: u64 = (self.hashes.len() as u64 * self.millibits_per_key as u64)
+            .div_ceil(8000)
+            .min(MAX_DATA_LEN);
+        let len: usize = target_len.next_multiple_of(CACHE_LINE_SIZE as u64) as usize;
+        let num_probes: u8 = self.num_probes();
+        let mut block: Vec<u8> = vec![0; len + METADATA_LEN];
+
+        for hash in self.hashes.iter() {
+            let cache_line: &mut [u8] = cache_line_mut(&mut block[..len], *hash as u32);
+
+            for bit in probes(num_probes, (*hash >> 32) as u32) {
+                cache_line[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+
+        block[len] = 0xff;
+        block[len + 2] = num_probes;
+
+        block
+    }
+}
+
+/// Answers whether a key may match a RocksDB full filter block, the same as RocksDB reads it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RocksDbFilterReader {
+    filter: Filter,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Filter {
+    AlwaysFalse,
+    AlwaysTrue,
+    FastLocalBloom { data: Vec<u8>, num_probes: u8 },
+}
+
+impl RocksDbFilterReader {
+    /// Parses the filter block. The blocks of 5 bytes or less match no key, the ones with reserved or
+    /// inconsistent metadata match every key, the same as RocksDB treats them for safety.
+    /// Fails for the legacy bloom and Ribbon filter blocks which are not supported.
+    pub fn from_bytes(block: &[u8]) -> Result<Self, LoadBloomFilterError> {
+        if block.len() <= METADATA_LEN {
+            return Ok(Self {
+                filter: Filter::AlwaysFalse,
+            });
+        }
+
+        let len: usize = block.len() - METADATA_LEN;
+        let metadata: &[u8] = &block[len..];
+
+        let filter: Filter = match metadata[0] as i8 {
+            0 => Filter::AlwaysTrue,
+            -1 => {
+                let num_probes: u8 = metadata[2] & 31;
+
+                // Only FastLocalBloom with 64 byte cache lines is defined, the rest is reserved.
+                if metadata[1] != 0
+                    || metadata[2] >> 5 != 0
+                    || !(1..=30).contains(&num_probes)
+                    || metadata[3..] != [0, 0]
+                    || len < CACHE_LINE_SIZE
+                {
+                    Filter::AlwaysTrue
+                } else {
+                    Filter::FastLocalBloom {
+                        data: block[..len].to_vec(),
+                        num_probes,
+                    }
+                }
+            }
+            -2 => {
+                return Err(LoadBloomFilterError::Format(
+                    "The RocksDB Ribbon filters are not supported.".to_owned(),
+                ))
+            }
+            raw_num_probes if raw_num_probes > 0 => {
+                return Err(LoadBloomFilterError::Format(
+                    "The RocksDB legacy bloom filters are not supported.".to_owned(),
+                ))
+            }
+            _ => Filter::AlwaysTrue,
+        };
+
+        Ok(Self { filter })
+    }
+
+    /// Returns the number of probes of the FastLocalBloom filter, none for the trivial filters.
+    pub fn num_probes(&self) -> Option<u8> {
+        match self.filter {
+            Filter::FastLocalBloom { num_probes, .. } => Some(num_probes),
+            _ => None,
+        }
+    }
+
+    /// Given the negative or false positive answer about the key presence, the same as RocksDB's `KeyMayMatch`.
+    pub fn key_may_match(&self, key: &[u8]) -> bool {
+        match &self.filter {
+            Filter::AlwaysFalse => false,
+            Filter::AlwaysTrue => true,
+            Filter::FastLocalBloom { data, num_probes } => {
+                let hash: u64 = xxph3_64(key);
+                let cache_line: &[u8] = cache_line(data, hash as u32);
+
+                probes(*num_probes, (hash >> 32) as u32)
+                    .all(|bit| cache_line[bit / 8] & (1 << (bit % 8)) != 0)
+            }
+        }
+    }
+}
+
+/// Returns the start of the cache line the lower half of the hash selects, the data holds at least one line.
+fn cache_line_start(len: usize, h1: u32) -> usize {
+    let number_of_lines: u64 = (len / CACHE_LINE_SIZE) as u64;
+
+    ((h1 as u64 * number_of_lines) >> 32) as usize * CACHE_LINE_SIZE
+}
+
+fn cache_line(data: &[u8], h1: u32) -> &[u8] {
+    let start: usize = cache_line_start(data.len(), h1);
+
+    &data[start..start + CACHE_LINE_SIZE]
+}
+
+fn cache_line_mut(data: &mut [u8], h1: u32) -> &mut [u8] {
+    let start: usize = cache_line_start(data.len(), h1);
+
+    &mut data[start..start + CACHE_LINE_SIZE]
+}
+
+/// Returns the bits of the cache line the upper half of the hash probes.
+fn probes(num_probes: u8, h2: u32) -> impl Iterator<Item = usize> {
+    (0..num_probes).scan(h2, |h, _| {
+        let bit: usize = (*h >> 23) as usize;
+
+        *h = h.wrapping_mul(0x9e3779b9);
+
+        Some(bit)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RocksDbFilterBuilder, RocksDbFilterReader};
+    use crate::LoadBloomFilterError;
+
+    /// The filter of the keys 0 to 86 (as 4 little endian bytes) with 5 bits per key, built with the RocksDB
+    /// util/bloom_impl.h and util/xxph3.h. Its checksum is the one the RocksDB FullBloomTest.Schema expects.
+    const FIXTURE: &str = "12212538beb43c70c6e922c804d741142c5d296388d0ec4c82a049660cb08e2e1f4de9af4b2c41ac1dd27634444f12b0037030131e100030d0bc91659ac82624ff00030000";
+
+    /// The FastLocalBloom cases of the RocksDB FullBloomTest.Schema as (bits per key, keys, number of probes,
+    /// checksum of the block, first false positives among the keys from 1000000).
+    type SchemaCase = (f64, std::ops::Range<u32>, u8, u32, &'static str);
+
+    const SCHEMA: [SchemaCase; 8] = [
+        (
+            5.0,
+            0..87,
+            3,
+            4130687756,
+            "31,38,40,43,61,83,86,112,125,131",
+        ),
+        (2.0, 0..2087, 1, 3817481309, "11,13,17,25,29,30,35,37,45,53"),
+        (
+            8.0,
+            0..2087,
+            5,
+            355564975,
+            "16,60,66,126,220,238,244,256,265,287",
+        ),
+        (
+            14.0,
+            0..2087,
+            8,
+            3709876890,
+            "130,240,522,565,989,2002,2526,3147,3543",
+        ),
+        (
+            16.0,
+            0..2087,
+            9,
+            1087138490,
+            "3299,3611,3916,6620,7822,8079,8482,8942",
+        ),
+        (
+            10.0,
+            1..2087,
+            6,
+            2058382345,
+            "16,126,133,422,466,472,813,1002,1035",
+        ),
+        (
+            9.5,
+            1..2088,
+            6,
+            3166884174,
+            "126,156,367,444,458,791,813,976,1015",
+        ),
+        (
+            10.499,
+            1..2088,
+            7,
+            4098502778,
+            "16,236,240,472,1015,1045,1111,1409,1465",
+        ),
+    ];
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// The RocksDB legacy `BloomHash` the tests checksum the blocks with.
+    fn bloom_hash(data: &[u8]) -> u32 {
+        const M: u32 = 0xc6a4a793;
+
+        let mut h: u32 = 0xbc9f1d34 ^ (data.len() as u32).wrapping_mul(M);
+        let mut blocks = data.chunks_exact(4);
+
+        for block in blocks.by_ref() {
+            h = h
+                .wrapping_add(u32::from_le_bytes(block.try_into().unwrap()))
+                .wrapping_mul(M);
+            h ^= h >> 16;
+        }
+
+        let tail: &[u8] = blocks.remainder();
+
+        if !tail.is_empty() {
+            for (i, byte) in tail.iter().enumerate() {
+                h = h.wrapping_add(((*byte as i8) as u32) << (8 * i));
+            }
+
+            h = h.wrapping_mul(M);
+            h ^= h >> 24;
+        }
+
+        h
+    }
+
+    #[test]
+    fn test_rocksdb_schema() {
+        let block: Vec<u8> =
+            RocksDbFilterBuilder::build(5.0, (0..87_u32).map(u32::to_le_bytes)).unwrap();
+
+        assert_eq!(block, unhex(FIXTURE));
+
+        for (bits_per_key, keys, num_probes, checksum, first_false_positives) in SCHEMA {
+            let block: Vec<u8> =
+                RocksDbFilterBuilder::build(bits_per_key, keys.map(u32::to_le_bytes)).unwrap();
+            let reader: RocksDbFilterReader = RocksDbFilterReader::from_bytes(&block).unwrap();
+            let false_positives: Vec<String> = (0..1_000_000_u32)
+                .filter(|i| reader.key_may_match(&(i + 1_000_000).to_le_bytes()))
+                .take(first_false_positives.split(',').count())
+                .map(|i| i.to_string())
+                .collect();
+
+            assert_eq!(reader.num_probes(), Some(num_probes), "{}", bits_per_key);
+            assert_eq!(bloom_hash(&block), checksum, "{}", bits_per_key);
+            assert_eq!(false_positives.join(","), first_false_positives);
+        }
+    }
+
+    #[test]
+    fn test_rocksdb_builder() {
+        let mut builder: RocksDbFilterBuilder = RocksDbFilterBuilder::new(10.0).unwrap();
+
+        assert!(builder.finish().is_empty());
+
+        for key in ["John Green", "John Green", "Vinegar", "John Green"] {
+            builder.add_key(key.as_bytes());
+        }
+
+        let block: Vec<u8> = builder.finish();
+        let reader: RocksDbFilterReader = RocksDbFilterReader::from_bytes(&block).unwrap();
+
+        assert_eq!(builder.num_entries(), 3);
+        assert_eq!(block.len(), 64 + 5);
+        assert_eq!(block[64..], [0xff, 0, 6, 0, 0]);
+        assert!(reader.key_may_match(b"John Green"));
+        assert!(reader.key_may_match(b"Vinegar"));
+        assert!(!reader.key_may_match(b"Coke"));
+
+        assert!(RocksDbFilterBuilder::new(0.4).is_err());
+        assert!(RocksDbFilterBuilder::new(f64::NAN).is_err());
+        assert_eq!(RocksDbFilterBuilder::new(0.5).unwrap().num_probes(), 1);
+        assert_eq!(RocksDbFilterBuilder::new(30.0).unwrap().num_probes(), 13);
+        assert_eq!(RocksDbFilterBuilder::new(1000.0).unwrap().num_probes(), 24);
+    }
+
+    #[test]
+    fn test_rocksdb_reader_raw() {
+        // The FastLocalBloom case of the RocksDB FullBloomTest.RawSchema: 256 bytes of 0x7b7b >> (i % 7)
+        // counted from the start of its 3000 byte buffer, 2 probes and the keys 12345 to 12408.
+        let mut block: Vec<u8> = (2739..2995).map(|i| (0x7b7b >> (i % 7)) as u8).collect();
+
+        block.extend_from_slice(&[0xff, 0, 2, 0, 0]);
+
+        let reader: RocksDbFilterReader = RocksDbFilterReader::from_bytes(&block).unwrap();
+        let matches: u64 = (0..64)
+            .filter(|i| reader.key_may_match(&(i + 12345_u32).to_le_bytes()))
+            .fold(0, |matches, i| matches | 1 << i);
+
+        assert_eq!(matches, 9957045189927952471);
+
+        let data: Vec<u8> = vec![0; 64];
+        let metadata_cases: [([u8; 5], bool); 8] = [
+            ([0xff, 0, 6, 0, 0], false),
+            // Reserved sub implementation, cache line size, number of probes and trailing bytes.
+            ([0xff, 1, 6, 0, 0], true),
+            ([0xff, 0, 0x26, 0, 0], true),
+            ([0xff, 0, 31, 0, 0], true),
+            ([0xff, 0, 0, 0, 0], true),
+            ([0xff, 0, 6, 1, 0], true),
+            // Zero probes and the reserved negative markers.
+            ([0, 0, 0, 0, 0], true),
+            ([0x80, 0, 0, 0, 0], true),
+        ];
+
+        for (metadata, matches) in metadata_cases {
+            let block: Vec<u8> = [&data[..], &metadata].concat();
+            let reader: RocksDbFilterReader = RocksDbFilterReader::from_bytes(&block).unwrap();
+
+            assert_eq!(reader.key_may_match(b"hello"), matches, "{:?}", metadata);
+        }
+
+        // Less than a cache line of data can not be probed, it matches every key.
+        let short: RocksDbFilterReader =
+            RocksDbFilterReader::from_bytes(&[0, 0, 0xff, 0, 6, 0, 0]).unwrap();
+
+        assert!(short.key_may_match(b"hello"));
+        assert!(!RocksDbFilterReader::from_bytes(&[])
+            .unwrap()
+            .key_may_match(b"hello"));
+        assert!(!RocksDbFilterReader::from_bytes(&[0xff, 0, 6, 0, 0])
+            .unwrap()
+            .key_may_match(b"hello"));
+
+        for unsupported in [[0xfe, 0, 0, 0, 0], [6, 1, 0, 0, 0]] {
+            let block: Vec<u8> = [&data[..], &unsupported].concat();
+
+            assert!(matches!(
+                RocksDbFilterReader::from_bytes(&block),
+                Err(LoadBloomFilterError::Format(_))
+            ));
+        }
+    }
+}
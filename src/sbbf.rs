@@ -0,0 +1,613 @@
+//! The split block bloom filter (SBBF) of the Apache Parquet format.
+//!
+//! Parquet column chunks may carry a bloom filter of the values, written at the `bloom_filter_offset`
+//! of the column chunk metadata as a Thrift compact `BloomFilterHeader` followed by the bitset:
+//!
+//! - the bitset is a sequence of 256 bit blocks, each block is eight 32 bit little endian words;
+//! - a value is hashed with xxHash64 (seed 0) of its plain encoding: the raw bytes of the byte arrays
+//!   and the little endian bytes of the numbers;
+//! - the upper 32 bits of the hash select the block `((hash >> 32) * number_of_blocks) >> 32`,
+//!   the lower 32 bits set one bit in each word: the bit `(lower * SALT[i]) >> 27` of the word `i`.
+//!
+//! See <https://github.com/apache/parquet-format/blob/master/BloomFilter.md>.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use xxhash_rust::xxh64::xxh64;
+
+use crate::LoadBloomFilterError;
+
+const SALT: [u32; 8] = [
+    0x47b6137b, 0x44974d91, 0x8824ad5b, 0xa2b7289d, 0x705495c7, 0x2df1424b, 0x9efc4947, 0x5c6bfb31,
+];
+
+/// The size of a block in bytes.
+const BLOCK_SIZE: usize = 32;
+
+/// The bounds of the bitset size used by the Parquet writers.
+const MIN_BITSET_SIZE: usize = BLOCK_SIZE;
+const MAX_BITSET_SIZE: usize = 128 * 1024 * 1024;
+
+/// The Thrift compact protocol types used by the header.
+const THRIFT_I32: u8 = 5;
+const THRIFT_STRUCT: u8 = 12;
+
+/// The deepest nesting of the Thrift structs skipped while parsing the header.
+const MAX_THRIFT_DEPTH: usize = 32;
+
+type Block = [u32; 8];
+
+/// A value of a Parquet column, hashed by its plain encoding.
+pub trait ParquetValue {
+    /// Returns the plain encoding of the value the Parquet bloom filters hash.
+    fn plain_bytes(&self) -> Cow<'_, [u8]>;
+}
+
+impl ParquetValue for str {
+    fn plain_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+impl ParquetValue for String {
+    fn plain_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self.as_bytes())
+    }
+}
+
+impl ParquetValue for [u8] {
+    fn plain_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+impl ParquetValue for Vec<u8> {
+    fn plain_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(self)
+    }
+}
+
+macro_rules! impl_parquet_value_for_numbers {
+    ($($number:ty),*) => {
+        $(
+            impl ParquetValue for $number {
+                fn plain_bytes(&self) -> Cow<'_, [u8]> {
+                    Cow::Owned(self.to_le_bytes().to_vec())
+                }
+            }
+        )*
+    };
+}
+
+// The unsigned integers are stored as the INT32 and INT64 physical types with the same bytes.
+impl_parquet_value_for_numbers!(i32, i64, u32, u64, f32, f64);
+
+impl<T: ParquetValue + ?Sized> ParquetValue for &T {
+    fn plain_bytes(&self) -> Cow<'_, [u8]> {
+        (**self).plain_bytes()
+    }
+}
+
+/// Returns the hash of the value the Parquet bloom filters are queried with.
+pub fn parquet_hash<T: ParquetValue + ?Sized>(value: &T) -> u64 {
+    xxh64(&value.plain_bytes(), 0)
+}
+
+/// A split block bloom filter compatible with the Apache Parquet bloom filters.
+///
+/// ```rust
+/// use bfilters::SplitBlockBloomFilter;
+///
+/// let mut bloom_filter: SplitBlockBloomFilter = SplitBlockBloomFilter::new(1000, 0.01).unwrap();
+///
+/// bloom_filter.insert("Vinegar");
+/// bloom_filter.insert(&42_i64);
+///
+/// let bytes: Vec<u8> = bloom_filter.to_bytes();
+/// let parsed_bloom_filter: SplitBlockBloomFilter = SplitBlockBloomFilter::from_bytes(&bytes).unwrap();
+///
+/// assert!(parsed_bloom_filter.is_probably_present("Vinegar"));
+/// assert!(parsed_bloom_filter.is_probably_present(&42_i64));
+/// assert!(!parsed_bloom_filter.is_probably_present("Coke"));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitBlockBloomFilter {
+    blocks: Vec<Block>,
+}
+
+impl SplitBlockBloomFilter {
+    /// Creates an empty bloom filter for the given number of distinct values and false positive probability,
+    /// sized the same way as the Parquet writers do.
+    pub fn new(distinct_values: u64, false_positive_probability: f64) -> Result<Self, String> {
+        if false_positive_probability <= 0.0 || false_positive_probability >= 1.0 {
+            return Err(
+                "The bloom filter's false positive probability should be in range from 0 to 1."
+                    .to_owned(),
+            );
+        }
+
+        let number_of_bits: f64 =
+            -8.0 * distinct_values as f64 / (1.0 - false_positive_probability.powf(1.0 / 8.0)).ln();
+
+        Ok(Self::with_size(number_of_bits as usize / 8))
+    }
+
+    /// Creates an empty bloom filter with the given bitset size in bytes,
+    /// rounded up to a power of two between 32 bytes and 128 MiB.
+    pub fn with_size(bitset_size: usize) -> Self {
+        let bitset_size: usize = bitset_size
+            .clamp(MIN_BITSET_SIZE, MAX_BITSET_SIZE)
+            .next_power_of_two();
+
+        Self {
+            blocks: vec![[0; 8]; bitset_size / BLOCK_SIZE],
+        }
+    }
+
+    /// Builds the bloom filter of the given values sized for the number of their distinct hashes.
+    pub fn from_values<I, T>(values: I, false_positive_probability: f64) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = T>,
+        T: ParquetValue,
+    {
+        let hashes: HashSet<u64> = values
+            .into_iter()
+            .map(|value| parquet_hash(&value))
+            .collect();
+        let mut bloom_filter: Self = Self::new(hashes.len() as u64, false_positive_probability)?;
+
+        for hash in hashes {
+            bloom_filter.insert_hash(hash);
+        }
+
+        Ok(bloom_filter)
+    }
+
+    /// Wraps the bitset read from a Parquet file, its size should be a non zero multiple of 32 bytes.
+    pub fn from_bitset(bitset: &[u8]) -> Result<Self, LoadBloomFilterError> {
+        if bitset.is_empty() || !bitset.len().is_multiple_of(BLOCK_SIZE) {
+            return Err(LoadBloomFilterError::Format(format!(
+                "The split block bloom filter bitset size {} is not a multiple of {}.",
+                bitset.len(),
+                BLOCK_SIZE
+            )));
+        }
+
+        let blocks: Vec<Block> = bitset
+            .chunks_exact(BLOCK_SIZE)
+            .map(|block| {
+                let mut words: Block = [0; 8];
+
+                for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+                    *word = u32::from_le_bytes(bytes.try_into().unwrap());
+                }
+
+                words
+            })
+            .collect();
+
+        Ok(Self { blocks })
+    }
+
+    /// Parses the bloom filter as it is written at the bloom filter offset of a Parquet column chunk:
+    /// the Thrift compact header followed by the bitset. The bytes after the bitset are ignored.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, LoadBloomFilterError> {
+        let (bitset_size, header_size): (usize, usize) = parse_header(bytes)?;
+        let bitset: &[u8] = bytes
+            .get(header_size..header_size + bitset_size)
+            .ok_or_else(|| {
+                LoadBloomFilterError::Format(format!(
+                    "The split block bloom filter bitset is truncated, expected {} bytes, found {}.",
+                    bitset_size,
+                    bytes.len() - header_size
+                ))
+            })?;
+
+        Self::from_bitset(bitset)
+    }
+
+    /// Serializes the bitset in the Parquet layout.
+    pub fn bitset(&self) -> Vec<u8> {
+        self.blocks
+            .iter()
+            .flat_map(|block| block.iter().flat_map(|word| word.to_le_bytes()))
+            .collect()
+    }
+
+    /// Serializes the bloom filter as the Parquet writers do: the Thrift compact header
+    /// (xxHash, the block algorithm and no compression) followed by the bitset.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let bitset_size: i32 = (self.blocks.len() * BLOCK_SIZE) as i32;
+        let mut bytes: Vec<u8> = vec![0x10 | THRIFT_I32];
+
+        write_varint(
+            &mut bytes,
+            ((bitset_size << 1) ^ (bitset_size >> 31)) as u32 as u64,
+        );
+
+        // The algorithm, hash and compression unions with their only empty struct member set.
+        for _ in 0..3 {
+            bytes.extend_from_slice(&[0x10 | THRIFT_STRUCT, 0x10 | THRIFT_STRUCT, 0, 0]);
+        }
+
+        bytes.push(0);
+        bytes.extend_from_slice(&self.bitset());
+        bytes
+    }
+
+    /// Returns the size of the bitset in bytes.
+    pub fn bitset_size(&self) -> usize {
+        self.blocks.len() * BLOCK_SIZE
+    }
+
+    /// Inserts the value hashed by its plain encoding.
+    pub fn insert<T: ParquetValue + ?Sized>(&mut self, value: &T) {
+        self.insert_hash(parquet_hash(value));
+    }
+
+    /// Inserts the value by its xxHash64, see parquet_hash(...).
+    pub fn insert_hash(&mut self, hash: u64) {
+        let block_index: usize = self.block_index(hash);
+        let block: &mut Block = &mut self.blocks[block_index];
+
+        for (word, mask) in block.iter_mut().zip(mask(hash as u32)) {
+            *word |= mask;
+        }
+    }
+
+    /// Given the negative or false positive answer about the value presence in the bloom filter.
+    pub fn is_probably_present<T: ParquetValue + ?Sized>(&self, value: &T) -> bool {
+        self.is_hash_probably_present(parquet_hash(value))
+    }
+
+    /// Given the negative or false positive answer about the presence of the value with the given xxHash64.
+    pub fn is_hash_probably_present(&self, hash: u64) -> bool {
+        let block: &Block = &self.blocks[self.block_index(hash)];
+
+        block
+            .iter()
+            .zip(mask(hash as u32))
+            .all(|(word, mask)| word & mask != 0)
+    }
+
+    /// Merges the bloom filter of the same size into this one.
+    pub fn union(&mut self, other: &SplitBlockBloomFilter) -> Result<(), String> {
+        if self.blocks.len() != other.blocks.len() {
+            return Err(
+                "Only split block bloom filters with the same bitset size could be merged."
+                    .to_owned(),
+            );
+        }
+
+        for (block, other_block) in self.blocks.iter_mut().zip(other.blocks.iter()) {
+            for (word, other_word) in block.iter_mut().zip(other_block) {
+                *word |= other_word;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn block_index(&self, hash: u64) -> usize {
+        (((hash >> 32) * self.blocks.len() as u64) >> 32) as usize
+    }
+}
+
+/// Returns the block with one bit set in every word for the lower half of the hash.
+fn mask(key: u32) -> Block {
+    let mut mask: Block = [0; 8];
+
+    for (word, salt) in mask.iter_mut().zip(SALT) {
+        *word = 1 << (key.wrapping_mul(salt) >> 27);
+    }
+
+    mask
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+
+    bytes.push(value as u8);
+}
+
+/// Reads the Thrift compact protocol encoded header just enough to validate it and skip the unknown fields.
+struct ThriftReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl ThriftReader<'_> {
+    fn error() -> LoadBloomFilterError {
+        LoadBloomFilterError::Format(
+            "The split block bloom filter header is malformed or truncated.".to_owned(),
+        )
+    }
+
+    fn read_byte(&mut self) -> Result<u8, LoadBloomFilterError> {
+        let byte: u8 = *self.bytes.get(self.position).ok_or_else(Self::error)?;
+
+        self.position += 1;
+
+        Ok(byte)
+    }
+
+    fn skip_bytes(&mut self, len: u64) -> Result<(), LoadBloomFilterError> {
+        if len > (self.bytes.len() - self.position) as u64 {
+            return Err(Self::error());
+        }
+
+        self.position += len as usize;
+
+        Ok(())
+    }
+
+    fn read_varint(&mut self) -> Result<u64, LoadBloomFilterError> {
+        let mut value: u64 = 0;
+
+        for shift in (0..64).step_by(7) {
+            let byte: u8 = self.read_byte()?;
+
+            value |= u64::from(byte & 0x7f) << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(Self::error())
+    }
+
+    fn read_zigzag(&mut self) -> Result<i64, LoadBloomFilterError> {
+        let value: u64 = self.read_varint()?;
+
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    /// Reads the header of the next struct field, returns None on the stop field.
+    fn read_field(&mut self, last_id: i16) -> Result<Option<(i16, u8)>, LoadBloomFilterError> {
+        let byte: u8 = self.read_byte()?;
+
+        if byte == 0 {
+            return Ok(None);
+        }
+
+        let id: i16 = match byte >> 4 {
+            0 => self.read_zigzag()? as i16,
+            delta => last_id + delta as i16,
+        };
+
+        Ok(Some((id, byte & 0x0f)))
+    }
+
+    /// Skips the value of the given type.
+    fn skip(&mut self, thrift_type: u8, depth: usize) -> Result<(), LoadBloomFilterError> {
+        if depth > MAX_THRIFT_DEPTH {
+            return Err(Self::error());
+        }
+
+        match thrift_type {
+            // The booleans are stored in the field type itself.
+            1 | 2 => Ok(()),
+            3 => self.skip_bytes(1),
+            4..=6 => self.read_varint().map(|_| ()),
+            7 => self.skip_bytes(8),
+            8 => {
+                let len: u64 = self.read_varint()?;
+
+                self.skip_bytes(len)
+            }
+            9 | 10 => {
+                let byte: u8 = self.read_byte()?;
+                let len: u64 = match byte >> 4 {
+                    15 => self.read_varint()?,
+                    len => len as u64,
+                };
+
+                for _ in 0..len {
+                    // The booleans in the lists take a byte each.
+                    match byte & 0x0f {
+                        1 | 2 => self.skip_bytes(1)?,
+                        element_type => self.skip(element_type, depth + 1)?,
+                    }
+                }
+
+                Ok(())
+            }
+            11 => {
+                let len: u64 = self.read_varint()?;
+
+                if len > 0 {
+                    let types: u8 = self.read_byte()?;
+
+                    for _ in 0..len {
+                        self.skip(types >> 4, depth + 1)?;
+                        self.skip(types & 0x0f, depth + 1)?;
+                    }
+                }
+
+                Ok(())
+            }
+            THRIFT_STRUCT => {
+                let mut last_id: i16 = 0;
+
+                while let Some((id, field_type)) = self.read_field(last_id)? {
+                    self.skip(field_type, depth + 1)?;
+                    last_id = id;
+                }
+
+                Ok(())
+            }
+            _ => Err(Self::error()),
+        }
+    }
+
+    /// Reads the union and returns the id of its member, the member value is skipped.
+    fn read_union(&mut self) -> Result<i16, LoadBloomFilterError> {
+        let (id, member_type): (i16, u8) = self.read_field(0)?.ok_or_else(Self::error)?;
+
+        self.skip(member_type, 1)?;
+
+        match self.read_field(id)? {
+            None => Ok(id),
+            Some(_) => Err(Self::error()),
+        }
+    }
+}
+
+/// Parses the header and returns the bitset size with the header size.
+fn parse_header(bytes: &[u8]) -> Result<(usize, usize), LoadBloomFilterError> {
+    let mut reader: ThriftReader = ThriftReader { bytes, position: 0 };
+    let mut bitset_size: Option<i64> = None;
+    let mut unions: [Option<i16>; 3] = [None; 3];
+    let mut last_id: i16 = 0;
+
+    while let Some((id, field_type)) = reader.read_field(last_id)? {
+        match (id, field_type) {
+            (1, THRIFT_I32) => bitset_size = Some(reader.read_zigzag()?),
+            (2..=4, THRIFT_STRUCT) => unions[id as usize - 2] = Some(reader.read_union()?),
+            _ => reader.skip(field_type, 0)?,
+        }
+
+        last_id = id;
+    }
+
+    // The only algorithm, hash and compression the format defines are the member 1 of each union.
+    for (union, name) in unions.iter().zip(["algorithm", "hash", "compression"]) {
+        match union {
+            Some(1) => {}
+            Some(member) => {
+                return Err(LoadBloomFilterError::Format(format!(
+                    "The split block bloom filter {} {} is not supported.",
+                    name, member
+                )))
+            }
+            None => {
+                return Err(LoadBloomFilterError::Format(format!(
+                    "The split block bloom filter header has no {}.",
+                    name
+                )))
+            }
+        }
+    }
+
+    match bitset_size {
+        Some(bitset_size) if bitset_size > 0 && bitset_size <= MAX_BITSET_SIZE as i64 => {
+            Ok((bitset_size as usize, reader.position))
+        }
+        _ => Err(LoadBloomFilterError::Format(
+            "The split block bloom filter bitset size is missing or invalid.".to_owned(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SplitBlockBloomFilter;
+    use crate::LoadBloomFilterError;
+
+    /// Written by the parquet crate 54.3.1 for 10 distinct values with 0.01 false positive probability
+    /// after inserting "hello", "parquet", "John Green", 7_i64, 42_i64, -1_i32, 1.5_f64 and 2.5_f32.
+    const REFERENCE: &str = "15401c1c00001c1c00001c1c0000001441102480124420400c4048915228000b06000c94a04080100240da36101008";
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_sbbf_reference_vectors() {
+        let mut bloom_filter: SplitBlockBloomFilter = SplitBlockBloomFilter::new(10, 0.01).unwrap();
+
+        for value in ["hello", "parquet", "John Green"] {
+            bloom_filter.insert(value);
+        }
+
+        bloom_filter.insert(&7_i64);
+        bloom_filter.insert(&42_i64);
+        bloom_filter.insert(&-1_i32);
+        bloom_filter.insert(&1.5_f64);
+        bloom_filter.insert(&2.5_f32);
+
+        assert_eq!(hex(&bloom_filter.to_bytes()), REFERENCE);
+
+        let parsed: SplitBlockBloomFilter =
+            SplitBlockBloomFilter::from_bytes(&unhex(REFERENCE)).unwrap();
+
+        assert_eq!(parsed, bloom_filter);
+        assert!(parsed.is_probably_present("John Green"));
+        assert!(parsed.is_probably_present(&7_i64));
+        assert!(!parsed.is_probably_present(&8_i64));
+        assert!(!parsed.is_probably_present("absent"));
+        assert!(!parsed.is_probably_present("world"));
+
+        // The sizes the parquet crate picks, with the 15 bytes header in front of the bitset.
+        for (distinct_values, false_positive_probability, bitset_size) in [
+            (1_000, 0.01, 2_048),
+            (1_000_000, 0.01, 2_097_152),
+            (1, 0.5, 32),
+            (100_000, 0.001, 262_144),
+        ] {
+            assert_eq!(
+                SplitBlockBloomFilter::new(distinct_values, false_positive_probability)
+                    .unwrap()
+                    .bitset_size(),
+                bitset_size
+            );
+        }
+    }
+
+    #[test]
+    fn test_sbbf_from_values() {
+        let values: Vec<String> = (0..1000).map(|i| format!("item-{}", i)).collect();
+        let bloom_filter: SplitBlockBloomFilter =
+            SplitBlockBloomFilter::from_values(&values, 0.01).unwrap();
+
+        // The parquet crate gives 20 false positives of the next 10000 items.
+        let false_positives: usize = (1000..11000)
+            .filter(|i| bloom_filter.is_probably_present(&format!("item-{}", i)))
+            .count();
+
+        assert_eq!(bloom_filter.bitset_size(), 2048);
+        assert!(values
+            .iter()
+            .all(|value| bloom_filter.is_probably_present(value)));
+        assert_eq!(false_positives, 20);
+    }
+
+    #[test]
+    fn test_sbbf_invalid_header() {
+        let mut bytes: Vec<u8> = unhex(REFERENCE);
+
+        // The hash union member 2 instead of the xxHash one.
+        bytes[8] = 0x2c;
+
+        assert!(matches!(
+            SplitBlockBloomFilter::from_bytes(&bytes),
+            Err(LoadBloomFilterError::Format(_))
+        ));
+        assert!(matches!(
+            SplitBlockBloomFilter::from_bytes(&unhex(REFERENCE)[..40]),
+            Err(LoadBloomFilterError::Format(_))
+        ));
+        assert!(SplitBlockBloomFilter::from_bitset(&[0; 31]).is_err());
+
+        // An unknown field 5 before the stop field is skipped.
+        let mut bytes: Vec<u8> = unhex(REFERENCE);
+
+        bytes.splice(14..14, [0x18, 0x03, b'a', b'b', b'c']);
+
+        assert!(SplitBlockBloomFilter::from_bytes(&bytes)
+            .unwrap()
+            .is_probably_present("hello"));
+    }
+}
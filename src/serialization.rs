@@ -0,0 +1,589 @@
+//! The serde representation of the bloom filter, compact in any serde format.
+//!
+//! The bloom filter is serialized as a struct of three fields:
+//!
+//! | field            | value                                                                        |
+//! |------------------|------------------------------------------------------------------------------|
+//! | `format_version` | the version of this layout, SERDE_FORMAT_VERSION                             |
+//! | `params`         | the parameters: probability, bits, items, hashes, items added, key, salt...  |
+//! | `bits`           | the payload of the binary format, a byte string or base64 in JSON and alike  |
+//!
+//! The self-describing formats (JSON, CBOR, MessagePack with named fields) are read field by field, so they
+//! also accept the flat layout of the crate 0.2 with the `bitarray_naive` bit array and the fields
+//! the JSON files are saved with next to the bloom filter (`key`, `checksum` and `signature`).
+//!
+//! The secret key of a keyed bloom filter is not serialized, such bloom filters are deserialized
+//! as PendingBloomFilter and given their key back with pending_bloom_filter.with_key(...).
+
+use std::fmt;
+
+use base64::display::Base64Display;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bitarray_naive::BitArray;
+use serde::de::{self, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+
+use crate::format::{self, SERDE_FORMAT_VERSION, SIGNATURE_SIZE};
+use crate::hash::HASH_VERSION;
+use crate::{BloomFilter, HashKey, LoadBloomFilterError};
+
+const FIELDS: &[&str] = &["format_version", "params", "bits"];
+
+/// The parameters of the bloom filter, every field is always written for the formats without field names.
+#[derive(Serialize, Deserialize)]
+struct Params {
+    false_positive_probability: f32,
+    number_of_bits: u32,
+    items_count: u32,
+    number_of_hashes: u32,
+    items_added: u32,
+    #[serde(default)]
+    keyed: bool,
+    #[serde(default)]
+    salt: Option<u64>,
+    #[serde(default = "legacy_hash_version")]
+    hash_version: u32,
+}
+
+/// The bloom filters saved before the hash version was introduced use the first hashing scheme.
+fn legacy_hash_version() -> u32 {
+    1
+}
+
+/// Serializes the payload as a byte string, or as a base64 string streamed without a copy in the human-readable formats.
+struct Bits<'a>(&'a [u8]);
+
+impl Serialize for Bits<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(&Base64Display::new(self.0, &STANDARD))
+        } else {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+}
+
+/// The deserialized payload, accepted as a byte string, a base64 string or a sequence of bytes.
+/// The base64 string is decoded as a whole once the deserializer has buffered it.
+struct BitsBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for BitsBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(BitsVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(BitsVisitor)
+        }
+    }
+}
+
+struct BitsVisitor;
+
+impl<'de> Visitor<'de> for BitsVisitor {
+    type Value = BitsBuf;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("the bloom filter bits as bytes or a base64 string")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<BitsBuf, E> {
+        STANDARD
+            .decode(value)
+            .map(BitsBuf)
+            .map_err(|err| E::custom(format!("The bloom filter bits are not base64: {}.", err)))
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<BitsBuf, E> {
+        Ok(BitsBuf(value.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<BitsBuf, E> {
+        Ok(BitsBuf(value))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<BitsBuf, A::Error> {
+        let mut bits: Vec<u8> = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bits.push(byte);
+        }
+
+        Ok(BitsBuf(bits))
+    }
+}
+
+impl BloomFilter {
+    fn params(&self) -> Params {
+        Params {
+            false_positive_probability: self.false_positive_probability,
+            number_of_bits: self.number_of_bits,
+            items_count: self.items_count,
+            number_of_hashes: self.number_of_hashes,
+            items_added: self.items_added,
+            keyed: self.keyed,
+            salt: self.salt,
+            hash_version: self.hash_version,
+        }
+    }
+
+    /// Instantiates the bloom filter from the deserialized parameters and payload, validating the payload size.
+    /// The format and the hash versions are validated unless the caller reports them as typed errors itself.
+    fn from_params<E: de::Error>(
+        params: Params,
+        mut bits: Vec<u8>,
+        format_version: u16,
+        check_versions: bool,
+    ) -> Result<Self, E> {
+        if check_versions && format_version > SERDE_FORMAT_VERSION {
+            return Err(E::custom(format!(
+                "The bloom filter format version {} is not supported.",
+                format_version
+            )));
+        }
+
+        if check_versions && params.hash_version != HASH_VERSION {
+            return Err(E::custom(format!(
+                "The bloom filter hash version {} is not supported, querying it would give false negatives.",
+                params.hash_version
+            )));
+        }
+
+        // The bit indexes are calculated modulo the number of bits.
+        if params.number_of_bits == 0 || params.number_of_hashes == 0 {
+            return Err(E::custom(
+                "The bloom filter has no bits or no hash functions.",
+            ));
+        }
+
+        let payload_len: usize = format::payload_len(params.number_of_bits);
+
+        if bits.len() != payload_len {
+            return Err(E::custom(format!(
+                "The bloom filter of {} bits has {} bytes of bits, expected {}.",
+                params.number_of_bits,
+                bits.len(),
+                payload_len
+            )));
+        }
+
+        // The bit array keeps a spare byte when the number of bits is a multiple of 8.
+        bits.resize(params.number_of_bits as usize / 8 + 1, 0);
+
+        Ok(Self {
+            false_positive_probability: params.false_positive_probability,
+            number_of_bits: params.number_of_bits,
+            items_count: params.items_count,
+            number_of_hashes: params.number_of_hashes,
+            bit_array: BitArray {
+                size: params.number_of_bits as i64,
+                bit_array: bits,
+            },
+            items_added: params.items_added,
+            keyed: params.keyed,
+            key: None,
+            salt: params.salt,
+            hash_version: params.hash_version,
+            checksum: None,
+            signature: None,
+            signing_key: None,
+            format_version,
+        })
+    }
+}
+
+impl Serialize for BloomFilter {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("BloomFilter", FIELDS.len())?;
+
+        state.serialize_field("format_version", &SERDE_FORMAT_VERSION)?;
+        state.serialize_field("params", &self.params())?;
+        state.serialize_field("bits", &Bits(self.payload()))?;
+        state.end()
+    }
+}
+
+/// Fails for the keyed bloom filters serialized without their key, see PendingBloomFilter.
+impl<'de> Deserialize<'de> for BloomFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        PendingBloomFilter::deserialize(deserializer)?
+            .into_bloom_filter()
+            .map_err(|_| {
+                de::Error::custom(
+                    "The keyed bloom filter was serialized without its key, \
+                     it should be deserialized as PendingBloomFilter and given its key.",
+                )
+            })
+    }
+}
+
+/// The deserialized bloom filter that could be missing its secret key.
+///
+/// ```rust
+/// use bfilters::{BloomFilter, HashKey, PendingBloomFilter};
+///
+/// let key: HashKey = *b"0123456789abcdef";
+/// let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap().with_key(key).unwrap();
+///
+/// bloom_filter.insert("Vinegar");
+///
+/// let json: String = serde_json::to_string(&bloom_filter).unwrap();
+///
+/// assert!(serde_json::from_str::<BloomFilter>(&json).is_err());
+///
+/// let pending_bloom_filter: PendingBloomFilter = serde_json::from_str(&json).unwrap();
+/// let mut bloom_filter: BloomFilter = pending_bloom_filter.with_key(key).unwrap();
+///
+/// assert!(bloom_filter.is_probably_present("Vinegar"));
+/// ```
+pub struct PendingBloomFilter(pub(crate) BloomFilter);
+
+impl PendingBloomFilter {
+    /// Returns true if the bloom filter is a keyed one.
+    pub fn is_keyed(&self) -> bool {
+        self.0.keyed
+    }
+
+    /// Returns the keyed bloom filter with the secret key it was created with.
+    /// Fails if the bloom filter is not a keyed one.
+    pub fn with_key(self, key: HashKey) -> Result<BloomFilter, LoadBloomFilterError> {
+        self.0.with_loaded_key(Some(key))
+    }
+
+    /// Returns the bloom filter, fails if it is a keyed one serialized without its key.
+    pub fn into_bloom_filter(self) -> Result<BloomFilter, LoadBloomFilterError> {
+        self.0.with_loaded_key(None)
+    }
+}
+
+impl<'de> Deserialize<'de> for PendingBloomFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_struct(
+                "BloomFilter",
+                FIELDS,
+                BloomFilterVisitor {
+                    check_versions: true,
+                },
+            )
+            .map(PendingBloomFilter)
+    }
+}
+
+/// The bloom filter read from a saved file, whose format and hash versions are validated by the loading itself,
+/// so they are reported as LoadBloomFilterError::Format and LoadBloomFilterError::UnsupportedHashVersion.
+pub(crate) struct SavedPendingBloomFilter(pub(crate) BloomFilter);
+
+impl<'de> Deserialize<'de> for SavedPendingBloomFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_struct(
+                "BloomFilter",
+                FIELDS,
+                BloomFilterVisitor {
+                    check_versions: false,
+                },
+            )
+            .map(SavedPendingBloomFilter)
+    }
+}
+
+struct BloomFilterVisitor {
+    check_versions: bool,
+}
+
+impl<'de> Visitor<'de> for BloomFilterVisitor {
+    type Value = BloomFilter;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a bloom filter")
+    }
+
+    /// Reads the fields in order, the formats without field names have no legacy layout to accept.
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<BloomFilter, A::Error> {
+        let format_version: u16 = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+
+        if format_version != SERDE_FORMAT_VERSION {
+            return Err(de::Error::custom(format!(
+                "The bloom filter format version {} is not supported.",
+                format_version
+            )));
+        }
+
+        let params: Params = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let bits: BitsBuf = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+
+        BloomFilter::from_params(params, bits.0, format_version, self.check_versions)
+    }
+
+    /// Reads the current or the legacy layout, the newer format versions are rejected.
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<BloomFilter, A::Error> {
+        let mut format_version: Option<u16> = None;
+        let mut params: Option<Params> = None;
+        let mut bits: Option<BitsBuf> = None;
+        let mut false_positive_probability: Option<f32> = None;
+        let mut number_of_bits: Option<u32> = None;
+        let mut items_count: Option<u32> = None;
+        let mut number_of_hashes: Option<u32> = None;
+        let mut items_added: Option<u32> = None;
+        let mut keyed: Option<bool> = None;
+        let mut salt: Option<u64> = None;
+        let mut hash_version: Option<u32> = None;
+        let mut bit_array: Option<BitArray> = None;
+        let mut key: Option<HashKey> = None;
+        let mut checksum: Option<u32> = None;
+        let mut signature: Option<[u8; SIGNATURE_SIZE]> = None;
+
+        while let Some(field) = map.next_key::<String>()? {
+            match field.as_str() {
+                "format_version" => format_version = Some(map.next_value()?),
+                "params" => params = Some(map.next_value()?),
+                "bits" => bits = Some(map.next_value()?),
+                "false_positive_probability" => {
+                    false_positive_probability = Some(map.next_value()?)
+                }
+                "number_of_bits" => number_of_bits = Some(map.next_value()?),
+                "items_count" => items_count = Some(map.next_value()?),
+                "number_of_hashes" => number_of_hashes = Some(map.next_value()?),
+                "items_added" => items_added = Some(map.next_value()?),
+                "keyed" => keyed = Some(map.next_value()?),
+                "salt" => salt = map.next_value()?,
+                "hash_version" => hash_version = Some(map.next_value()?),
+                "bit_array" => bit_array = Some(map.next_value()?),
+                "key" => key = map.next_value()?,
+                "checksum" => checksum = map.next_value()?,
+                "signature" => signature = map.next_value()?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        let params: Params = match params {
+            Some(params) => params,
+            None => Params {
+                false_positive_probability: false_positive_probability
+                    .ok_or_else(|| de::Error::missing_field("false_positive_probability"))?,
+                number_of_bits: number_of_bits
+                    .ok_or_else(|| de::Error::missing_field("number_of_bits"))?,
+                items_count: items_count.ok_or_else(|| de::Error::missing_field("items_count"))?,
+                number_of_hashes: number_of_hashes
+                    .ok_or_else(|| de::Error::missing_field("number_of_hashes"))?,
+                items_added: items_added.ok_or_else(|| de::Error::missing_field("items_added"))?,
+                keyed: keyed.unwrap_or_default(),
+                salt,
+                hash_version: hash_version.unwrap_or_else(legacy_hash_version),
+            },
+        };
+
+        let bits: Vec<u8> = match (bits, bit_array) {
+            (Some(bits), _) => bits.0,
+            (None, Some(mut bit_array)) => {
+                bit_array
+                    .bit_array
+                    .truncate(format::payload_len(params.number_of_bits));
+                bit_array.bit_array
+            }
+            (None, None) => return Err(de::Error::missing_field("bits")),
+        };
+
+        Ok(BloomFilter {
+            key,
+            checksum,
+            signature,
+            ..BloomFilter::from_params(
+                params,
+                bits,
+                format_version.unwrap_or_default(),
+                self.check_versions,
+            )?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::PendingBloomFilter;
+    use crate::{BloomFilter, HashKey, LoadBloomFilterError};
+
+    /// A user struct embedding the bloom filter.
+    #[derive(Serialize, Deserialize)]
+    struct Embedded {
+        name: String,
+        bloom_filter: BloomFilter,
+    }
+
+    fn assert_round_trip(name: &str, bytes: &[u8], embedded: Embedded, payload_len: usize) {
+        let mut bloom_filter: BloomFilter = embedded.bloom_filter;
+
+        assert_eq!(embedded.name, "tokens", "{}", name);
+        assert!(bloom_filter.is_probably_present("John Green"), "{}", name);
+        assert!(!bloom_filter.is_probably_present("John White"), "{}", name);
+        assert_eq!(bloom_filter.salt(), Some(42), "{}", name);
+        assert_eq!(bloom_filter.items_added(), 1, "{}", name);
+        assert!(
+            bytes.len() < payload_len + 256,
+            "{}: {} bytes for {} bytes of bits",
+            name,
+            bytes.len(),
+            payload_len
+        );
+    }
+
+    #[test]
+    fn test_serde_formats_round_trip() {
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 10_000)
+            .unwrap()
+            .with_salt(42)
+            .unwrap();
+
+        bloom_filter.insert("John Green");
+
+        let payload_len: usize = bloom_filter.payload().len();
+        let embedded: Embedded = Embedded {
+            name: "tokens".to_owned(),
+            bloom_filter,
+        };
+
+        let bincode: Vec<u8> = bincode::serialize(&embedded).unwrap();
+        assert_round_trip(
+            "bincode",
+            &bincode,
+            bincode::deserialize(&bincode).unwrap(),
+            payload_len,
+        );
+
+        let mut cbor: Vec<u8> = Vec::new();
+        ciborium::into_writer(&embedded, &mut cbor).unwrap();
+        assert_round_trip(
+            "cbor",
+            &cbor,
+            ciborium::from_reader(&cbor[..]).unwrap(),
+            payload_len,
+        );
+
+        let msgpack: Vec<u8> = rmp_serde::to_vec(&embedded).unwrap();
+        assert_round_trip(
+            "msgpack",
+            &msgpack,
+            rmp_serde::from_slice(&msgpack).unwrap(),
+            payload_len,
+        );
+
+        let msgpack_named: Vec<u8> = rmp_serde::to_vec_named(&embedded).unwrap();
+        assert_round_trip(
+            "msgpack named",
+            &msgpack_named,
+            rmp_serde::from_slice(&msgpack_named).unwrap(),
+            payload_len,
+        );
+
+        // The base64 takes 4 characters for every 3 bytes.
+        let json: String = serde_json::to_string(&embedded).unwrap();
+        assert_round_trip(
+            "json",
+            json.as_bytes(),
+            serde_json::from_str(&json).unwrap(),
+            payload_len * 4 / 3,
+        );
+        assert!(json.contains(r#""format_version":2"#));
+    }
+
+    #[test]
+    fn test_serde_keyed_round_trip() {
+        let key: HashKey = *b"0123456789abcdef";
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_key(key)
+            .unwrap();
+
+        bloom_filter.insert("John Green");
+
+        let json: String = serde_json::to_string(&bloom_filter).unwrap();
+        let bincode: Vec<u8> = bincode::serialize(&bloom_filter).unwrap();
+
+        assert!(serde_json::from_str::<BloomFilter>(&json).is_err());
+        assert!(bincode::deserialize::<BloomFilter>(&bincode).is_err());
+
+        let pending_bloom_filter: PendingBloomFilter = serde_json::from_str(&json).unwrap();
+
+        assert!(pending_bloom_filter.is_keyed());
+        assert!(matches!(
+            pending_bloom_filter.into_bloom_filter(),
+            Err(LoadBloomFilterError::MissingKey)
+        ));
+
+        for mut bloom_filter in [
+            serde_json::from_str::<PendingBloomFilter>(&json).unwrap(),
+            bincode::deserialize::<PendingBloomFilter>(&bincode).unwrap(),
+        ]
+        .map(|pending_bloom_filter| pending_bloom_filter.with_key(key).unwrap())
+        {
+            assert!(bloom_filter.is_probably_present("John Green"));
+            assert!(!bloom_filter.is_probably_present("John White"));
+        }
+
+        let unkeyed: String =
+            serde_json::to_string(&BloomFilter::new(Some(0.01), 1000).unwrap()).unwrap();
+
+        assert!(matches!(
+            serde_json::from_str::<PendingBloomFilter>(&unkeyed)
+                .unwrap()
+                .with_key(key),
+            Err(LoadBloomFilterError::UnexpectedKey)
+        ));
+    }
+
+    #[test]
+    fn test_serde_invalid_bits() {
+        let json: String = serde_json::to_string(&BloomFilter::new(Some(0.01), 1000).unwrap())
+            .unwrap()
+            .replace(r#""bits":""#, r#""bits":"AAAA"#);
+
+        assert!(serde_json::from_str::<BloomFilter>(&json).is_err());
+        assert!(serde_json::from_str::<BloomFilter>(r#"{"bits":"*"}"#).is_err());
+    }
+
+    #[test]
+    fn test_serde_unsupported_params() {
+        let json: serde_json::Value =
+            serde_json::to_value(BloomFilter::new(Some(0.01), 1000).unwrap()).unwrap();
+
+        for (pointer, value) in [
+            ("/format_version", serde_json::json!(99)),
+            ("/params/hash_version", serde_json::json!(2)),
+            ("/params/number_of_hashes", serde_json::json!(0)),
+        ] {
+            let mut json: serde_json::Value = json.clone();
+
+            *json.pointer_mut(pointer).unwrap() = value;
+
+            assert!(
+                serde_json::from_value::<BloomFilter>(json.clone()).is_err(),
+                "{}",
+                pointer
+            );
+            assert!(
+                serde_json::from_value::<PendingBloomFilter>(json).is_err(),
+                "{}",
+                pointer
+            );
+        }
+
+        let mut json: serde_json::Value = json;
+
+        json["params"]["number_of_bits"] = serde_json::json!(0);
+        json["bits"] = serde_json::json!("");
+
+        assert!(serde_json::from_value::<BloomFilter>(json).is_err());
+    }
+}
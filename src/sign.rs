@@ -0,0 +1,98 @@
+//! HMAC-SHA256 signatures of the saved bloom filters.
+//!
+//! The signature covers the bloom filter parameters and bits, so a file altered by anyone without
+//! the signing key (e.g. with some bits cleared to let the blocked items through) is rejected on loading.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::format::{Header, SIGNATURE_SIZE};
+use crate::{LoadBloomFilterError, SigningKey};
+
+pub(crate) type HmacSha256 = Hmac<Sha256>;
+
+/// Starts the HMAC-SHA256 of the header parameters, the payload is fed to it afterwards.
+pub(crate) fn header_mac(key: &SigningKey, header: &Header) -> HmacSha256 {
+    let mut mac: HmacSha256 =
+        HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+
+    mac.update(&header.canonical_bytes());
+    mac
+}
+
+fn mac(key: &SigningKey, header: &Header, payload: &[u8]) -> HmacSha256 {
+    let mut mac: HmacSha256 = header_mac(key, header);
+
+    mac.update(payload);
+    mac
+}
+
+/// Calculates the HMAC-SHA256 tag of the header parameters and the given payload.
+pub(crate) fn calc_signature(
+    key: &SigningKey,
+    header: &Header,
+    payload: &[u8],
+) -> [u8; SIGNATURE_SIZE] {
+    mac(key, header, payload).finalize().into_bytes().into()
+}
+
+/// Validates the tag the bloom filter was saved with in constant time.
+pub(crate) fn verify_signature(
+    key: &SigningKey,
+    header: &Header,
+    payload: &[u8],
+    signature: Option<&[u8]>,
+) -> Result<(), LoadBloomFilterError> {
+    verify_mac(mac(key, header, payload), signature)
+}
+
+/// Validates the tag the bloom filter was saved with against the HMAC fed with its header and payload.
+pub(crate) fn verify_mac(
+    mac: HmacSha256,
+    signature: Option<&[u8]>,
+) -> Result<(), LoadBloomFilterError> {
+    let signature: &[u8] = signature.ok_or(LoadBloomFilterError::MissingSignature)?;
+
+    mac.verify_slice(signature)
+        .map_err(|_| LoadBloomFilterError::InvalidSignature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{calc_signature, verify_signature};
+    use crate::format::Header;
+    use crate::{LoadBloomFilterError, SigningKey, HASH_VERSION};
+
+    #[test]
+    fn test_verify_signature() {
+        let key: SigningKey = [7; 32];
+        let header: Header = Header {
+            flags: 0,
+            hash_version: HASH_VERSION,
+            false_positive_probability: 0.01,
+            number_of_bits: 9,
+            items_count: 1,
+            number_of_hashes: 6,
+            items_added: 1,
+            salt: None,
+            checksum: None,
+            compression: None,
+        };
+        let payload: [u8; 2] = [0xff, 0x01];
+        let signature: [u8; 32] = calc_signature(&key, &header, &payload);
+
+        assert!(verify_signature(&key, &header, &payload, Some(&signature)).is_ok());
+        assert!(matches!(
+            verify_signature(&key, &header, &[0xff, 0x00], Some(&signature)),
+            Err(LoadBloomFilterError::InvalidSignature)
+        ));
+        assert!(matches!(
+            verify_signature(&[8; 32], &header, &payload, Some(&signature)),
+            Err(LoadBloomFilterError::InvalidSignature)
+        ));
+        assert!(matches!(
+            verify_signature(&key, &header, &payload, None),
+            Err(LoadBloomFilterError::MissingSignature)
+        ));
+    }
+}
@@ -0,0 +1,363 @@
+//! Streaming serialization of the bloom filter through bounded buffers with progress reporting.
+//!
+//! The binary payload is copied in chunks straight between the bit array and the reader or writer,
+//! so no serialized copy of a multi-GB bloom filter is held in memory. The JSON is written incrementally too,
+//! but its base64 bits are parsed as a whole string, so reading it holds the encoded and the decoded bits at once.
+
+use std::fs::File;
+use std::io::{self, BufReader, Chain, Read, Write};
+use std::path::Path;
+
+use crate::format::{FileFormat, Header, HEADER_SIZE, MAGIC, SIGNATURE_SIZE};
+use crate::serialization::SavedPendingBloomFilter;
+use crate::{
+    compress, persist, BloomFilter, LoadBloomFilterError, LoadOptions, SaveBloomFilterError,
+};
+
+/// The number of payload bytes written or read at once, also the minimal step of the progress reports.
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Counts the bytes going through the writer and reports them at most once per chunk.
+struct ProgressWriter<W: Write, F: FnMut(u64, u64)> {
+    inner: W,
+    done: u64,
+    reported: u64,
+    total: u64,
+    progress: F,
+}
+
+impl<W: Write, F: FnMut(u64, u64)> ProgressWriter<W, F> {
+    /// Reports the final progress, the total not known upfront is the number of bytes written.
+    fn finish(mut self) -> io::Result<()> {
+        let total: u64 = match self.total {
+            0 => self.done,
+            total => total,
+        };
+
+        self.inner.flush()?;
+        (self.progress)(self.done, total);
+
+        Ok(())
+    }
+}
+
+impl<W: Write, F: FnMut(u64, u64)> Write for ProgressWriter<W, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written: usize = self.inner.write(buf)?;
+
+        self.done += written as u64;
+
+        if self.done - self.reported >= CHUNK_SIZE as u64 {
+            self.reported = self.done;
+            (self.progress)(self.done, self.total);
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Counts the bytes going through the reader and reports them at most once per chunk.
+struct ProgressReader<R: Read, F: FnMut(u64, u64)> {
+    inner: R,
+    done: u64,
+    reported: u64,
+    total: u64,
+    progress: F,
+}
+
+impl<R: Read, F: FnMut(u64, u64)> ProgressReader<R, F> {
+    fn finish(mut self) {
+        (self.progress)(self.done, self.total);
+    }
+}
+
+impl<R: Read, F: FnMut(u64, u64)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read: usize = self.inner.read(buf)?;
+
+        self.done += read as u64;
+
+        if self.done - self.reported >= CHUNK_SIZE as u64 {
+            self.reported = self.done;
+            (self.progress)(self.done, self.total);
+        }
+
+        Ok(read)
+    }
+}
+
+impl BloomFilter {
+    /// Writes the bloom filter in the given format to the writer through a bounded buffer.
+    /// The progress callback receives the number of bytes written and the total number of bytes,
+    /// which is known upfront for the binary format only and is 0 for JSON until the final report.
+    ///
+    /// ```rust
+    /// use bfilters::{BloomFilter, FileFormat, LoadOptions};
+    ///
+    /// let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+    /// let mut bytes: Vec<u8> = Vec::new();
+    ///
+    /// bloom_filter.insert("Vinegar");
+    /// bloom_filter
+    ///     .write_to(&mut bytes, FileFormat::Binary, |done, total| println!("{}/{}", done, total))
+    ///     .unwrap();
+    ///
+    /// let mut loaded_bloom_filter: BloomFilter =
+    ///     BloomFilter::read_from(&bytes[..], LoadOptions::new(), |_, _| ()).unwrap();
+    ///
+    /// assert!(loaded_bloom_filter.is_probably_present("Vinegar"));
+    /// ```
+    pub fn write_to<W: Write, F: FnMut(u64, u64)>(
+        &self,
+        writer: W,
+        format: FileFormat,
+        progress: F,
+    ) -> Result<(), SaveBloomFilterError> {
+        let total: u64 = match format {
+            FileFormat::Json => 0,
+            FileFormat::Binary => self.binary_header().total_len() as u64,
+        };
+
+        let mut writer: ProgressWriter<W, F> = ProgressWriter {
+            inner: writer,
+            done: 0,
+            reported: 0,
+            total,
+            progress,
+        };
+
+        match format {
+            FileFormat::Json => self.write_json(&mut writer, None)?,
+            FileFormat::Binary => self.write_binary(&mut writer)?,
+        }
+
+        Ok(writer.finish()?)
+    }
+
+    /// Writes the binary layout chunk by chunk, the same bytes as bloom_filter.to_bytes() returns.
+    pub(crate) fn write_binary<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.binary_header().to_bytes())?;
+
+        for chunk in self.payload().chunks(CHUNK_SIZE) {
+            writer.write_all(chunk)?;
+        }
+
+        if let Some(signature) = self.calc_signature() {
+            writer.write_all(&signature)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the bloom filter in any format from the reader, the binary one through a bounded buffer.
+    /// The progress callback receives the number of bytes read and the total number of bytes,
+    /// which is known upfront for the binary bloom filters only and is 0 for the JSON ones.
+    pub fn read_from<R: Read, F: FnMut(u64, u64)>(
+        reader: R,
+        options: LoadOptions,
+        progress: F,
+    ) -> Result<Self, LoadBloomFilterError> {
+        Self::read_with_total(reader, None, options, progress)
+    }
+
+    fn read_with_total<R: Read, F: FnMut(u64, u64)>(
+        mut reader: R,
+        total: Option<u64>,
+        options: LoadOptions,
+        progress: F,
+    ) -> Result<Self, LoadBloomFilterError> {
+        let mut magic: [u8; MAGIC.len()] = [0; MAGIC.len()];
+        let mut magic_len: usize = 0;
+
+        // A pipe or a socket could return fewer bytes than the magic at first.
+        while magic_len < magic.len() {
+            match reader.read(&mut magic[magic_len..]) {
+                Ok(0) => break,
+                Ok(read) => magic_len += read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let format: FileFormat = FileFormat::detect(&magic[..magic_len]);
+        let reader: BufReader<Chain<&[u8], R>> =
+            BufReader::with_capacity(CHUNK_SIZE, magic[..magic_len].chain(reader));
+
+        let mut reader: ProgressReader<BufReader<Chain<&[u8], R>>, F> = ProgressReader {
+            inner: reader,
+            done: 0,
+            reported: 0,
+            total: total.unwrap_or_default(),
+            progress,
+        };
+
+        let bloom_filter: Self = match format {
+            FileFormat::Json => serde_json::from_reader::<_, SavedPendingBloomFilter>(&mut reader)?
+                .0
+                .check_format_version()?,
+            FileFormat::Binary => {
+                let mut header_bytes: [u8; HEADER_SIZE] = [0; HEADER_SIZE];
+
+                reader.read_exact(&mut header_bytes)?;
+
+                let header: Header = Header::parse_header(&header_bytes)?;
+                let mut bloom_filter: Self = Self::from_header(&header, &[]);
+
+                reader.total = total.unwrap_or(header.total_len() as u64);
+
+                let payload: &mut [u8] =
+                    &mut bloom_filter.bit_array.bit_array[..header.payload_len()];
+
+                if header.compression.is_some() {
+                    compress::read_compressed(&mut reader, &header, payload)?;
+                } else {
+                    for chunk in payload.chunks_mut(CHUNK_SIZE) {
+                        reader.read_exact(chunk)?;
+                    }
+                }
+
+                if header.is_signed() {
+                    let mut signature: [u8; SIGNATURE_SIZE] = [0; SIGNATURE_SIZE];
+
+                    reader.read_exact(&mut signature)?;
+                    bloom_filter.signature = Some(signature);
+                }
+
+                bloom_filter
+            }
+        };
+
+        reader.finish();
+
+        bloom_filter.verify(&options)?.with_loaded_key(options.key)
+    }
+
+    /// Atomically saves the bloom filter in the given format, reporting the progress of writing.
+    pub fn save_with_progress<P: AsRef<Path>, F: FnMut(u64, u64)>(
+        &self,
+        path: P,
+        format: FileFormat,
+        progress: F,
+    ) -> Result<(), SaveBloomFilterError> {
+        persist::write_atomic_with(path.as_ref(), |writer| {
+            self.write_to(writer, format, progress)
+        })
+    }
+
+    /// Loads the bloom filter in any format from the file, reporting the progress of reading.
+    pub fn from_file_with_progress<P: AsRef<Path>, F: FnMut(u64, u64)>(
+        path: P,
+        options: LoadOptions,
+        progress: F,
+    ) -> Result<Self, LoadBloomFilterError> {
+        let file: File = File::open(path)?;
+        let total: u64 = file.metadata()?.len();
+
+        Self::read_with_total(file, Some(total), options, progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{self, Read};
+    use std::path::Path;
+
+    use crate::{BloomFilter, FileFormat, LoadBloomFilterError, LoadOptions};
+
+    #[test]
+    fn test_stream_progress() {
+        let item: &str = "John Green";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1_000_000)
+            .unwrap()
+            .with_salt(42)
+            .unwrap();
+
+        bloom_filter.insert(item);
+
+        for (format, tmp_save_path) in [
+            (FileFormat::Binary, Path::new("./bfilter_stream.bin")),
+            (FileFormat::Json, Path::new("./bfilter_stream.json")),
+        ] {
+            let mut saved: Vec<(u64, u64)> = Vec::new();
+            let mut loaded: Vec<(u64, u64)> = Vec::new();
+
+            bloom_filter
+                .save_with_progress(tmp_save_path, format, |done, total| {
+                    saved.push((done, total))
+                })
+                .unwrap();
+
+            let mut loaded_bloom_filter: BloomFilter = BloomFilter::from_file_with_progress(
+                tmp_save_path,
+                LoadOptions::new(),
+                |done, total| loaded.push((done, total)),
+            )
+            .unwrap();
+            let file_len: u64 = fs::metadata(tmp_save_path).unwrap().len();
+
+            fs::remove_file(tmp_save_path).unwrap();
+
+            assert!(loaded_bloom_filter.is_probably_present(item));
+            assert!(saved.len() > 1);
+            assert!(loaded.len() > 1);
+            assert!(saved.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+            assert_eq!(saved.last(), Some(&(file_len, file_len)));
+            assert!(saved[..saved.len() - 1]
+                .iter()
+                .all(|(_, total)| *total == file_len || format == FileFormat::Json && *total == 0));
+            assert_eq!(loaded.last(), Some(&(file_len, file_len)));
+        }
+    }
+
+    /// A reader returning a single byte at a time, like a slow pipe.
+    struct ByteByByte<'a>(&'a [u8]);
+
+    impl Read for ByteByByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0
+                .take(1)
+                .read(buf)
+                .inspect(|read| self.0 = &self.0[*read..])
+        }
+    }
+
+    #[test]
+    fn test_read_from_short_reads() {
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        bloom_filter.insert("John Green");
+
+        for format in [FileFormat::Binary, FileFormat::Json] {
+            let bytes: Vec<u8> = bloom_filter.serialize(format).unwrap();
+            let mut loaded_bloom_filter: BloomFilter =
+                BloomFilter::read_from(ByteByByte(&bytes), LoadOptions::new(), |_, _| ()).unwrap();
+
+            assert!(loaded_bloom_filter.is_probably_present("John Green"));
+        }
+    }
+
+    #[test]
+    fn test_read_from_truncated() {
+        let mut bytes: Vec<u8> = Vec::new();
+
+        BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .write_to(&mut bytes, FileFormat::Binary, |_, _| ())
+            .unwrap();
+
+        assert_eq!(
+            bytes,
+            BloomFilter::new(Some(0.01), 1000).unwrap().to_bytes()
+        );
+        assert!(matches!(
+            BloomFilter::read_from(&bytes[..bytes.len() - 1], LoadOptions::new(), |_, _| ()),
+            Err(LoadBloomFilterError::Io(_))
+        ));
+    }
+}
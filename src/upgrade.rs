@@ -0,0 +1,154 @@
+//! Conversion of the bloom filter files written by the older versions of the crate into the current formats.
+//!
+//! The legacy JSON files of the crate 0.2 (the serde JSON of the bloom filter with the `bitarray_naive`
+//! bit array and no `format_version` field) are still loaded by BloomFilter::from_file(...) as they are.
+//! Upgrading rewrites them in the current JSON or binary layout with the same parameters and bits.
+
+use std::path::Path;
+
+use crate::{
+    persist, BloomFilter, FileFormat, LoadBloomFilterError, LoadOptions, SaveBloomFilterError,
+};
+
+/// The error that can be returned on BloomFilter::upgrade_file.
+#[derive(Debug)]
+pub enum UpgradeBloomFilterError {
+    /// The bloom filter could not be loaded from the original file.
+    Load(LoadBloomFilterError),
+    /// The upgraded bloom filter could not be saved.
+    Save(SaveBloomFilterError),
+    /// The upgraded bloom filter does not read back with the same parameters and bits, nothing was written.
+    Mismatch,
+}
+
+impl From<LoadBloomFilterError> for UpgradeBloomFilterError {
+    fn from(err: LoadBloomFilterError) -> Self {
+        UpgradeBloomFilterError::Load(err)
+    }
+}
+
+impl From<SaveBloomFilterError> for UpgradeBloomFilterError {
+    fn from(err: SaveBloomFilterError) -> Self {
+        UpgradeBloomFilterError::Save(err)
+    }
+}
+
+impl BloomFilter {
+    /// Loads the bloom filter file in any format, including the legacy JSON one, and atomically saves it
+    /// in the current version of the given format. The upgraded path could be the same as the original one.
+    ///
+    /// The bits are copied to the same positions. Before the upgraded file is written, its bytes are loaded back
+    /// and compared with the original bloom filter, so a conversion changing any parameter or bit writes nothing.
+    ///
+    /// ```rust
+    /// use std::fs;
+    /// use bfilters::{BloomFilter, FileFormat, LoadOptions};
+    ///
+    /// let legacy: &str = r#"{"false_positive_probability":0.01,"number_of_bits":95,"items_count":10,
+    ///     "number_of_hashes":6,"bit_array":{"size":95,"bit_array":[0,0,0,68,0,0,128,72,85,1,0,17]},"items_added":2}"#;
+    ///
+    /// fs::write("./bfilter_upgrade_doc.json", legacy).unwrap();
+    ///
+    /// let bloom_filter: BloomFilter = BloomFilter::upgrade_file(
+    ///     "./bfilter_upgrade_doc.json",
+    ///     "./bfilter_upgrade_doc.bin",
+    ///     FileFormat::Binary,
+    ///     LoadOptions::new(),
+    /// )
+    /// .unwrap();
+    ///
+    /// assert!(bloom_filter.is_legacy());
+    /// assert!(!BloomFilter::from_file("./bfilter_upgrade_doc.bin").unwrap().is_legacy());
+    ///
+    /// fs::remove_file("./bfilter_upgrade_doc.json").unwrap();
+    /// fs::remove_file("./bfilter_upgrade_doc.bin").unwrap();
+    /// ```
+    pub fn upgrade_file<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        upgraded_path: Q,
+        format: FileFormat,
+        options: LoadOptions,
+    ) -> Result<BloomFilter, UpgradeBloomFilterError> {
+        let bloom_filter: BloomFilter = BloomFilter::from_file_with_options(path, options)?;
+        let bytes: Vec<u8> = bloom_filter.serialize(format)?;
+        let upgraded: BloomFilter = BloomFilter::from_bytes_with_options(&bytes, options)?;
+
+        if upgraded.header() != bloom_filter.header()
+            || upgraded.payload() != bloom_filter.payload()
+        {
+            return Err(UpgradeBloomFilterError::Mismatch);
+        }
+
+        persist::write_atomic(upgraded_path.as_ref(), &bytes)?;
+
+        Ok(bloom_filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use crate::format::SERDE_FORMAT_VERSION;
+    use crate::{BloomFilter, FileFormat, LoadBloomFilterError, LoadOptions};
+
+    /// Saved by bfilters 0.2.0 with "John Green" and "John White" inserted.
+    const LEGACY_JSON: &str = r#"{"false_positive_probability":0.01,"number_of_bits":95,"items_count":10,"number_of_hashes":6,"bit_array":{"size":95,"bit_array":[0,0,0,68,0,0,128,72,85,1,0,17]},"items_added":2}"#;
+
+    const LEGACY_BITS: [u8; 12] = [0, 0, 0, 68, 0, 0, 128, 72, 85, 1, 0, 17];
+
+    #[test]
+    fn test_upgrade_legacy_json() {
+        let legacy_path: &Path = Path::new("./bfilter_upgrade.json");
+
+        fs::write(legacy_path, LEGACY_JSON).unwrap();
+
+        let mut legacy: BloomFilter = BloomFilter::from_file(legacy_path).unwrap();
+
+        assert!(legacy.is_legacy());
+        assert!(legacy.is_probably_present("John Green"));
+
+        for (format, upgraded_path) in [
+            (FileFormat::Binary, Path::new("./bfilter_upgrade.bin")),
+            (FileFormat::Json, legacy_path),
+        ] {
+            BloomFilter::upgrade_file(legacy_path, upgraded_path, format, LoadOptions::new())
+                .unwrap();
+
+            let mut upgraded: BloomFilter = BloomFilter::from_file(upgraded_path).unwrap();
+
+            assert!(!upgraded.is_legacy());
+            assert_eq!(upgraded.payload(), &LEGACY_BITS[..]);
+            assert_eq!(upgraded.number_of_bits(), 95);
+            assert_eq!(upgraded.items_added(), 2);
+            assert!(upgraded.is_probably_present("John Green"));
+            assert!(upgraded.is_probably_present("John White"));
+        }
+
+        let upgraded_json: String = fs::read_to_string(legacy_path).unwrap();
+
+        fs::remove_file(legacy_path).unwrap();
+        fs::remove_file("./bfilter_upgrade.bin").unwrap();
+
+        assert!(upgraded_json.contains(&format!(r#""format_version":{}"#, SERDE_FORMAT_VERSION)));
+    }
+
+    #[test]
+    fn test_newer_json_format_version() {
+        let newer_json: String = format!(
+            r#"{},"format_version":{}}}"#,
+            &LEGACY_JSON[..LEGACY_JSON.len() - 1],
+            SERDE_FORMAT_VERSION + 1
+        );
+
+        assert!(matches!(
+            BloomFilter::from_bytes(newer_json.as_bytes()),
+            Err(LoadBloomFilterError::Format(_))
+        ));
+        assert!(matches!(
+            BloomFilter::read_from(newer_json.as_bytes(), LoadOptions::new(), |_, _| ()),
+            Err(LoadBloomFilterError::Format(_))
+        ));
+    }
+}
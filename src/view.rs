@@ -0,0 +1,271 @@
+//! A zero-copy read-only view over a bloom filter serialized in the binary format.
+
+use crate::format::{self, Header, HEADER_SIZE};
+use crate::hash;
+use crate::sign;
+use crate::{BloomFilter, HashKey, LoadBloomFilterError, LoadOptions};
+
+/// A read-only bloom filter borrowing the bytes written by bloom_filter.to_bytes() or bloom_filter.save_binary(...),
+/// e.g. embedded into another file or a network message. Nothing is copied or allocated to query it.
+///
+/// ```rust
+/// use bfilters::{BloomFilter, BloomFilterRef};
+///
+/// let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+///
+/// bloom_filter.insert("Vinegar");
+///
+/// let bytes: Vec<u8> = bloom_filter.to_bytes();
+/// let bloom_filter_ref: BloomFilterRef = BloomFilterRef::new(&bytes).unwrap();
+///
+/// assert!(bloom_filter_ref.is_probably_present("Vinegar"));
+/// assert!(!bloom_filter_ref.is_probably_present("Coke"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct BloomFilterRef<'a> {
+    header: Header,
+    payload: &'a [u8],
+    key: Option<HashKey>,
+}
+
+impl<'a> BloomFilterRef<'a> {
+    /// Validates the binary header at the beginning of the bytes and wraps the bit payload following it.
+    /// The bytes could continue after the payload, see bloom_filter_ref.len().
+    pub fn new(bytes: &'a [u8]) -> Result<Self, LoadBloomFilterError> {
+        Self::new_with_options(bytes, LoadOptions::new())
+    }
+
+    /// Same as BloomFilterRef::new for the keyed bloom filter with the secret key it was created with.
+    pub fn new_with_key(bytes: &'a [u8], key: HashKey) -> Result<Self, LoadBloomFilterError> {
+        Self::new_with_options(bytes, LoadOptions::new().with_key(key))
+    }
+
+    /// Same as BloomFilterRef::new with the given options, e.g. to skip the checksum verification.
+    pub fn new_with_options(
+        bytes: &'a [u8],
+        options: LoadOptions,
+    ) -> Result<Self, LoadBloomFilterError> {
+        let header: Header = Header::parse(bytes)?;
+
+        if header.compression.is_some() {
+            return Err(LoadBloomFilterError::Format(
+                "The compressed bloom filter could not be read in place, it should be loaded with BloomFilter::from_file(...)."
+                    .to_owned(),
+            ));
+        }
+
+        let payload: &'a [u8] = &bytes[HEADER_SIZE..HEADER_SIZE + header.payload_len()];
+
+        header.check_key(options.key.as_ref())?;
+
+        if options.verify_checksum {
+            header.verify_checksum(payload)?;
+        }
+
+        if let Some(signing_key) = &options.signing_key {
+            let signature: Option<&[u8]> =
+                Some(&bytes[HEADER_SIZE + payload.len()..header.total_len()])
+                    .filter(|_| header.is_signed());
+
+            sign::verify_signature(signing_key, &header, payload, signature)?;
+        }
+
+        Ok(Self::from_parts(header, payload, options.key))
+    }
+
+    /// Wraps the already validated header and payload.
+    pub(crate) fn from_parts(header: Header, payload: &'a [u8], key: Option<HashKey>) -> Self {
+        Self {
+            header,
+            payload,
+            key,
+        }
+    }
+
+    /// Returns the validated header.
+    pub(crate) fn into_header(self) -> Header {
+        self.header
+    }
+
+    /// Returns the number of bytes the serialized bloom filter occupies (header, payload and signature).
+    pub fn len(&self) -> usize {
+        self.header.total_len()
+    }
+
+    /// Always false, the serialized bloom filter contains at least the header.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Given the negative or false positive answer about the item presence in the bloom filter.
+    pub fn is_probably_present(&self, item: &str) -> bool {
+        let header: &Header = &self.header;
+
+        (0..header.number_of_hashes).all(|seed| {
+            let index: u64 = hash::calc_bit_index(
+                item.as_bytes(),
+                seed,
+                header.number_of_bits,
+                self.key.as_ref(),
+                header.salt,
+            );
+
+            format::is_bit_set(self.payload, index)
+        })
+    }
+
+    /// Returns the false positive probability the bloom filter was configured with.
+    pub fn false_positive_probability(&self) -> f32 {
+        self.header.false_positive_probability
+    }
+
+    /// Returns the number of bits in the bloom filter's bit array.
+    pub fn number_of_bits(&self) -> u32 {
+        self.header.number_of_bits
+    }
+
+    /// Returns the number of items the bloom filter was sized for (also known as capacity).
+    pub fn items_count(&self) -> u32 {
+        self.header.items_count
+    }
+
+    /// Returns the number of hash functions used to store the single string item.
+    pub fn number_of_hashes(&self) -> u32 {
+        self.header.number_of_hashes
+    }
+
+    /// Returns the number of items inserted into the bloom filter.
+    pub fn items_added(&self) -> u32 {
+        self.header.items_added
+    }
+
+    /// Returns true if the bloom filter hashes the items with a secret key.
+    pub fn is_keyed(&self) -> bool {
+        self.header.is_keyed()
+    }
+
+    /// Returns the salt mixed into the item hashes, if any.
+    pub fn salt(&self) -> Option<u64> {
+        self.header.salt
+    }
+
+    /// Returns the number of bits set to true in the bloom filter's bit array.
+    pub fn count_set_bits(&self) -> u64 {
+        self.payload
+            .iter()
+            .map(|byte| byte.count_ones() as u64)
+            .sum()
+    }
+
+    /// Returns the share of bits set to true in the bloom filter's bit array.
+    pub fn fill_ratio(&self) -> f64 {
+        self.count_set_bits() as f64 / self.number_of_bits() as f64
+    }
+
+    /// Estimates the number of distinct items stored in the bloom filter from its fill ratio.
+    pub fn estimated_items_count(&self) -> f64 {
+        BloomFilter::calc_estimated_items_count(
+            self.number_of_bits(),
+            self.number_of_hashes(),
+            self.fill_ratio(),
+        )
+    }
+
+    /// Calculates the false positive probability for the current state of the bloom filter.
+    pub fn current_false_positive_probability(&self) -> f64 {
+        BloomFilter::calc_false_positive_probability(self.number_of_hashes(), self.fill_ratio())
+    }
+
+    /// Copies the borrowed bloom filter into a new owned one.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_owned(&self) -> BloomFilter {
+        let mut bloom_filter: BloomFilter = BloomFilter::from_header(&self.header, self.payload);
+
+        bloom_filter.key = self.key;
+        bloom_filter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BloomFilterRef;
+    use crate::{BloomFilter, HashKey, LoadBloomFilterError};
+
+    #[test]
+    fn test_ref_item_present() {
+        let item: &str = "John Green";
+        let wrong_item: &str = "John White";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_salt(42)
+            .unwrap();
+
+        bloom_filter.insert(item);
+
+        // The serialized bloom filter embedded into a bigger message.
+        let mut message: Vec<u8> = b"prefix".to_vec();
+        message.extend_from_slice(&bloom_filter.to_bytes());
+        message.extend_from_slice(b"suffix");
+
+        let bloom_filter_ref: BloomFilterRef = BloomFilterRef::new(&message[6..]).unwrap();
+
+        assert!(bloom_filter_ref.is_probably_present(item));
+        assert!(!bloom_filter_ref.is_probably_present(wrong_item));
+        assert_eq!(&message[6 + bloom_filter_ref.len()..], b"suffix");
+        assert_eq!(bloom_filter_ref.salt(), Some(42));
+        assert_eq!(bloom_filter_ref.items_added(), 1);
+        assert_eq!(
+            bloom_filter_ref.count_set_bits(),
+            bloom_filter.count_set_bits()
+        );
+        assert_eq!(
+            bloom_filter_ref.estimated_items_count(),
+            bloom_filter.estimated_items_count()
+        );
+        assert_eq!(
+            bloom_filter_ref.to_owned().to_bytes(),
+            bloom_filter.to_bytes()
+        );
+    }
+
+    #[test]
+    fn test_ref_keyed() {
+        let item: &str = "John Green";
+        let key: HashKey = *b"0123456789abcdef";
+
+        let mut bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000)
+            .unwrap()
+            .with_key(key)
+            .unwrap();
+
+        bloom_filter.insert(item);
+
+        let bytes: Vec<u8> = bloom_filter.to_bytes();
+
+        assert!(matches!(
+            BloomFilterRef::new(&bytes),
+            Err(LoadBloomFilterError::MissingKey)
+        ));
+
+        let bloom_filter_ref: BloomFilterRef = BloomFilterRef::new_with_key(&bytes, key).unwrap();
+
+        assert!(bloom_filter_ref.is_keyed());
+        assert!(bloom_filter_ref.is_probably_present(item));
+        assert!(bloom_filter_ref.to_owned().is_probably_present(item));
+    }
+
+    #[test]
+    fn test_ref_invalid_bytes() {
+        let bytes: Vec<u8> = BloomFilter::new(Some(0.01), 1000).unwrap().to_bytes();
+
+        assert!(matches!(
+            BloomFilterRef::new(&bytes[..bytes.len() - 1]),
+            Err(LoadBloomFilterError::Format(_))
+        ));
+        assert!(matches!(
+            BloomFilterRef::new(b"{}"),
+            Err(LoadBloomFilterError::Format(_))
+        ));
+    }
+}
@@ -0,0 +1,422 @@
+//! Crash-safe incremental persistence of the bloom filter with a write-ahead log.
+//!
+//! Every item accepted by the bloom filter in memory is appended to the `<snapshot>.wal` log file next to
+//! the snapshot written by bloom_filter.save(...), the items rejected by the full bloom filter are not logged. Opening the persistent
+//! bloom filter loads the snapshot and replays the log over it. Compaction saves a new snapshot and empties the log.
+//!
+//! The log is a sequence of records, all the integers are little endian:
+//!
+//! | size | field                     |
+//! |------|---------------------------|
+//! | 4    | item length               |
+//! | 4    | CRC32C of the item        |
+//! | n    | item bytes (UTF-8)        |
+//!
+//! A record torn by a crash in the middle of the append fails the checksum and is cut off with the rest of the log.
+//! The records are never empty, so a zero-filled tail left by the file system is cut off the same way.
+
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{BloomFilter, FileFormat, LoadBloomFilterError, LoadOptions, SaveBloomFilterError};
+
+const RECORD_HEADER_SIZE: usize = 8;
+
+/// A bloom filter persisting every inserted item to a write-ahead log,
+/// so a crash loses nothing inserted since the last full save.
+///
+/// ```rust
+/// use std::fs;
+/// use bfilters::{BloomFilter, FileFormat, LoadOptions, PersistentBloomFilter};
+///
+/// let bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+/// let mut persistent_bloom_filter: PersistentBloomFilter =
+///     PersistentBloomFilter::create("./bfilter_wal_doc.bin", bloom_filter, FileFormat::Binary).unwrap();
+///
+/// persistent_bloom_filter.insert("Vinegar").unwrap();
+///
+/// // The process crashes here, the item is in the log only.
+/// drop(persistent_bloom_filter);
+///
+/// let mut persistent_bloom_filter: PersistentBloomFilter =
+///     PersistentBloomFilter::open("./bfilter_wal_doc.bin", LoadOptions::new()).unwrap();
+///
+/// assert!(persistent_bloom_filter.is_probably_present("Vinegar"));
+///
+/// fs::remove_file("./bfilter_wal_doc.bin").unwrap();
+/// fs::remove_file("./bfilter_wal_doc.bin.wal").unwrap();
+/// ```
+pub struct PersistentBloomFilter {
+    bloom_filter: BloomFilter,
+    path: PathBuf,
+    format: FileFormat,
+    log: File,
+    log_len: u64,
+    compaction_threshold: Option<u64>,
+}
+
+/// Returns the path of the log file of the given snapshot.
+fn log_path(path: &Path) -> PathBuf {
+    let mut log_path: OsString = path.as_os_str().to_owned();
+
+    log_path.push(".wal");
+    PathBuf::from(log_path)
+}
+
+/// Encodes the item into the log record.
+fn encode_record(item: &str) -> Vec<u8> {
+    let mut record: Vec<u8> = Vec::with_capacity(RECORD_HEADER_SIZE + item.len());
+
+    record.extend_from_slice(&(item.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32c::crc32c(item.as_bytes()).to_le_bytes());
+    record.extend_from_slice(item.as_bytes());
+    record
+}
+
+/// Saves the bloom filter as the snapshot in the given format.
+fn save_snapshot(
+    bloom_filter: &BloomFilter,
+    path: &Path,
+    format: FileFormat,
+) -> Result<(), SaveBloomFilterError> {
+    match format {
+        FileFormat::Json => bloom_filter.save(path),
+        FileFormat::Binary => bloom_filter.save_binary(path),
+    }
+}
+
+/// Decodes the log records and returns the items together with the length of the valid part of the log.
+fn decode_records(log: &[u8]) -> (Vec<&str>, usize) {
+    let mut items: Vec<&str> = Vec::new();
+    let mut offset: usize = 0;
+
+    while log.len() - offset >= RECORD_HEADER_SIZE {
+        let len: usize = u32::from_le_bytes(log[offset..offset + 4].try_into().unwrap()) as usize;
+        let checksum: u32 = u32::from_le_bytes(log[offset + 4..offset + 8].try_into().unwrap());
+        let start: usize = offset + RECORD_HEADER_SIZE;
+
+        let item: &[u8] = match log.get(start..start + len) {
+            Some(item) if len > 0 && crc32c::crc32c(item) == checksum => item,
+            _ => break,
+        };
+
+        match std::str::from_utf8(item) {
+            Ok(item) => items.push(item),
+            Err(_) => break,
+        }
+
+        offset = start + len;
+    }
+
+    (items, offset)
+}
+
+impl PersistentBloomFilter {
+    /// Saves the given bloom filter as the snapshot in the given format and starts an empty log next to it.
+    /// The existing snapshot and log at the path are replaced, the log is emptied only once the snapshot is saved.
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        bloom_filter: BloomFilter,
+        format: FileFormat,
+    ) -> Result<Self, SaveBloomFilterError> {
+        let path: PathBuf = path.as_ref().to_owned();
+
+        save_snapshot(&bloom_filter, &path, format)?;
+
+        // The log is appended to only, so the writes after emptying it start at its beginning.
+        let log: File = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(log_path(&path))?;
+
+        log.set_len(0)?;
+
+        Ok(Self {
+            bloom_filter,
+            path,
+            format,
+            log,
+            log_len: 0,
+            compaction_threshold: None,
+        })
+    }
+
+    /// Loads the snapshot at the given path with the given options and replays the log over it.
+    /// A log torn by a crash is cut off after its last complete record.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        options: LoadOptions,
+    ) -> Result<Self, LoadBloomFilterError> {
+        let path: PathBuf = path.as_ref().to_owned();
+        let bytes: Vec<u8> = std::fs::read(&path)?;
+        let format: FileFormat = FileFormat::detect(&bytes);
+        let mut bloom_filter: BloomFilter = BloomFilter::from_bytes_with_options(&bytes, options)?;
+
+        let mut log: File = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(log_path(&path))?;
+        let mut log_bytes: Vec<u8> = Vec::new();
+
+        log.read_to_end(&mut log_bytes)?;
+
+        let (items, log_len) = decode_records(&log_bytes);
+
+        for item in items {
+            bloom_filter.insert(item);
+        }
+
+        if log_len < log_bytes.len() {
+            log.set_len(log_len as u64)?;
+        }
+
+        Ok(Self {
+            bloom_filter,
+            path,
+            format,
+            log,
+            log_len: log_len as u64,
+            compaction_threshold: None,
+        })
+    }
+
+    /// Makes the bloom filter compact itself once the log grows over the given number of bytes.
+    pub fn with_compaction_threshold(mut self, log_len: u64) -> Self {
+        self.compaction_threshold = Some(log_len);
+        self
+    }
+
+    /// Inserts the item into the bloom filter and appends it to the log if it was inserted.
+    /// Returning false if the bloom filter is full, such an item is not logged.
+    /// The record survives the crash of the process, bloom_filter.sync() makes it survive the power loss.
+    /// Fails for the empty item, which the log can not tell from the zero-filled tail. If the log
+    /// could not be written, the item stays in memory only and the partially written record is cut off.
+    pub fn insert(&mut self, item: &str) -> Result<bool, SaveBloomFilterError> {
+        if item.is_empty() {
+            return Err(SaveBloomFilterError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The empty item can not be logged.",
+            )));
+        }
+
+        if !self.bloom_filter.insert(item) {
+            return Ok(false);
+        }
+
+        let record: Vec<u8> = encode_record(item);
+
+        if let Err(err) = self.log.write_all(&record) {
+            // The later records would be appended after the torn one, so replaying would drop them.
+            self.log.set_len(self.log_len)?;
+
+            return Err(err.into());
+        }
+
+        self.log_len += record.len() as u64;
+
+        match self.compaction_threshold {
+            Some(threshold) if self.log_len >= threshold => self.compact().map(|_| true),
+            _ => Ok(true),
+        }
+    }
+
+    /// Given the negative or false positive answer about the item presence in the bloom filter.
+    pub fn is_probably_present(&mut self, item: &str) -> bool {
+        self.bloom_filter.is_probably_present(item)
+    }
+
+    /// Flushes the log to the disk.
+    pub fn sync(&self) -> io::Result<()> {
+        self.log.sync_data()
+    }
+
+    /// Saves the bloom filter as the new snapshot and empties the log.
+    ///
+    /// A crash between the two steps leaves the log items in the snapshot already, replaying them
+    /// sets no new bits but counts them twice in bloom_filter.items_added().
+    pub fn compact(&mut self) -> Result<(), SaveBloomFilterError> {
+        save_snapshot(&self.bloom_filter, &self.path, self.format)?;
+        self.log.set_len(0)?;
+        self.log.sync_all()?;
+        self.log_len = 0;
+
+        Ok(())
+    }
+
+    /// Returns the number of bytes in the log since the last compaction.
+    pub fn log_len(&self) -> u64 {
+        self.log_len
+    }
+
+    /// Returns the bloom filter with all the logged items inserted.
+    pub fn bloom_filter(&self) -> &BloomFilter {
+        &self.bloom_filter
+    }
+
+    /// Compacts the log and returns the bloom filter.
+    pub fn into_inner(mut self) -> Result<BloomFilter, SaveBloomFilterError> {
+        self.compact()?;
+
+        Ok(self.bloom_filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::Path;
+
+    use super::{decode_records, encode_record, log_path, PersistentBloomFilter};
+    use crate::{BloomFilter, FileFormat, LoadOptions};
+
+    #[test]
+    fn test_wal_replay_torn_record() {
+        let tmp_save_path: &Path = Path::new("./bfilter_wal.json");
+        let bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        let mut persistent_bloom_filter: PersistentBloomFilter =
+            PersistentBloomFilter::create(tmp_save_path, bloom_filter, FileFormat::Json).unwrap();
+
+        persistent_bloom_filter.insert("John Green").unwrap();
+        drop(persistent_bloom_filter);
+
+        // A crash in the middle of the second append.
+        let mut log = OpenOptions::new()
+            .append(true)
+            .open(log_path(tmp_save_path))
+            .unwrap();
+
+        log.write_all(&encode_record("John White")[..12]).unwrap();
+        drop(log);
+
+        let mut persistent_bloom_filter: PersistentBloomFilter =
+            PersistentBloomFilter::open(tmp_save_path, LoadOptions::new()).unwrap();
+        let log_len: u64 = fs::metadata(log_path(tmp_save_path)).unwrap().len();
+
+        assert!(persistent_bloom_filter.is_probably_present("John Green"));
+        assert!(!persistent_bloom_filter.is_probably_present("John White"));
+        assert_eq!(persistent_bloom_filter.bloom_filter().items_added(), 1);
+        assert_eq!(log_len, encode_record("John Green").len() as u64);
+
+        persistent_bloom_filter.insert("John White").unwrap();
+        drop(persistent_bloom_filter);
+
+        let mut persistent_bloom_filter: PersistentBloomFilter =
+            PersistentBloomFilter::open(tmp_save_path, LoadOptions::new()).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+        fs::remove_file(log_path(tmp_save_path)).unwrap();
+
+        assert!(persistent_bloom_filter.is_probably_present("John White"));
+        assert_eq!(persistent_bloom_filter.bloom_filter().items_added(), 2);
+    }
+
+    #[test]
+    fn test_wal_compaction_threshold() {
+        let tmp_save_path: &Path = Path::new("./bfilter_wal.bin");
+        let bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        let mut persistent_bloom_filter: PersistentBloomFilter =
+            PersistentBloomFilter::create(tmp_save_path, bloom_filter, FileFormat::Binary)
+                .unwrap()
+                .with_compaction_threshold(30);
+
+        for item in ["John Green", "John White", "John Black"] {
+            persistent_bloom_filter.insert(item).unwrap();
+        }
+
+        // The log is compacted after the second record.
+        assert_eq!(
+            persistent_bloom_filter.log_len(),
+            encode_record("John Black").len() as u64
+        );
+
+        let mut snapshot: BloomFilter = BloomFilter::from_file(tmp_save_path).unwrap();
+
+        assert!(snapshot.is_probably_present("John White"));
+        assert!(!snapshot.is_probably_present("John Black"));
+
+        let mut bloom_filter: BloomFilter = persistent_bloom_filter.into_inner().unwrap();
+        let mut snapshot: BloomFilter = BloomFilter::from_file(tmp_save_path).unwrap();
+        let log_len: u64 = fs::metadata(log_path(tmp_save_path)).unwrap().len();
+
+        fs::remove_file(tmp_save_path).unwrap();
+        fs::remove_file(log_path(tmp_save_path)).unwrap();
+
+        assert!(bloom_filter.is_probably_present("John Black"));
+        assert!(snapshot.is_probably_present("John Black"));
+        assert_eq!(log_len, 0);
+    }
+
+    #[test]
+    fn test_wal_reopen_after_compaction() {
+        let tmp_save_path: &Path = Path::new("./bfilter_wal_reopen.bin");
+        let bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1000).unwrap();
+
+        let mut persistent_bloom_filter: PersistentBloomFilter =
+            PersistentBloomFilter::create(tmp_save_path, bloom_filter, FileFormat::Binary)
+                .unwrap()
+                .with_compaction_threshold(30);
+
+        for item in ["John Green", "John White", "John Black"] {
+            persistent_bloom_filter.insert(item).unwrap();
+        }
+
+        assert!(persistent_bloom_filter.insert("").is_err());
+
+        let log_len: u64 = fs::metadata(log_path(tmp_save_path)).unwrap().len();
+
+        assert_eq!(log_len, persistent_bloom_filter.log_len());
+        drop(persistent_bloom_filter);
+
+        let mut persistent_bloom_filter: PersistentBloomFilter =
+            PersistentBloomFilter::open(tmp_save_path, LoadOptions::new()).unwrap();
+
+        fs::remove_file(tmp_save_path).unwrap();
+        fs::remove_file(log_path(tmp_save_path)).unwrap();
+
+        for item in ["John Green", "John White", "John Black"] {
+            assert!(persistent_bloom_filter.is_probably_present(item));
+        }
+
+        assert!(!persistent_bloom_filter.is_probably_present(""));
+        assert_eq!(persistent_bloom_filter.bloom_filter().items_added(), 3);
+    }
+
+    #[test]
+    fn test_wal_insert_into_full() {
+        let tmp_save_path: &Path = Path::new("./bfilter_wal_full.bin");
+        let bloom_filter: BloomFilter = BloomFilter::new(Some(0.01), 1).unwrap();
+
+        let mut persistent_bloom_filter: PersistentBloomFilter =
+            PersistentBloomFilter::create(tmp_save_path, bloom_filter, FileFormat::Binary).unwrap();
+
+        let inserted: bool = persistent_bloom_filter.insert("John Green").unwrap();
+        let rejected: bool = !persistent_bloom_filter.insert("John White").unwrap();
+        let log_len: u64 = fs::metadata(log_path(tmp_save_path)).unwrap().len();
+
+        fs::remove_file(tmp_save_path).unwrap();
+        fs::remove_file(log_path(tmp_save_path)).unwrap();
+
+        assert!(inserted);
+        assert!(rejected);
+        assert_eq!(persistent_bloom_filter.log_len(), log_len);
+        assert_eq!(log_len, encode_record("John Green").len() as u64);
+    }
+
+    #[test]
+    fn test_wal_zero_filled_tail() {
+        let mut log: Vec<u8> = encode_record("John Green");
+
+        log.extend_from_slice(&[0; 16]);
+
+        let (items, log_len) = decode_records(&log);
+
+        assert_eq!(items, vec!["John Green"]);
+        assert_eq!(log_len, encode_record("John Green").len());
+    }
+}